@@ -0,0 +1,56 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+use wordora_ai::{
+    markov::{GenerateOptions, MarkovChain},
+    tokenizer::TokenizerConfig,
+};
+
+const CORPUS: &str = include_str!("../src/default_corpus.txt");
+
+fn corpus_lines() -> Vec<&'static str> {
+    CORPUS
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect()
+}
+
+fn learn(c: &mut Criterion) {
+    let lines = corpus_lines();
+    let tokenizer = TokenizerConfig::default();
+    c.bench_function("learn default corpus", |b| {
+        b.iter(|| {
+            let mut chain = MarkovChain::new(2, tokenizer.clone());
+            for line in &lines {
+                chain.learn(line);
+            }
+            black_box(chain)
+        })
+    });
+}
+
+fn generate(c: &mut Criterion) {
+    let lines = corpus_lines();
+    let tokenizer = TokenizerConfig::default();
+    let mut chain = MarkovChain::new(2, tokenizer.clone());
+    for line in &lines {
+        chain.learn(line);
+    }
+    chain.finalize();
+    let opts = GenerateOptions::default();
+    let seeds: Vec<String> = lines
+        .iter()
+        .filter_map(|line| tokenizer.tokenize(line).into_iter().next())
+        .collect();
+
+    // **📌 毎回ちがう種から生成する**
+    let mut i = 0;
+    c.bench_function("generate sentence", |b| {
+        b.iter(|| {
+            i += 1;
+            black_box(chain.generate(&seeds[i % seeds.len()], &opts))
+        })
+    });
+}
+
+criterion_group!(benches, learn, generate);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+// **📌 ffi フィーチャーでは C のヘッダーを OUT_DIR に生成**
+// WORDORA_UPDATE_HEADER を付けてビルドしたときだけ include/wordora.h を書き換える
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        println!("cargo:rerun-if-env-changed=WORDORA_UPDATE_HEADER");
+        let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let out = std::env::var("OUT_DIR").unwrap();
+        let bindings = cbindgen::generate(&dir).expect("Failed to generate C header");
+        bindings.write_to_file(format!("{}/wordora.h", out));
+        if std::env::var_os("WORDORA_UPDATE_HEADER").is_some() {
+            bindings.write_to_file(format!("{}/include/wordora.h", dir));
+        }
+    }
+}
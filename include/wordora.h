@@ -0,0 +1,66 @@
+#ifndef WORDORA_H
+#define WORDORA_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Opaque handle created by `wordora_new` and released by `wordora_free`
+ */
+typedef struct WordoraChain WordoraChain;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * New empty chain of the given order, null if it could not be created
+ */
+WordoraChain *wordora_new(uintptr_t order);
+
+/**
+ * Learn a UTF-8 sentence; 0 on success, -1 for a null pointer, invalid UTF-8
+ * or a panic inside the library
+ *
+ * # Safety
+ * `chain` must come from `wordora_new` and `text` must be a NUL-terminated
+ * string, or either may be null
+ */
+int wordora_learn(WordoraChain *chain, const char *text);
+
+/**
+ * Generate up to `len` tokens after `seed`, joined for display; null for a
+ * null pointer, invalid UTF-8 or a panic. The string is owned by the caller
+ * and released with `wordora_string_free`
+ *
+ * # Safety
+ * `chain` must come from `wordora_new` and `seed` must be a NUL-terminated
+ * string, or either may be null
+ */
+char *wordora_generate(const WordoraChain *chain, const char *seed, uintptr_t len);
+
+/**
+ * Release a string returned by `wordora_generate`
+ *
+ * # Safety
+ * `text` must come from `wordora_generate` and not be freed twice, or be null
+ */
+void wordora_string_free(char *text);
+
+/**
+ * Release a chain created by `wordora_new`
+ *
+ * # Safety
+ * `chain` must come from `wordora_new` and not be freed twice, or be null
+ */
+void wordora_free(WordoraChain *chain);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* WORDORA_H */
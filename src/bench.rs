@@ -0,0 +1,87 @@
+use crate::{
+    markov::{GenerateOptions, MarkovChain},
+    tokenizer::TokenizerConfig,
+};
+use std::time::{Duration, Instant};
+
+// **📌 BenchReport構造体：学習と生成の速さの測定結果**
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub lines: usize,
+    /// Tokens learned, as split by the tokenizer
+    pub tokens: usize,
+    pub learn_time: Duration,
+    pub sentences: usize,
+    /// Tokens in the generated sentences
+    pub generated_tokens: usize,
+    pub generate_time: Duration,
+    /// Distinct words in the trained chain
+    pub vocabulary: usize,
+    /// Peak resident memory of the process in bytes, where the platform
+    /// reports it
+    pub peak_memory: Option<u64>,
+}
+
+impl BenchReport {
+    pub fn tokens_per_second(&self) -> f64 {
+        self.tokens as f64 / self.learn_time.as_secs_f64().max(f64::EPSILON)
+    }
+
+    pub fn sentences_per_second(&self) -> f64 {
+        self.sentences as f64 / self.generate_time.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Train a fresh chain on `lines`, then generate `sentences` sentences from
+/// it, each seeded by the first token of a corpus line in turn
+pub fn bench(
+    lines: &[String],
+    order: usize,
+    tokenizer: &TokenizerConfig,
+    sentences: usize,
+    opts: &GenerateOptions,
+) -> BenchReport {
+    // **📌 トークン数は時間を測る前に数えておく**
+    let tokenized: Vec<Vec<String>> = lines.iter().map(|line| tokenizer.tokenize(line)).collect();
+    let tokens = tokenized.iter().map(Vec::len).sum();
+    let seeds: Vec<&str> = tokenized
+        .iter()
+        .filter_map(|tokens| tokens.first().map(String::as_str))
+        .collect();
+
+    let mut chain = MarkovChain::new(order, tokenizer.clone());
+    let started = Instant::now();
+    for line in lines {
+        chain.learn(line);
+    }
+    chain.finalize();
+    let learn_time = started.elapsed();
+
+    let mut generated_tokens = 0;
+    let started = Instant::now();
+    for i in 0..sentences {
+        let seed = seeds.get(i % seeds.len().max(1)).copied();
+        let reply = chain.generate(seed.unwrap_or(&opts.fallback_word), opts);
+        generated_tokens += reply.split_whitespace().count();
+    }
+    let generate_time = started.elapsed();
+
+    BenchReport {
+        lines: lines.len(),
+        tokens,
+        learn_time,
+        sentences,
+        generated_tokens,
+        generate_time,
+        vocabulary: chain.words().count(),
+        peak_memory: peak_memory(),
+    }
+}
+
+/// Peak resident memory of the process in bytes, read from `/proc` on Linux
+pub fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
@@ -0,0 +1,135 @@
+use crate::postprocess::repair_spacing;
+use regex::Regex;
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use unicode_normalization::UnicodeNormalization;
+
+// **📌 BlocklistConfig構造体：返事に出してはいけない言葉の設定**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BlocklistConfig {
+    /// File of banned words, one per line; lines written as `/.../` are
+    /// regexes and lines starting with `#` are comments
+    pub path: Option<PathBuf>,
+    /// Times a reply containing a banned word is generated again before
+    /// nothing is sent instead
+    pub retries: usize,
+}
+
+impl Default for BlocklistConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            retries: 5,
+        }
+    }
+}
+
+impl BlocklistConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.retries > 100 {
+            return Err("retries must be at most 100".to_string());
+        }
+        Ok(())
+    }
+}
+
+// **📌 Blocklist構造体：生成から外す禁止語**
+#[derive(Debug, Clone, Default)]
+pub struct Blocklist {
+    /// Banned words, NFKC-normalized and lowercased
+    words: HashSet<String>,
+    /// Characters in the longest banned word, bounding the token runs checked
+    longest: usize,
+    /// Case-insensitive patterns matched against tokens and whole replies
+    patterns: Vec<Regex>,
+}
+
+impl Blocklist {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })
+    }
+
+    /// Read a blocklist in the format of [`BlocklistConfig::path`]
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut blocklist = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.strip_prefix('/').and_then(|l| l.strip_suffix('/')) {
+                Some(pattern) if !pattern.is_empty() => {
+                    let regex = Regex::new(&format!("(?i){}", pattern))
+                        .map_err(|e| format!("line {}: {}", i + 1, e))?;
+                    blocklist.patterns.push(regex);
+                }
+                _ => {
+                    let word = normalize(line);
+                    blocklist.longest = blocklist.longest.max(word.chars().count());
+                    blocklist.words.insert(word);
+                }
+            }
+        }
+        Ok(blocklist)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty() && self.patterns.is_empty()
+    }
+
+    /// Whether the token must never be generated
+    pub fn blocks(&self, token: &str) -> bool {
+        !self.is_empty()
+            && (self.words.contains(&normalize(token))
+                || self.patterns.iter().any(|re| re.is_match(token)))
+    }
+
+    /// Whether a reply of space-separated tokens contains a banned word, also
+    /// when the tokenizer split it over several tokens, or matches a pattern
+    pub fn matches(&self, reply: &str) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        let tokens: Vec<String> = reply.split_whitespace().map(normalize).collect();
+        for start in 0..tokens.len() {
+            let mut run = String::new();
+            for token in &tokens[start..] {
+                run.push_str(token);
+                if run.chars().count() > self.longest {
+                    break;
+                }
+                if self.words.contains(&run) {
+                    return true;
+                }
+            }
+        }
+        let text = repair_spacing(&reply.split_whitespace().collect::<Vec<_>>());
+        self.patterns.iter().any(|re| re.is_match(&text))
+    }
+
+    /// Call `generate` until a reply has no banned word, at most `retries`
+    /// more times; empty when every reply had one
+    pub fn regenerate<F: FnMut() -> String>(&self, retries: usize, mut generate: F) -> String {
+        for _ in 0..=retries {
+            let reply = generate();
+            if !self.matches(&reply) {
+                return reply;
+            }
+        }
+        String::new()
+    }
+}
+
+fn normalize(word: &str) -> String {
+    word.nfkc().collect::<String>().to_lowercase()
+}
@@ -0,0 +1,150 @@
+use crate::server;
+use std::{collections::HashMap, io, time::Duration};
+use tokio::time::Interval;
+use wordora_ai::{
+    config::Config,
+    decay::Decay,
+    guard::LearnGuard,
+    layered::LayeredModel,
+    markov::MarkovChain,
+    recent::RecentChain,
+    trigger::{Incoming, Triggers},
+};
+
+/// Name of the overlay holding what was learned over the bridge
+const BRIDGE: &str = "bridge";
+
+// **📌 Bridge構造体：IRCやMatrixから届いた発言を学習して答える**
+pub struct Bridge {
+    config: Config,
+    /// Shared model with the bridge's overlay on top
+    model: LayeredModel,
+    /// Messages learned since the overlay was last saved
+    unsaved: usize,
+    decay: Decay,
+    guard: LearnGuard,
+    /// Latest messages of each channel or room, when `[recent]` is enabled
+    recent: HashMap<String, RecentChain>,
+    triggers: Triggers,
+}
+
+impl Bridge {
+    /// Stack the saved bridge overlay, if any, on the model
+    pub fn new(config: &Config, mut model: LayeredModel) -> io::Result<Self> {
+        let chain = config
+            .bridge
+            .model
+            .as_ref()
+            .filter(|path| path.exists())
+            .map(|path| MarkovChain::load(path))
+            .transpose()?;
+        model.push_overlay(BRIDGE, chain);
+        Ok(Self {
+            config: config.clone(),
+            model,
+            unsaved: 0,
+            decay: Decay::new(&config.decay),
+            guard: LearnGuard::new(&config.guard),
+            recent: HashMap::new(),
+            triggers: Triggers::new(&config.bridge.trigger)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        })
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Take in a message `user` sent to `channel`, `text` being its body
+    /// without the bot's name, and return the reply when the trigger rules
+    /// call for one
+    pub fn hear(
+        &mut self,
+        channel: &str,
+        user: &str,
+        incoming: &Incoming,
+        text: &str,
+    ) -> Option<String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+        if self.config.bridge.learn && !self.model.is_read_only() {
+            match self.guard.check(user, text, self.model.base().tokenizer()) {
+                Ok(()) => self.learn(text),
+                Err(reason) => eprintln!("🛡️ not learning from {}: {}", user, reason),
+            }
+        }
+        if self.config.recent.enabled() {
+            let base = self.model.base();
+            self.recent
+                .entry(channel.to_string())
+                .or_insert_with(|| {
+                    RecentChain::new(&self.config.recent, base.order(), base.tokenizer().clone())
+                })
+                .push(text);
+        }
+        self.triggers.check(incoming)?;
+        let reply = server::reply(&self.config, &self.model, self.recent.get(channel), text);
+        (!reply.is_empty()).then_some(reply)
+    }
+
+    fn learn(&mut self, text: &str) {
+        let chain = self.model.overlay_mut(BRIDGE).expect("bridge overlay");
+        chain.learn(text);
+        self.decay.tick(chain);
+        if let Some(max_vocab) = self.config.model.max_vocab {
+            chain.evict(max_vocab);
+        }
+        self.unsaved += 1;
+        if self.unsaved >= self.config.bridge.save_every {
+            self.save();
+        }
+    }
+
+    /// Save the overlay if it has a path and unsaved messages
+    pub fn save(&mut self) {
+        if self.unsaved == 0 {
+            return;
+        }
+        if let (Some(path), Some(chain)) = (&self.config.bridge.model, self.model.overlay(BRIDGE)) {
+            if let Some(dir) = path.parent() {
+                let _ = std::fs::create_dir_all(dir);
+            }
+            match chain.save(path) {
+                Ok(()) => self.unsaved = 0,
+                Err(e) => eprintln!("⚠️ Failed to save bridge model: {}", e),
+            }
+        }
+    }
+}
+
+/// `text` without a leading `name:` or `name,` addressing the bot, ignoring
+/// case; `None` when it does not start that way
+pub fn addressed<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let head = text.get(..name.len())?;
+    if !head.eq_ignore_ascii_case(name) {
+        return None;
+    }
+    let rest = text[name.len()..].strip_prefix([':', ','])?;
+    Some(rest.trim_start())
+}
+
+/// Timer for `model.autosave_seconds`, if set
+pub fn autosave_timer(config: &Config) -> Option<Interval> {
+    config
+        .model
+        .autosave_seconds
+        .map(|seconds| tokio::time::interval(Duration::from_secs_f64(seconds)))
+}
+
+/// Next tick of an optional timer; pending forever without one, so a
+/// `select!` branch on it never fires
+pub async fn tick(timer: &mut Option<Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,1389 @@
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, history::DefaultHistory,
+    Context, Editor, ExternalPrinter, Helper, Hinter, Validator,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex, RwLock, RwLockReadGuard},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+};
+use wordora_ai::{
+    config::Config,
+    conversation::{Conversation, Speaker},
+    corpus::CorpusManifest,
+    decay::Decay,
+    encoding::TextEncoding,
+    events::{Event, EventBus},
+    fallback::FallbackResponder,
+    feedback::Rating,
+    kana::OutputScript,
+    layered::LayeredModel,
+    markov::{blend_salience, generate_blend, GenerateOptions, MarkovChain, Strategy, TraceStep},
+    persona::Personas,
+    postprocess::{apply_filters, repair_spacing},
+    rerank::best_reply,
+    transcript::TranscriptEntry,
+    watch::FileWatcher,
+};
+
+/// Name of the overlay holding what was learned during this session
+const SESSION: &str = "session";
+
+/// Time between checks for models the `model.watch` thread reloaded
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Alternatives listed per step by /debug
+const EXPLAIN_CANDIDATES: usize = 5;
+
+/// Colors of the user and bot prompts in the terminal chat
+const USER_COLOR: &str = "\x1b[1;36m";
+const BOT_COLOR: &str = "\x1b[1;32m";
+const RESET: &str = "\x1b[0m";
+
+/// Slash commands offered by tab completion
+const COMMANDS: &[&str] = &[
+    "/why", "/learn", "/teach", "/commit", "/discard", "/persona", "/topic", "/good", "/bad",
+    "/debug", "/trainer",
+];
+
+/// Which topic replies lean towards, set with /topic
+#[derive(Debug, Clone, PartialEq)]
+enum TopicChoice {
+    /// The topic each message is about, if any
+    Detect,
+    Off,
+    Fixed(String),
+}
+
+// **📌 Chat構造体：ターミナルでの会話の状態を管理**
+pub struct Chat<'a> {
+    config: &'a Config,
+    /// Base model and configured overlays, topped by the session overlay which is
+    /// dropped on exit unless committed; shared with the background commit task
+    model: Arc<RwLock<LayeredModel>>,
+    blend: Vec<(MarkovChain, f64)>,
+    personas: Personas,
+    /// Personas answering instead of the model, with their weights; empty for the model
+    persona: Vec<(usize, f64)>,
+    topic: TopicChoice,
+    /// Topic the last reply leaned towards
+    last_topic: Option<String>,
+    conversation: Conversation,
+    opts: GenerateOptions,
+    /// Integrations subscribe here to follow the conversation
+    events: Arc<EventBus>,
+    seed: String,
+    /// Tokens of the last reply the chain generated, until it is rated
+    last_reply: Vec<String>,
+    /// Record how each reply was generated, shown with /debug on
+    debug: bool,
+    /// List the words of each message the model has never seen, see /trainer
+    trainer: bool,
+    /// Tokens of the last message no layer knew, in trainer mode
+    unknown: Vec<String>,
+    /// Color the prompts of the terminal chat
+    color: bool,
+    /// Encoding stdout is written in
+    output: TextEncoding,
+    /// Decisions taken while generating the last reply, with `debug`
+    trace: Vec<TraceStep>,
+    /// Whether the last reply started from the fallback word
+    fallback: bool,
+    /// Answers instead of the chain when no seed is known
+    responder: Option<Box<dyn FallbackResponder>>,
+    /// Fades what the session learned earlier as new messages come in
+    decay: Decay,
+    /// Models reloaded or retrained in the background, with `model.watch`
+    reloads: Option<mpsc::Receiver<Reload>>,
+}
+
+/// One line of input in `--json` mode
+#[derive(Deserialize)]
+struct JsonMessage {
+    message: String,
+}
+
+/// Answer to an ordinary message in `--json` mode
+#[derive(Serialize)]
+struct JsonReply<'a> {
+    reply: String,
+    seed: &'a str,
+    tokens: Vec<&'a str>,
+    /// Decisions behind the reply, with /debug on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace: Option<&'a [TraceStep]>,
+    /// Tokens of the message the model did not know, with /trainer on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unknown: Option<&'a [String]>,
+}
+
+/// Output of a slash command, or why a line was not understood, in `--json` mode
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum JsonNotice {
+    Lines(Vec<String>),
+    Error(String),
+}
+
+// **📌 Reload：裏で読み込み直したモデル**
+enum Reload {
+    /// The base model file changed
+    Base {
+        path: PathBuf,
+        chain: MarkovChain,
+    },
+    /// An overlay file changed
+    Overlay {
+        path: PathBuf,
+        chain: MarkovChain,
+    },
+    /// The base model was retrained from the corpus manifest at `path`
+    Retrained {
+        path: PathBuf,
+        chain: MarkovChain,
+    },
+    Failed {
+        path: PathBuf,
+        error: String,
+    },
+}
+
+impl<'a> Chat<'a> {
+    pub fn new(config: &'a Config, mut model: LayeredModel) -> Self {
+        // **📌 ブレンド用のモデルを読み込み**
+        let blend = config
+            .blend
+            .iter()
+            .map(|b| {
+                (
+                    MarkovChain::load(&b.path).expect("Failed to load blend model"),
+                    b.weight,
+                )
+            })
+            .collect();
+        let personas = Personas::load(&config.persona).expect("Failed to load persona");
+        model.push_overlay(SESSION, None);
+
+        let mut events = EventBus::new();
+        if config.chat.log_events {
+            events.subscribe(|event| eprintln!("📣 {:?}", event));
+        }
+        if let Some(path) = &config.chat.transcript {
+            let log = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .expect("Failed to open transcript");
+            events.subscribe(transcript_writer(log));
+        }
+
+        Self {
+            config,
+            model: Arc::new(RwLock::new(model)),
+            blend,
+            personas,
+            persona: Vec::new(),
+            topic: if config.topic.detect {
+                TopicChoice::Detect
+            } else {
+                TopicChoice::Off
+            },
+            last_topic: None,
+            conversation: Conversation::new(config.history.turns, config.history.distinct_replies),
+            opts: config.generation.clone(),
+            events: Arc::new(events),
+            seed: String::new(),
+            last_reply: Vec::new(),
+            debug: false,
+            trainer: config.chat.trainer,
+            unknown: Vec::new(),
+            color: use_color(config),
+            output: config.chat.encoding.for_output(io::stdout().is_terminal()),
+            trace: Vec::new(),
+            fallback: false,
+            responder: config.fallback.build(),
+            decay: Decay::new(&config.decay),
+            reloads: config.model.watch.then(|| watch(config)),
+        }
+    }
+
+    // **💬 ターミナルでチャット**
+    pub async fn run(&mut self) {
+        say(self.output, &format!("{}\n", self.config.prompt.banner));
+
+        // **📌 行の編集は別スレッド、裏の作業の知らせはプロンプトを崩さずに表示**
+        let mut input = LineReader::spawn(self.config, self.color, self.output);
+        let mut notices = self.spawn_autocommit();
+        let mut watching = tokio::time::interval(WATCH_INTERVAL);
+        loop {
+            tokio::select! {
+                line = input.lines.recv() => {
+                    let Some(input_line) = line else {
+                        break;
+                    };
+                    if input_line == self.config.prompt.exit {
+                        break;
+                    }
+                    self.answer(&input_line).await;
+                    input.next();
+                }
+                Some(notice) = recv(&mut notices) => input.print(notice),
+                _ = watching.tick(), if self.reloads.is_some() => {
+                    for line in self.reload() {
+                        input.print(line);
+                    }
+                }
+            }
+        }
+        input.close();
+
+        if self
+            .model()
+            .overlay(SESSION)
+            .is_some_and(|chain| !chain.is_empty())
+        {
+            println!("🗑️ discarded uncommitted session learning");
+        }
+    }
+
+    /// Show the answer to one line typed in the terminal chat
+    async fn answer(&mut self, input: &str) {
+        if let Some(lines) = self.command(input) {
+            for line in lines {
+                say(self.output, &format!("{}\n", line));
+            }
+            return;
+        }
+
+        say(
+            self.output,
+            &paint(&self.config.prompt.bot, BOT_COLOR, self.color),
+        );
+        let mut typer = Typer::new(
+            Duration::from_millis(self.config.chat.typing_delay_ms),
+            self.config.chat.type_by_char,
+            self.output,
+        );
+        // **📌 生成しながら1トークンずつ表示（待つ間はこのワーカーだけを止め、他のタスクは動かす）**
+        let response = tokio::task::block_in_place(|| {
+            let runtime = tokio::runtime::Handle::current();
+            self.message(input, |token| runtime.block_on(typer.push(token)))
+        });
+        let tokens: Vec<&str> = response.split_whitespace().collect();
+        // **📌 ストリーミングしなかった返答もタイピング風に表示**
+        if typer.is_empty() {
+            for token in &tokens {
+                typer.push(token).await;
+            }
+        }
+        say(self.output, "\n");
+        if let Some(note) = self.unknown_note() {
+            say(self.output, &format!("{}\n", note));
+        }
+        if self.debug {
+            for line in self.explain() {
+                say(self.output, &format!("{}\n", line));
+            }
+        }
+
+        self.speak(&tokens);
+    }
+
+    /// Commit and save the session every `chat.autocommit_seconds` on a
+    /// background task, which reports what it did on the returned channel.
+    /// Saving runs on the blocking pool so it never stalls the runtime
+    fn spawn_autocommit(&self) -> Option<UnboundedReceiver<String>> {
+        let seconds = self.config.chat.autocommit_seconds?;
+        let (send, notices) = unbounded_channel();
+        let model = self.model.clone();
+        let events = self.events.clone();
+        let config = Arc::new(self.config.clone());
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs_f64(seconds));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let learned = model
+                    .read()
+                    .unwrap()
+                    .overlay(SESSION)
+                    .is_some_and(|chain| !chain.is_empty());
+                if !learned {
+                    continue;
+                }
+                let (model, config, events) = (model.clone(), config.clone(), events.clone());
+                let notice = tokio::task::spawn_blocking(move || commit(&model, &config, &events))
+                    .await
+                    .unwrap_or_else(|e| format!("⚠️ Failed to commit: {}", e));
+                if send.send(notice).is_err() {
+                    return;
+                }
+            }
+        });
+        Some(notices)
+    }
+
+    // **🧾 JSON Lines でチャット（他のツールから使う用）**
+    /// Answer `{"message": ...}` lines from stdin with one JSON object per line
+    /// on stdout until stdin closes. Slash commands answer `{"lines": [...]}`
+    /// and unreadable lines `{"error": ...}`
+    pub async fn run_json(&mut self) -> io::Result<()> {
+        let mut stdin = BufReader::new(tokio::io::stdin());
+        let mut notices = self.spawn_autocommit();
+        // **📌 ほかの分岐が先に終わっても読みかけの行を失わないよう外に置く**
+        let mut bytes = Vec::new();
+        loop {
+            let line = tokio::select! {
+                read = stdin.read_until(b'\n', &mut bytes) => match read? {
+                    0 => break,
+                    _ => {
+                        let line = std::mem::take(&mut bytes);
+                        self.config.chat.encoding.decode(line.trim_ascii_end())
+                    }
+                },
+                Some(notice) = recv(&mut notices) => {
+                    eprintln!("{}", notice);
+                    continue;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let json = match serde_json::from_str::<JsonMessage>(&line) {
+                Err(e) => serde_json::to_string(&JsonNotice::Error(e.to_string())),
+                Ok(JsonMessage { message }) => {
+                    let message = message.trim();
+                    if message == self.config.prompt.exit {
+                        break;
+                    }
+                    let mut lines = self.reload();
+                    if let Some(command) = self.command(message) {
+                        lines.extend(command);
+                        serde_json::to_string(&JsonNotice::Lines(lines))
+                    } else {
+                        for line in lines {
+                            eprintln!("{}", line);
+                        }
+                        let response = self.message(message, |_| {});
+                        let tokens: Vec<&str> = response.split_whitespace().collect();
+                        serde_json::to_string(&JsonReply {
+                            reply: repair_spacing(&tokens),
+                            seed: &self.seed,
+                            tokens,
+                            trace: self.debug.then_some(self.trace.as_slice()),
+                            unknown: self.trainer.then_some(self.unknown.as_slice()),
+                        })
+                    }
+                }
+            };
+            let json = format!("{}\n", json.map_err(io::Error::other)?);
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(&self.output.encode(&json))?;
+            stdout.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Whether replies are explained, see /debug
+    pub fn debugging(&self) -> bool {
+        self.debug
+    }
+
+    /// Line listing the tokens of the last message the model did not know,
+    /// in trainer mode and when there were any
+    pub fn unknown_note(&self) -> Option<String> {
+        (self.trainer && !self.unknown.is_empty()).then(|| {
+            format!(
+                "❓ unknown: {} (/teach a sentence using them)",
+                self.unknown.join(", ")
+            )
+        })
+    }
+
+    /// Tokens of `input` no layer has seen, in order and without repeats
+    fn unknown_tokens(&self, input: &str) -> Vec<String> {
+        let model = self.model();
+        let mut unknown: Vec<String> = Vec::new();
+        for token in model.base().tokenizer().tokenize_input(input) {
+            if !model.knows(&token) && !unknown.contains(&token) {
+                unknown.push(token);
+            }
+        }
+        unknown
+    }
+
+    /// Tokens and transitions of `text` no layer has seen yet, in order and
+    /// without repeats
+    fn novelty(&self, text: &str) -> (Vec<String>, Vec<(String, String)>) {
+        let model = self.model();
+        let tokens = model.base().tokenizer().tokenize(text);
+        let mut new_tokens: Vec<String> = Vec::new();
+        for token in &tokens {
+            if !model.knows(token) && !new_tokens.contains(token) {
+                new_tokens.push(token.clone());
+            }
+        }
+        let mut new_transitions: Vec<(String, String)> = Vec::new();
+        for pair in tokens.windows(2) {
+            let transition = (pair[0].clone(), pair[1].clone());
+            if !model.has_transition(&pair[0], &pair[1]) && !new_transitions.contains(&transition) {
+                new_transitions.push(transition);
+            }
+        }
+        (new_tokens, new_transitions)
+    }
+
+    /// Describe how the last reply was generated: its seed, then each
+    /// sampled token with its most likely alternatives, fallbacks and stops
+    pub fn explain(&self) -> Vec<String> {
+        let mut out = vec![format!(
+            "🔍 seed {:?}{}",
+            self.seed,
+            if self.fallback {
+                " (unknown, fell back)"
+            } else {
+                ""
+            }
+        )];
+        if let Some(topic) = &self.last_topic {
+            out.push(format!("🏷️ topic {}", topic));
+        }
+        if self.trace.is_empty() {
+            out.push("  no trace: only sampled replies from the model are traced".to_string());
+        }
+        for step in &self.trace {
+            out.push(match step {
+                TraceStep::Sampled {
+                    context,
+                    candidates,
+                    chosen,
+                } => {
+                    let mut shown: Vec<String> = candidates
+                        .iter()
+                        .take(EXPLAIN_CANDIDATES)
+                        .map(|(word, p)| {
+                            let mark = if word == chosen { "*" } else { "" };
+                            format!("{}{} {:.0}%", mark, word, p * 100.0)
+                        })
+                        .collect();
+                    if candidates.len() > EXPLAIN_CANDIDATES {
+                        shown.push(format!("+{}", candidates.len() - EXPLAIN_CANDIDATES));
+                    }
+                    format!("  {} → {}: {}", context.join(" "), chosen, shown.join(", "))
+                }
+                TraceStep::Fallback { context, word } => {
+                    format!(
+                        "  ↩️ nothing follows {}, restarting from {}",
+                        context.join(" "),
+                        word
+                    )
+                }
+                TraceStep::Stopped { context, reason } => {
+                    format!("  ⏹️ stopped after {}: {}", context.join(" "), reason)
+                }
+            });
+        }
+        out
+    }
+
+    pub fn config(&self) -> &Config {
+        self.config
+    }
+
+    pub fn model(&self) -> RwLockReadGuard<'_, LayeredModel> {
+        self.model.read().unwrap()
+    }
+
+    /// Seed word the last reply was generated from
+    pub fn seed(&self) -> &str {
+        &self.seed
+    }
+
+    /// Swap in the models reloaded in the background since the last call and
+    /// return the lines to show about them
+    pub fn reload(&mut self) -> Vec<String> {
+        let Some(reloads) = &self.reloads else {
+            return Vec::new();
+        };
+        let reloads: Vec<Reload> = reloads.try_iter().collect();
+        let mut out = Vec::new();
+        for reload in reloads {
+            let (path, line) = match reload {
+                Reload::Base { path, chain } => {
+                    self.model.write().unwrap().set_base(chain);
+                    let line = format!("🔄 reloaded {}", path.display());
+                    (path, line)
+                }
+                Reload::Overlay { path, chain } => {
+                    self.model
+                        .write()
+                        .unwrap()
+                        .replace_overlay(&path.to_string_lossy(), chain);
+                    let line = format!("🔄 reloaded {}", path.display());
+                    (path, line)
+                }
+                Reload::Retrained { path, chain } => {
+                    self.model.write().unwrap().set_base(chain);
+                    let line = format!("🔄 retrained from {}", path.display());
+                    (path, line)
+                }
+                Reload::Failed { path, error } => {
+                    out.push(format!("⚠️ Failed to reload {}: {}", path.display(), error));
+                    continue;
+                }
+            };
+            self.events.publish(Event::ModelReloaded { path: &path });
+            out.push(line);
+        }
+        out
+    }
+
+    /// Learn from and answer an ordinary message. When the reply is streamed each
+    /// token is passed to `on_token` as it is generated.
+    pub fn message<F: FnMut(&str)>(&mut self, input: &str, mut on_token: F) -> String {
+        self.events.publish(Event::MessageReceived { text: input });
+        // **📌 学習する前に、知らなかった単語を調べておく**
+        self.unknown = if self.trainer {
+            self.unknown_tokens(input)
+        } else {
+            Vec::new()
+        };
+        if self.config.chat.learn {
+            self.learn(input);
+        }
+        // **📌 表示にかかった時間は生成時間に含めない**
+        let started = Instant::now();
+        let mut output = Duration::ZERO;
+        let response = self.reply(input, &mut |token: &str| {
+            let printing = Instant::now();
+            on_token(token);
+            output += printing.elapsed();
+        });
+        let tokens = self
+            .config
+            .output_script
+            .apply(&response.split_whitespace().collect::<Vec<_>>());
+        let response = apply_filters(&self.config.postprocess, tokens).join(" ");
+        self.events.publish(Event::ResponseGenerated {
+            input,
+            response: &response,
+            latency: started.elapsed().saturating_sub(output),
+            fallback: self.fallback,
+        });
+        response
+    }
+
+    // **🔊 読み上げ**
+    pub fn speak(&self, tokens: &[&str]) {
+        if self.config.tts.enabled {
+            if let Err(e) = self.config.tts.speak(tokens) {
+                eprintln!("⚠️ TTS failed: {}", e);
+            }
+        }
+    }
+
+    fn learn(&mut self, text: &str) {
+        let mut model = self.model.write().unwrap();
+        if model.is_read_only() {
+            return;
+        }
+        let session = model.overlay_mut(SESSION).expect("session overlay");
+        session.learn(text);
+        self.decay.tick(session);
+        if let Some(max_vocab) = self.config.model.max_vocab {
+            session.evict(max_vocab);
+        }
+        self.events.publish(Event::LearnApplied {
+            layer: SESSION,
+            text,
+        });
+    }
+
+    /// Handle a slash command and return the lines to show; `None` for ordinary messages
+    pub fn command(&mut self, input: &str) -> Option<Vec<String>> {
+        let (name, arg) = input.split_once(' ').unwrap_or((input, ""));
+        let arg = arg.trim();
+        let mut out = Vec::new();
+
+        match name {
+            // **🔍 /why <単語>：その単語の直前に来る単語を表示**
+            "/why" => {
+                let model = self.model();
+                let predecessors = model.base().predecessors(arg);
+                if predecessors.is_empty() {
+                    out.push(format!("❓ no tokens precede {}", arg));
+                }
+                for (prev, count) in predecessors.iter().take(10) {
+                    out.push(format!("  {} → {} ({})", prev, arg, count));
+                }
+            }
+            // **📝 /learn <文章>：このセッションだけで学習**
+            "/learn" | "/teach" | "/commit" | "/discard" | "/good" | "/bad"
+                if self.model().is_read_only() =>
+            {
+                out.push("🔒 read-only mode: the model cannot change".to_string());
+            }
+            "/learn" => {
+                self.learn(arg);
+                out.push("📝 learned for this session (/commit to keep)".to_string());
+            }
+            // **🎓 /teach <文章>：すぐに学習して、増えた単語と遷移を表示**
+            "/teach" if arg.is_empty() => out.push("⚠️ usage: /teach <sentence>".to_string()),
+            "/teach" => {
+                let (tokens, transitions) = self.novelty(arg);
+                self.learn(arg);
+                out.push("🎓 learned for this session (/commit to keep)".to_string());
+                out.push(if tokens.is_empty() {
+                    "  no new tokens".to_string()
+                } else {
+                    format!("  new tokens: {}", tokens.join(", "))
+                });
+                out.push(if transitions.is_empty() {
+                    "  no new transitions".to_string()
+                } else {
+                    let transitions: Vec<String> = transitions
+                        .iter()
+                        .map(|(from, to)| format!("{} → {}", from, to))
+                        .collect();
+                    format!("  new transitions: {}", transitions.join(", "))
+                });
+            }
+            // **💾 /commit：セッションの学習を下のレイヤーに反映**
+            "/commit" => out.push(commit(&self.model, self.config, &self.events)),
+            "/discard" => {
+                let mut model = self.model.write().unwrap();
+                model.overlay_mut(SESSION).expect("session overlay").clear();
+                out.push("🗑️ discarded session learning".to_string());
+            }
+            // **👍 /good, /bad：直前の返事の遷移を強める・弱める（/commit で保存）**
+            "/good" | "/bad" if self.last_reply.is_empty() => {
+                out.push("❓ no reply to rate yet".to_string());
+            }
+            "/good" | "/bad" => {
+                let rating = if name == "/good" {
+                    Rating::Good
+                } else {
+                    Rating::Bad
+                };
+                let tokens = std::mem::take(&mut self.last_reply);
+                let changed = self
+                    .model
+                    .write()
+                    .unwrap()
+                    .reinforce(SESSION, &tokens, self.config.feedback.factor(rating))
+                    .unwrap_or(0);
+                out.push(match rating {
+                    Rating::Good => {
+                        format!("👍 reinforced {} transitions (/commit to keep)", changed)
+                    }
+                    Rating::Bad => format!("👎 weakened {} transitions (/commit to keep)", changed),
+                });
+            }
+            // **🔍 /debug [on|off]：返事を作った過程を表示する**
+            "/debug" => {
+                match arg {
+                    "on" => self.debug = true,
+                    "off" => self.debug = false,
+                    "" => {}
+                    _ => {
+                        out.push("⚠️ usage: /debug [on|off]".to_string());
+                        return Some(out);
+                    }
+                }
+                out.push(format!(
+                    "🔍 debug {}",
+                    if self.debug { "on" } else { "off" }
+                ));
+            }
+            // **🎓 /trainer [on|off]：返事のあとに知らない単語を表示する**
+            "/trainer" => {
+                match arg {
+                    "on" => self.trainer = true,
+                    "off" => self.trainer = false,
+                    "" => {}
+                    _ => {
+                        out.push("⚠️ usage: /trainer [on|off]".to_string());
+                        return Some(out);
+                    }
+                }
+                out.push(format!(
+                    "🎓 trainer {}",
+                    if self.trainer { "on" } else { "off" }
+                ));
+            }
+            // **🎭 /persona [名前[:重み] ...|off]：答えるモデルを切り替える**
+            "/persona" if arg.is_empty() => {
+                if self.personas.is_empty() {
+                    out.push("🎭 no [[persona]] configured".to_string());
+                }
+                for name in self.personas.names() {
+                    out.push(format!("  {}", name));
+                }
+                if !self.persona.is_empty() {
+                    out.push(format!(
+                        "🎭 speaking as {}",
+                        self.personas.describe(&self.persona)
+                    ));
+                }
+            }
+            "/persona" if arg == "off" => {
+                self.persona.clear();
+                out.push("🎭 back to the main model".to_string());
+            }
+            // **🏷️ /topic [名前|auto|off]：返事を寄せる話題を選ぶ**
+            "/topic" if arg.is_empty() => {
+                let model = self.model();
+                let topics = model.topics();
+                if topics.is_empty() {
+                    out.push(
+                        "🏷️ the model has no topics; train with --topic or #topic lines"
+                            .to_string(),
+                    );
+                }
+                for topic in topics {
+                    out.push(format!("  {}", topic));
+                }
+                out.push(match &self.topic {
+                    TopicChoice::Detect => "🏷️ following the topic of each message".to_string(),
+                    TopicChoice::Off => "🏷️ topics off".to_string(),
+                    TopicChoice::Fixed(topic) => format!("🏷️ always leaning towards {}", topic),
+                });
+            }
+            "/topic" if arg == "auto" => {
+                self.topic = TopicChoice::Detect;
+                out.push("🏷️ following the topic of each message".to_string());
+            }
+            "/topic" if arg == "off" => {
+                self.topic = TopicChoice::Off;
+                out.push("🏷️ topics off".to_string());
+            }
+            "/topic" => {
+                if self.model().topics().contains(&arg) {
+                    self.topic = TopicChoice::Fixed(arg.to_string());
+                    out.push(format!("🏷️ always leaning towards {}", arg));
+                } else {
+                    out.push(format!("⚠️ unknown topic {:?}", arg));
+                }
+            }
+            "/persona" => match self.personas.select(arg) {
+                Ok(selection) => {
+                    out.push(format!(
+                        "🎭 speaking as {}",
+                        self.personas.describe(&selection)
+                    ));
+                    self.persona = selection;
+                }
+                Err(e) => out.push(format!("⚠️ {}", e)),
+            },
+            _ => return None,
+        }
+        Some(out)
+    }
+
+    /// Pick a seed from the input and history, then generate the reply
+    fn reply<F: FnMut(&str)>(&mut self, input: &str, on_token: &mut F) -> String {
+        let model = self.model.read().unwrap();
+        let chain = model.base();
+        // **📌 ペルソナを選んでいればブレンドの代わりに使う**
+        let blend: Vec<(&MarkovChain, f64)> = if self.persona.is_empty() {
+            self.blend.iter().map(|(chain, w)| (chain, *w)).collect()
+        } else {
+            self.personas.chains(&self.persona)
+        };
+
+        // **📌 入力と会話履歴からシード単語を選ぶ（未知の単語は近い既知の単語に置き換え）**
+        let tokens: Vec<String> = chain
+            .tokenizer()
+            .tokenize_input(input)
+            .into_iter()
+            .map(|token| {
+                let resolved = if blend.is_empty() {
+                    model.resolve_seed(&token)
+                } else {
+                    blend
+                        .iter()
+                        .find_map(|(chain, _)| chain.resolve_seed(&token))
+                };
+                resolved.map(String::from).unwrap_or(token)
+            })
+            .collect();
+        let is_known = |token: &str| {
+            if blend.is_empty() {
+                model.contains(token)
+            } else {
+                blend.iter().any(|(chain, _)| chain.contains(token))
+            }
+        };
+        // **📌 多くの文に出てくる助詞などよりも内容を表す単語をシードに選ぶ**
+        let salience = |token: &str| {
+            if blend.is_empty() {
+                model.salience(token)
+            } else {
+                blend_salience(&blend, token)
+            }
+        };
+        // **📌 応答ペアを学習していれば、入力に続きそうな返事の出だしを優先**
+        let response_seed = if blend.is_empty() {
+            model.response_seed(&tokens)
+        } else {
+            blend
+                .iter()
+                .find_map(|(chain, _)| chain.response_seed(&tokens))
+        };
+        let start_word = response_seed
+            .map(String::from)
+            .or_else(|| self.conversation.seed(&tokens, is_known, salience))
+            .or_else(|| tokens.first().cloned())
+            .unwrap_or_default();
+
+        // **📌 入力の複数の単語から返事をつなぎ合わせる場合はそのシードも選ぶ**
+        let compose = &self.config.compose;
+        let seeds = if compose.enabled() {
+            compose.salient(&tokens, is_known, salience)
+        } else {
+            Vec::new()
+        };
+        let composing = seeds.len() > 1;
+
+        self.seed = if composing {
+            seeds.join(" ")
+        } else {
+            start_word.clone()
+        };
+        self.fallback = !is_known(&start_word);
+
+        // **📌 話題タグ付きで学習したモデルでは入力の話題に寄せて答える（ペルソナ以外）**
+        let topic = match &self.topic {
+            _ if !blend.is_empty() => None,
+            TopicChoice::Detect => model
+                .detect_topic(&tokens, self.config.topic.min_score)
+                .map(String::from),
+            TopicChoice::Off => None,
+            TopicChoice::Fixed(name) => Some(name.clone()),
+        };
+        self.last_topic = topic.clone();
+
+        let words = chain.tokenizer().tokenize(input);
+        self.conversation.push(Speaker::User, words.clone());
+        self.opts.boost = self.conversation.boosts(self.config.history.boost);
+        self.opts.endings = self
+            .personas
+            .endings(&self.persona, &self.config.generation.endings);
+        let opts = &self.opts;
+        // **📌 候補を1つしか作らない場合は生成しながら表示（文字の書き換えや後処理、ビームサーチ、禁止語や長さ、繰り返しの確認では最後にまとめて）**
+        let stream = self.config.rerank.candidates <= 1
+            && self.config.output_script == OutputScript::AsIs
+            && self.config.postprocess.is_empty()
+            && opts.strategy == Strategy::Sample
+            && opts.blocklist.is_empty()
+            && !composing
+            && topic.is_none()
+            && !opts.limits.enabled()
+            && self.config.history.distinct_replies == 0;
+
+        // **📌 シードが分からないときは設定された応答に任せる**
+        let responder = self.responder.as_deref();
+        let fallback_reply = || {
+            responder
+                .and_then(|responder| responder.respond(input))
+                .unwrap_or_default()
+        };
+        self.last_reply.clear();
+        self.trace.clear();
+        if self.fallback && responder.is_some() {
+            let response = fallback_reply();
+            self.conversation.push(
+                Speaker::Bot,
+                response.split_whitespace().map(|s| s.to_string()).collect(),
+            );
+            return response;
+        }
+
+        // **📌 /debug on のときは候補の一覧を記録しながら生成**
+        let debug = self.debug && opts.strategy == Strategy::Sample;
+        let mut traces: Vec<(String, Vec<TraceStep>)> = Vec::new();
+        let mut generate = |opts: &GenerateOptions| {
+            if composing {
+                compose.compose(&seeds, opts, |seed, opts| {
+                    if blend.is_empty() {
+                        model.generate(seed, opts)
+                    } else {
+                        generate_blend(&blend, seed, opts)
+                    }
+                })
+            } else if !blend.is_empty() {
+                generate_blend(&blend, &start_word, opts)
+            } else if opts.keyword {
+                // **📌 入力の中で一番長い既知の単語をキーワードにする**
+                let keyword = words
+                    .iter()
+                    .filter(|w| model.contains(w) && w.chars().any(char::is_alphabetic))
+                    .max_by_key(|w| w.chars().count())
+                    .unwrap_or(&start_word);
+                model.generate_around(keyword, opts)
+            } else if let Some(topic) = &topic {
+                model.generate_with_topic(topic, &start_word, opts, self.config.topic.weight)
+            } else if stream || debug {
+                opts.limits.retry(|| {
+                    let mut tokens = model.stream(&start_word, opts);
+                    if debug {
+                        tokens = tokens.traced();
+                    }
+                    let reply = tokens
+                        .by_ref()
+                        .inspect(|token| {
+                            if stream {
+                                on_token(token)
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    traces.push((reply.clone(), tokens.take_trace()));
+                    reply
+                })
+            } else {
+                model.generate(&start_word, opts)
+            }
+        };
+        // **📌 禁止語を含む返事や、最近と同じ返事は作り直す**
+        let retries = self.config.blocklist.retries;
+        let history = &self.config.history;
+        let mut response = self.conversation.fresh_reply(
+            opts,
+            history.repeat_retries,
+            history.repeat_heat,
+            |opts| {
+                best_reply(chain, &words, &self.config.rerank, || {
+                    opts.blocklist.regenerate(retries, || generate(opts))
+                })
+            },
+        );
+        if response.trim().is_empty() {
+            response = fallback_reply();
+        } else {
+            self.last_reply = response.split_whitespace().map(str::to_string).collect();
+        }
+        if let Some((_, trace)) = traces.into_iter().find(|(reply, _)| *reply == response) {
+            self.trace = trace;
+        }
+
+        self.conversation.push(
+            Speaker::Bot,
+            response.split_whitespace().map(|s| s.to_string()).collect(),
+        );
+        response
+    }
+}
+
+/// Commit the session into the layer below it and save that layer, returning
+/// the line to show
+fn commit(model: &RwLock<LayeredModel>, config: &Config, events: &EventBus) -> String {
+    let target = model.write().unwrap().commit(SESSION).flatten();
+    // **📌 保存のあいだも返事は作れるように読み取りロックで書き出す**
+    let model = model.read().unwrap();
+    let (chain, path) = match &target {
+        Some(name) => (model.overlay(name), Some(PathBuf::from(name))),
+        None => (Some(model.base()), config.model.path.clone()),
+    };
+    match (chain, path) {
+        (Some(chain), Some(path)) => match chain.save(&path) {
+            Ok(()) => {
+                events.publish(Event::ModelSaved { path: &path });
+                format!("💾 committed to {}", path.display())
+            }
+            Err(e) => format!("⚠️ Failed to save model: {}", e),
+        },
+        _ => "💾 committed (no model.path configured, not saved)".to_string(),
+    }
+}
+
+/// Next message of an optional channel; pending forever without one, so a
+/// `select!` branch on it never fires
+async fn recv(channel: &mut Option<UnboundedReceiver<String>>) -> Option<String> {
+    match channel {
+        Some(channel) => channel.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+// **📌 LineReader構造体：別スレッドで rustyline を動かして1行ずつ渡す**
+struct LineReader {
+    lines: UnboundedReceiver<String>,
+    /// Lets the editor show the next prompt once the answer was printed
+    ready: Option<mpsc::Sender<()>>,
+    /// Prints above the prompt while a line is being typed; `None` when
+    /// stdin is not a terminal
+    printer: Option<Box<dyn ExternalPrinter + Send>>,
+    /// Thread running the editor, or reading stdin directly when it is not a
+    /// terminal or not in UTF-8
+    editor: Option<thread::JoinHandle<()>>,
+    output: TextEncoding,
+}
+
+impl LineReader {
+    fn spawn(config: &Config, color: bool, output: TextEncoding) -> Self {
+        let (send, lines) = unbounded_channel();
+        let (ready, next) = mpsc::channel();
+        // **📌 パイプや端末以外の文字コードでは rustyline を使わずバイト列で読む**
+        let input = config.chat.encoding;
+        let raw = !io::stdin().is_terminal() || (input == TextEncoding::ShiftJis && !cfg!(windows));
+        if raw {
+            let reader = thread::spawn(move || read_lines(input, send, next));
+            return Self {
+                lines,
+                ready: Some(ready),
+                printer: None,
+                editor: Some(reader),
+                output,
+            };
+        }
+        let (send_printer, printer) = mpsc::channel();
+        let prompt = config.prompt.user.clone();
+        let history = config.chat.history_file.clone();
+        let editor = thread::spawn(move || {
+            let mut editor: Editor<SlashCompleter, DefaultHistory> =
+                Editor::new().expect("Failed to start line editor");
+            editor.set_helper(Some(SlashCompleter { color }));
+            if let Some(path) = &history {
+                // **📌 初回は履歴ファイルがまだないので失敗しても無視**
+                let _ = editor.load_history(path);
+            }
+            let printer = editor
+                .create_external_printer()
+                .ok()
+                .map(|printer| Box::new(printer) as Box<dyn ExternalPrinter + Send>);
+            let _ = send_printer.send(printer);
+
+            loop {
+                let line = match editor.readline(&prompt) {
+                    Ok(line) => line,
+                    // **📌 Ctrl-C は入力中の行を取り消すだけ、Ctrl-D で終了**
+                    Err(ReadlineError::Interrupted) => continue,
+                    Err(ReadlineError::Eof) => break,
+                    Err(e) => panic!("Failed to read input: {}", e),
+                };
+                let line = line.trim();
+                if !line.is_empty() {
+                    let _ = editor.add_history_entry(line);
+                }
+                if send.send(line.to_string()).is_err() || next.recv().is_err() {
+                    break;
+                }
+            }
+
+            if let Some(path) = &history {
+                if let Err(e) = editor.save_history(path) {
+                    eprintln!("⚠️ Failed to save input history: {}", e);
+                }
+            }
+        });
+        Self {
+            lines,
+            ready: Some(ready),
+            printer: printer.recv().ok().flatten(),
+            editor: Some(editor),
+            output,
+        }
+    }
+
+    /// Read the next line, once the last one was answered
+    fn next(&mut self) {
+        if let Some(ready) = &self.ready {
+            let _ = ready.send(());
+        }
+    }
+
+    fn print(&mut self, line: String) {
+        let printed = self
+            .printer
+            .as_mut()
+            .is_some_and(|printer| printer.print(format!("{}\n", line)).is_ok());
+        if !printed {
+            say(self.output, &format!("{}\n", line));
+        }
+    }
+
+    /// Stop the editor and wait for it to save the input history
+    fn close(mut self) {
+        self.ready = None;
+        if let Some(editor) = self.editor.take() {
+            let _ = editor.join();
+        }
+    }
+}
+
+/// Send the lines of stdin decoded from `encoding`, each once the last one
+/// was answered, until stdin closes
+fn read_lines(encoding: TextEncoding, send: UnboundedSender<String>, next: mpsc::Receiver<()>) {
+    let mut stdin = io::stdin().lock();
+    loop {
+        let mut bytes = Vec::new();
+        match stdin.read_until(b'\n', &mut bytes) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => panic!("Failed to read input: {}", e),
+        }
+        let line = encoding.decode(bytes.trim_ascii());
+        if send.send(line.trim().to_string()).is_err() || next.recv().is_err() {
+            break;
+        }
+    }
+}
+
+/// Watch the model, its overlays and the corpus manifest on a background
+/// thread woken by file change notifications, sending what was reloaded or
+/// retrained. A retrained model is saved to `model.path` unless the model is
+/// read-only
+fn watch(config: &Config) -> mpsc::Receiver<Reload> {
+    let (send, reloads) = mpsc::channel();
+    let config = config.clone();
+    thread::spawn(move || {
+        let (wake, woken) = mpsc::channel();
+        let mut models = watcher(
+            config
+                .model
+                .path
+                .iter()
+                .chain(&config.model.overlays)
+                .cloned(),
+            &wake,
+        );
+        let mut corpus = corpus_watcher(&config, &wake);
+        while woken.recv().is_ok() {
+            // **📌 まとめて届いた通知は1回分として扱う**
+            while woken.try_recv().is_ok() {}
+            let mut found = Vec::new();
+            if let Some(manifest) = &config.model.corpus {
+                if !corpus.changed().is_empty() {
+                    found.push(match retrain(&config, manifest) {
+                        Ok(chain) => {
+                            let out = config.model.path.as_ref();
+                            if let Some(out) = out.filter(|_| !config.model.read_only) {
+                                match chain.save(out) {
+                                    Ok(()) => models.refresh(out),
+                                    Err(e) => eprintln!("⚠️ Failed to save model: {}", e),
+                                }
+                            }
+                            Reload::Retrained {
+                                path: manifest.clone(),
+                                chain,
+                            }
+                        }
+                        Err(e) => Reload::Failed {
+                            path: manifest.clone(),
+                            error: e.to_string(),
+                        },
+                    });
+                    // **📌 マニフェストが変わると学習元のファイルも変わりうる**
+                    corpus = corpus_watcher(&config, &wake);
+                }
+            }
+            for path in models.changed() {
+                found.push(match MarkovChain::load(&path) {
+                    Ok(chain) => {
+                        chain.finalize();
+                        if config.model.path.as_ref() == Some(&path) {
+                            Reload::Base { path, chain }
+                        } else {
+                            Reload::Overlay { path, chain }
+                        }
+                    }
+                    Err(e) => Reload::Failed {
+                        path,
+                        error: e.to_string(),
+                    },
+                });
+            }
+            for reload in found {
+                if send.send(reload).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    reloads
+}
+
+/// Watcher over the corpus manifest and the sources it lists
+fn corpus_watcher(config: &Config, wake: &mpsc::Sender<()>) -> FileWatcher {
+    let Some(manifest) = &config.model.corpus else {
+        return FileWatcher::default();
+    };
+    let sources: Vec<PathBuf> = CorpusManifest::load(manifest)
+        .map(|plan| plan.sources.into_iter().map(|s| s.path).collect())
+        .unwrap_or_default();
+    watcher(std::iter::once(manifest.clone()).chain(sources), wake)
+}
+
+/// Watcher over `paths` waking `wake`; one that sees nothing when the OS
+/// refuses to watch them
+fn watcher(paths: impl IntoIterator<Item = PathBuf>, wake: &mpsc::Sender<()>) -> FileWatcher {
+    FileWatcher::new(paths, wake.clone()).unwrap_or_else(|e| {
+        eprintln!("⚠️ Failed to watch files: {}", e);
+        FileWatcher::default()
+    })
+}
+
+/// Train a new base model from a corpus manifest
+fn retrain(config: &Config, manifest: &Path) -> io::Result<MarkovChain> {
+    let plan = CorpusManifest::load(manifest)?;
+    let order = plan.order.unwrap_or(config.model.order);
+    let mut chain = MarkovChain::new(order, config.tokenizer.clone());
+    for source in &plan.sources {
+        plan.learn(source, &mut chain, &config.corpus)?;
+    }
+    chain.finalize();
+    Ok(chain)
+}
+
+/// Subscriber appending every reply to a JSON Lines transcript
+fn transcript_writer(log: File) -> impl Fn(&Event) + Send + Sync {
+    let log = Mutex::new(log);
+    move |event| {
+        let Event::ResponseGenerated {
+            input,
+            response,
+            latency,
+            fallback,
+        } = *event
+        else {
+            return;
+        };
+        let entry = TranscriptEntry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            input: input.to_string(),
+            response: response.to_string(),
+            latency_ms: latency.as_secs_f64() * 1000.0,
+            fallback,
+        };
+        let line = serde_json::to_string(&entry).expect("transcript entry serializes");
+        if let Err(e) = writeln!(log.lock().unwrap(), "{}", line) {
+            eprintln!("⚠️ Failed to write transcript: {}", e);
+        }
+    }
+}
+
+/// Whether the terminal chat should be colored: `chat.color` is on, `NO_COLOR`
+/// is unset and stdout is a terminal
+fn use_color(config: &Config) -> bool {
+    config.chat.color
+        && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        && io::stdout().is_terminal()
+}
+
+/// Print `text` to stdout in `encoding`
+fn say(encoding: TextEncoding, text: &str) {
+    let mut stdout = io::stdout().lock();
+    stdout
+        .write_all(&encoding.encode(text))
+        .and_then(|()| stdout.flush())
+        .expect("Failed to write to stdout");
+}
+
+/// `text` in `color` when coloring, as-is otherwise
+fn paint<'a>(text: &'a str, color: &str, enabled: bool) -> Cow<'a, str> {
+    if enabled {
+        Cow::Owned(format!("{}{}{}", color, text, RESET))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+// **📌 Typer構造体：返答をトークンごと、または1文字ずつ表示する**
+struct Typer {
+    tokens: Vec<String>,
+    /// Bytes of the spacing-repaired text already printed
+    printed: usize,
+    delay: Duration,
+    /// Pause after every character instead of after every token
+    by_char: bool,
+    output: TextEncoding,
+}
+
+impl Typer {
+    fn new(delay: Duration, by_char: bool, output: TextEncoding) -> Self {
+        Self {
+            tokens: Vec::new(),
+            printed: 0,
+            delay,
+            by_char,
+            output,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Print the text the token adds; spacing only depends on earlier tokens,
+    /// so the repaired text only ever grows at the end. Pauses without
+    /// holding up the runtime's other tasks
+    async fn push(&mut self, token: &str) {
+        self.tokens.push(token.to_string());
+        let tokens: Vec<&str> = self.tokens.iter().map(String::as_str).collect();
+        let text = repair_spacing(&tokens);
+        let added = &text[self.printed..];
+        if self.by_char {
+            for c in added.chars() {
+                say(self.output, c.encode_utf8(&mut [0; 4]));
+                if !c.is_whitespace() {
+                    tokio::time::sleep(self.delay).await;
+                }
+            }
+        } else {
+            say(self.output, added);
+            tokio::time::sleep(self.delay).await;
+        }
+        self.printed = text.len();
+    }
+}
+
+// **📌 SlashCompleter構造体：スラッシュコマンドの補完**
+#[derive(Helper, Hinter, Validator)]
+struct SlashCompleter {
+    /// Color the prompt; rustyline wants it plain and colored only here
+    color: bool,
+}
+
+impl Highlighter for SlashCompleter {
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
+        &'s self,
+        prompt: &'p str,
+        _default: bool,
+    ) -> Cow<'b, str> {
+        paint(prompt, USER_COLOR, self.color)
+    }
+}
+
+impl Completer for SlashCompleter {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let typed = &line[..pos];
+        if !typed.starts_with('/') || typed.contains(' ') {
+            return Ok((pos, Vec::new()));
+        }
+        let matches = COMMANDS
+            .iter()
+            .filter(|command| command.starts_with(typed))
+            .map(|command| command.to_string())
+            .collect();
+        Ok((0, matches))
+    }
+}
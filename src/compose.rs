@@ -0,0 +1,123 @@
+use crate::{
+    markov::{is_terminator, GenerateOptions, GenerationParams},
+    postprocess::DANGLING,
+};
+use rand::seq::IndexedRandom;
+use serde::Deserialize;
+
+// **📌 ComposeConfig構造体：入力の複数の単語から返事をつなぎ合わせる設定**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ComposeConfig {
+    /// Input words each seeding a segment of the reply; 1 seeds the reply once
+    pub seeds: usize,
+    /// Tokens generated after the seed of each segment
+    pub segment_length: usize,
+    /// Words one of which opens every segment after the first, followed by `、`
+    pub connectors: Vec<String>,
+}
+
+impl Default for ComposeConfig {
+    fn default() -> Self {
+        Self {
+            seeds: 1,
+            segment_length: 8,
+            connectors: vec![
+                "それに".to_string(),
+                "あと".to_string(),
+                "そういえば".to_string(),
+            ],
+        }
+    }
+}
+
+impl ComposeConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.seeds == 0 {
+            return Err("seeds must be at least 1".to_string());
+        }
+        if self.segment_length == 0 {
+            return Err("segment_length must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.seeds > 1
+    }
+
+    /// Up to `seeds` known words of the input worth answering, in input order:
+    /// the most salient ones, then the longest, skipping punctuation and
+    /// particles
+    pub fn salient<'a, F, S>(&self, tokens: &'a [String], is_known: F, salience: S) -> Vec<&'a str>
+    where
+        F: Fn(&str) -> bool,
+        S: Fn(&str) -> f64,
+    {
+        let mut candidates: Vec<(usize, &str)> = Vec::new();
+        for (i, token) in tokens.iter().enumerate() {
+            let token = token.as_str();
+            if token.chars().any(char::is_alphabetic)
+                && !DANGLING.contains(&token)
+                && is_known(token)
+                && !candidates.iter().any(|(_, seen)| *seen == token)
+            {
+                candidates.push((i, token));
+            }
+        }
+        // **📌 珍しい単語ほど、同じなら長い単語ほど内容を表すとみなし、選んだ後は入力の順に戻す**
+        candidates.sort_by(|(i, a), (j, b)| {
+            salience(b)
+                .total_cmp(&salience(a))
+                .then(b.chars().count().cmp(&a.chars().count()))
+                .then(i.cmp(j))
+        });
+        candidates.truncate(self.seeds);
+        candidates.sort_by_key(|(i, _)| *i);
+        candidates.into_iter().map(|(_, token)| token).collect()
+    }
+
+    /// Generate a short segment from each seed with `generate` and join them,
+    /// ending every segment with a terminator and opening the next with a
+    /// connector; segments that come out the same are kept once. The length
+    /// limits of `opts` apply to the whole reply, not to each segment
+    pub fn compose<F>(&self, seeds: &[&str], opts: &GenerateOptions, mut generate: F) -> String
+    where
+        F: FnMut(&str, &GenerateOptions) -> String,
+    {
+        let segment = GenerateOptions {
+            length: self.segment_length,
+            limits: GenerationParams::default(),
+            ..opts.clone()
+        };
+        opts.limits
+            .retry(|| self.join(seeds, &segment, &mut generate))
+    }
+
+    fn join<F>(&self, seeds: &[&str], opts: &GenerateOptions, generate: &mut F) -> String
+    where
+        F: FnMut(&str, &GenerateOptions) -> String,
+    {
+        let mut rng = rand::rng();
+        let mut segments: Vec<String> = Vec::new();
+        let mut tokens: Vec<String> = Vec::new();
+        for seed in seeds {
+            let segment = generate(seed, opts);
+            if segment.trim().is_empty() || segments.contains(&segment) {
+                continue;
+            }
+            if !tokens.is_empty() {
+                if let Some(connector) = self.connectors.choose(&mut rng) {
+                    tokens.push(connector.clone());
+                    tokens.push("、".to_string());
+                }
+            }
+            tokens.extend(segment.split_whitespace().map(str::to_string));
+            if !tokens.last().is_some_and(|token| is_terminator(token)) {
+                tokens.push("。".to_string());
+            }
+            segments.push(segment);
+        }
+        tokens.join(" ")
+    }
+}
@@ -0,0 +1,96 @@
+use std::{io, path::Path};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+// **📌 Compression：モデルファイルの圧縮形式**
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Format a file should be written in, from its extension
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst" | "zstd") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// Format of data that was read back, from its magic bytes
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if bytes.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Levels the format accepts
+    pub fn levels(self) -> std::ops::RangeInclusive<i32> {
+        match self {
+            Compression::None => 0..=0,
+            Compression::Gzip => 0..=9,
+            Compression::Zstd => 1..=22,
+        }
+    }
+
+    /// Compress `bytes`, at the format's default level unless `level` is given
+    pub fn compress(self, bytes: &[u8], level: Option<i32>) -> io::Result<Vec<u8>> {
+        if let Some(level) = level.filter(|level| !self.levels().contains(level)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("compression level {} is out of range for {:?}", level, self),
+            ));
+        }
+        match self {
+            Compression::None => Ok(bytes.to_vec()),
+            #[cfg(feature = "compression")]
+            Compression::Gzip => {
+                use std::io::Write;
+                let level = level.map_or(flate2::Compression::default(), |level| {
+                    flate2::Compression::new(level as u32)
+                });
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), level);
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+            #[cfg(feature = "compression")]
+            Compression::Zstd => zstd::encode_all(bytes, level.unwrap_or(0)),
+            #[cfg(not(feature = "compression"))]
+            _ => Err(unsupported()),
+        }
+    }
+
+    /// Undo whatever compression `bytes` were written with
+    pub fn decompress(bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+        match Self::detect(&bytes) {
+            Compression::None => Ok(bytes),
+            #[cfg(feature = "compression")]
+            Compression::Gzip => {
+                use std::io::Read;
+                let mut out = Vec::new();
+                flate2::read::GzDecoder::new(bytes.as_slice()).read_to_end(&mut out)?;
+                Ok(out)
+            }
+            #[cfg(feature = "compression")]
+            Compression::Zstd => zstd::decode_all(bytes.as_slice()),
+            #[cfg(not(feature = "compression"))]
+            _ => Err(unsupported()),
+        }
+    }
+}
+
+#[cfg(not(feature = "compression"))]
+fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "compressed models need the `compression` feature",
+    )
+}
@@ -0,0 +1,624 @@
+use crate::{
+    blocklist::BlocklistConfig,
+    compose::ComposeConfig,
+    corpus::{Cleaning, CorpusLimits},
+    decay::DecayConfig,
+    encoding::TextEncoding,
+    fallback::{FallbackConfig, FallbackKind},
+    feedback::FeedbackConfig,
+    guard::GuardConfig,
+    kana::OutputScript,
+    markov::GenerateOptions,
+    persona::PersonaConfig,
+    postprocess::FilterStage,
+    reaction::ReactionMode,
+    recent::RecentConfig,
+    rerank::RerankOptions,
+    session::SessionConfig,
+    story::StoryOptions,
+    tokenizer::TokenizerConfig,
+    topic::TopicConfig,
+    trigger::TriggerConfig,
+    tts::TtsConfig,
+    typing::TypingConfig,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Config file looked up in the working directory
+pub const CONFIG_FILE: &str = "wordora.toml";
+
+/// Bundled generation presets, selectable with `--preset` or `preset = "..."`
+pub const PRESETS: &[(&str, &str)] = &[
+    ("coherent", include_str!("../presets/coherent.toml")),
+    ("chaotic", include_str!("../presets/chaotic.toml")),
+    (
+        "short-replies",
+        include_str!("../presets/short-replies.toml"),
+    ),
+    ("storyteller", include_str!("../presets/storyteller.toml")),
+];
+
+// **📌 Config構造体：wordora.toml の設定を管理**
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Bundled preset the rest of the file is layered on top of
+    pub preset: Option<String>,
+    pub generation: GenerateOptions,
+    pub tokenizer: TokenizerConfig,
+    pub model: ModelConfig,
+    /// Limits applied when reading corpus files for training
+    pub corpus: CorpusLimits,
+    /// Steps `train --clean` applies to every file; unset uses
+    /// [`Cleaning::recommended`]
+    pub clean: Option<Cleaning>,
+    /// Fading of older counts when learning continuously in chat or on Discord
+    pub decay: DecayConfig,
+    /// Heuristics the Discord and Telegram bots use to refuse abusive input
+    pub guard: GuardConfig,
+    /// Small model of each channel's latest messages blended into bot replies
+    pub recent: RecentConfig,
+    /// Delay before the Discord and Telegram bots send a reply, as if typing it
+    pub typing: TypingConfig,
+    /// Words replies must never contain
+    pub blocklist: BlocklistConfig,
+    /// How much rating a reply with /good or /bad changes its transitions
+    pub feedback: FeedbackConfig,
+    /// Script the kana of every reply is rewritten in
+    pub output_script: OutputScript,
+    /// Stages every reply goes through before it is shown, in order, e.g.
+    /// `["trim-particles", { template = "{reply}ですね" }, "ensure-terminator"]`
+    pub postprocess: Vec<FilterStage>,
+    pub prompt: PromptConfig,
+    pub history: HistoryConfig,
+    pub chat: ChatConfig,
+    pub tts: TtsConfig,
+    pub rerank: RerankOptions,
+    /// Replies stitched from segments seeded by several words of the input
+    pub compose: ComposeConfig,
+    /// Answering a message in the flavor of the topic it is about, for models
+    /// trained with topic tags
+    pub topic: TopicConfig,
+    pub fallback: FallbackConfig,
+    pub story: StoryOptions,
+    /// Models mixed together at generation time instead of the main model
+    pub blend: Vec<BlendConfig>,
+    /// Named models `/persona` and the server's `persona` field switch between
+    pub persona: Vec<PersonaConfig>,
+    pub server: ServerConfig,
+    /// Per-user conversations of the HTTP server, see `POST /sessions`
+    pub session: SessionConfig,
+    pub discord: DiscordConfig,
+    pub telegram: TelegramConfig,
+    /// IRC and Matrix bridges, see `bridge irc` and `bridge matrix`
+    pub bridge: BridgeConfig,
+}
+
+// **📌 モデルの設定**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ModelConfig {
+    /// Saved model; loaded if it exists, otherwise written after training
+    pub path: Option<PathBuf>,
+    /// Saved models layered over the base, lowest priority first
+    pub overlays: Vec<PathBuf>,
+    /// Number of preceding tokens used as context
+    pub order: usize,
+    /// Most tokens a model learning from chat or Discord may hold; the least
+    /// frequent ones are evicted beyond it
+    pub max_vocab: Option<usize>,
+    /// Never learn, unlearn or merge into the loaded models
+    pub read_only: bool,
+    /// Seconds between saves of models learning online (Discord guilds and
+    /// `train --follow`) that have unsaved changes; unset only saves every
+    /// `save_every` messages
+    pub autosave_seconds: Option<f64>,
+    /// Reload the model and overlays in chat when their files change
+    pub watch: bool,
+    /// Corpus manifest (see `train --manifest`) the chat model is retrained
+    /// from when it or one of its sources changes, with `watch`
+    pub corpus: Option<PathBuf>,
+}
+
+impl Default for ModelConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            overlays: Vec::new(),
+            order: 1,
+            max_vocab: None,
+            read_only: false,
+            autosave_seconds: None,
+            watch: false,
+            corpus: None,
+        }
+    }
+}
+
+// **📌 チャットの設定**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ChatConfig {
+    /// Learn every message into the session overlay (kept only after `/commit`)
+    pub learn: bool,
+    /// Pause after each printed token of a reply; 0 prints it at once
+    pub typing_delay_ms: u64,
+    /// Type replies out one character at a time, pausing `typing_delay_ms`
+    /// after each, instead of one token at a time
+    pub type_by_char: bool,
+    /// Color the prompts; off with `--no-color`, `NO_COLOR` or when the
+    /// output is not a terminal
+    pub color: bool,
+    /// Where the input history is kept between sessions
+    pub history_file: Option<PathBuf>,
+    /// Print every event published on the chat's event bus to stderr
+    pub log_events: bool,
+    /// Append every exchange to this JSON Lines file, for `analyze-transcript`
+    pub transcript: Option<PathBuf>,
+    /// Commit and save what the session learned this often in the background,
+    /// as `/commit` does; unset keeps it until `/commit`
+    pub autocommit_seconds: Option<f64>,
+    /// Encoding of stdin and stdout: auto, utf-8, shift_jis or cp932
+    pub encoding: TextEncoding,
+    /// Trainer mode: list the words of each message the model has never seen
+    /// after the reply; toggled with `/trainer`
+    pub trainer: bool,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            learn: false,
+            typing_delay_ms: 30,
+            type_by_char: false,
+            color: true,
+            history_file: Some(PathBuf::from(".wordora_history")),
+            log_events: false,
+            transcript: None,
+            autocommit_seconds: None,
+            encoding: TextEncoding::default(),
+            trainer: false,
+        }
+    }
+}
+
+// **📌 会話履歴の設定**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// Number of user/bot turns kept
+    pub turns: usize,
+    /// Extra weight given to transitions towards tokens of the newest turn
+    pub boost: f64,
+    /// Latest bot replies a new reply must not repeat word for word; 0 allows
+    /// repeats
+    pub distinct_replies: usize,
+    /// Times a repeated reply is generated again before it is sent anyway
+    pub repeat_retries: usize,
+    /// Temperature added on each of those retries
+    pub repeat_heat: f64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            turns: 6,
+            boost: 0.5,
+            distinct_replies: 8,
+            repeat_retries: 3,
+            repeat_heat: 0.2,
+        }
+    }
+}
+
+// **📌 HTTPサーバーの設定**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub addr: String,
+    /// Threads used to answer the prompts of one batch request
+    pub parallelism: usize,
+    /// Largest number of prompts accepted in one batch request
+    pub max_batch: usize,
+    /// Request bodies larger than this are refused
+    pub max_body_bytes: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            addr: "127.0.0.1:8080".to_string(),
+            parallelism: 4,
+            max_batch: 64,
+            max_body_bytes: 1024 * 1024,
+        }
+    }
+}
+
+// **📌 Discordボットの設定**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DiscordConfig {
+    /// Environment variable holding the bot token
+    pub token_env: String,
+    /// Learn from every message in the channels the bot can read
+    pub learn: bool,
+    /// Folder with one overlay model per guild, named `<guild id>.bin`
+    pub guild_models: Option<PathBuf>,
+    /// Learned messages between saves of a guild model
+    pub save_every: usize,
+    /// Minimum time between two replies in the same channel
+    pub rate_limit_seconds: f64,
+    /// React to mentions with an emoji learned to follow their words
+    pub reaction: ReactionMode,
+    /// Remember what each author taught a guild model under their user ID,
+    /// so `forget --source <id> --model <guild>.bin` can remove it
+    pub track_authors: bool,
+    /// When the bot answers a message; by default mentions and replies to it
+    pub trigger: TriggerConfig,
+}
+
+impl Default for DiscordConfig {
+    fn default() -> Self {
+        Self {
+            token_env: "DISCORD_TOKEN".to_string(),
+            learn: false,
+            guild_models: Some(PathBuf::from("guilds")),
+            save_every: 20,
+            rate_limit_seconds: 5.0,
+            reaction: ReactionMode::Off,
+            track_authors: false,
+            trigger: TriggerConfig::default(),
+        }
+    }
+}
+
+// **📌 Telegramボットの設定**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TelegramConfig {
+    /// Environment variable holding the bot token
+    pub token_env: String,
+    /// Folder with one overlay model per chat, named `<chat id>.bin`
+    pub chat_models: Option<PathBuf>,
+    /// When the bot answers a message; by default every message
+    pub trigger: TriggerConfig,
+}
+
+impl Default for TelegramConfig {
+    fn default() -> Self {
+        Self {
+            token_env: "TELOXIDE_TOKEN".to_string(),
+            chat_models: Some(PathBuf::from("telegram_chats")),
+            trigger: TriggerConfig {
+                probability: 1.0,
+                ..TriggerConfig::default()
+            },
+        }
+    }
+}
+
+// **📌 IRCやMatrixにつなぐブリッジの設定**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BridgeConfig {
+    /// Learn from every message in the channels and rooms the bot is in
+    pub learn: bool,
+    /// Overlay model holding what was learned, loaded if it exists; nothing
+    /// is saved when unset
+    pub model: Option<PathBuf>,
+    /// Learned messages between saves of the overlay
+    pub save_every: usize,
+    /// When the bot answers a message; by default when addressed by name
+    pub trigger: TriggerConfig,
+    pub irc: IrcConfig,
+    pub matrix: MatrixConfig,
+}
+
+impl Default for BridgeConfig {
+    fn default() -> Self {
+        Self {
+            learn: false,
+            model: Some(PathBuf::from("bridge.bin")),
+            save_every: 20,
+            trigger: TriggerConfig::default(),
+            irc: IrcConfig::default(),
+            matrix: MatrixConfig::default(),
+        }
+    }
+}
+
+// **📌 IRCの接続先**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct IrcConfig {
+    /// Server to connect to, as `host:port`
+    pub server: String,
+    /// Connect over TLS
+    pub tls: bool,
+    pub nick: String,
+    /// Channels joined after connecting, e.g. `#wordora`
+    pub channels: Vec<String>,
+    /// Environment variable holding the server password, sent when set
+    pub password_env: String,
+}
+
+impl Default for IrcConfig {
+    fn default() -> Self {
+        Self {
+            server: "irc.libera.chat:6697".to_string(),
+            tls: true,
+            nick: "wordora".to_string(),
+            channels: Vec::new(),
+            password_env: "IRC_PASSWORD".to_string(),
+        }
+    }
+}
+
+// **📌 Matrixの接続先**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MatrixConfig {
+    /// Base URL of the homeserver
+    pub homeserver: String,
+    /// Environment variable holding the bot account's access token
+    pub token_env: String,
+    /// Rooms joined and answered in, by ID (`!abc:matrix.org`) or alias
+    /// (`#wordora:matrix.org`)
+    pub rooms: Vec<String>,
+}
+
+impl Default for MatrixConfig {
+    fn default() -> Self {
+        Self {
+            homeserver: "https://matrix.org".to_string(),
+            token_env: "MATRIX_TOKEN".to_string(),
+            rooms: Vec::new(),
+        }
+    }
+}
+
+// **📌 ブレンドするモデルの設定**
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlendConfig {
+    pub path: PathBuf,
+    pub weight: f64,
+}
+
+// **📌 プロンプトの設定**
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PromptConfig {
+    pub banner: String,
+    pub user: String,
+    pub bot: String,
+    pub exit: String,
+}
+
+impl Default for PromptConfig {
+    fn default() -> Self {
+        Self {
+            banner: "🔹 Wordora Markov ChatBot 🔹".to_string(),
+            user: "あなた: ".to_string(),
+            bot: "Bot: ".to_string(),
+            exit: "exit".to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// Load and validate a config file, layered over `preset` if one is given
+    pub fn load(path: &Path, preset: Option<&str>) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::from_toml(&text, preset)
+    }
+
+    /// Load `wordora.toml` if present, otherwise use the defaults
+    pub fn load_or_default(preset: Option<&str>) -> io::Result<Self> {
+        let path = Path::new(CONFIG_FILE);
+        if path.exists() {
+            Self::load(path, preset)
+        } else {
+            Self::from_toml("", preset)
+        }
+    }
+
+    /// Cleaning steps of `train --clean`
+    pub fn cleaning(&self) -> Cleaning {
+        self.clean.clone().unwrap_or_else(Cleaning::recommended)
+    }
+
+    fn from_toml(text: &str, preset: Option<&str>) -> io::Result<Self> {
+        let user: toml::Table = parse_toml(text)?;
+
+        // **📌 プリセットを土台にしてユーザーの設定で上書き**
+        let preset = preset.map(|name| name.to_string()).or_else(|| {
+            user.get("preset")
+                .and_then(|v| v.as_str())
+                .map(String::from)
+        });
+        let mut table = match &preset {
+            Some(name) => preset_table(name)?,
+            None => toml::Table::new(),
+        };
+        merge_tables(&mut table, user);
+
+        let mut config: Config = toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        config.preset = preset;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> io::Result<()> {
+        let invalid = |msg: String| Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+
+        if let Err(e) = self.tokenizer.validate() {
+            return invalid(e.to_string());
+        }
+        if self.model.order == 0 {
+            return invalid("model.order must be at least 1".to_string());
+        }
+        if self.model.max_vocab == Some(0) {
+            return invalid("model.max_vocab must be at least 1".to_string());
+        }
+        if self
+            .chat
+            .autocommit_seconds
+            .is_some_and(|seconds| !(seconds.is_finite() && seconds > 0.0))
+        {
+            return invalid("chat.autocommit_seconds must be positive".to_string());
+        }
+        if self
+            .model
+            .autosave_seconds
+            .is_some_and(|seconds| !(seconds.is_finite() && seconds > 0.0))
+        {
+            return invalid("model.autosave_seconds must be positive".to_string());
+        }
+        if let Err(e) = self.generation.validate() {
+            return invalid(format!("generation: {}", e));
+        }
+        if let Err(e) = self.decay.validate() {
+            return invalid(format!("decay: {}", e));
+        }
+        if let Err(e) = self.guard.validate() {
+            return invalid(format!("guard: {}", e));
+        }
+        if let Err(e) = self.recent.validate() {
+            return invalid(format!("recent: {}", e));
+        }
+        if let Err(e) = self.typing.validate() {
+            return invalid(format!("typing: {}", e));
+        }
+        if let Err(e) = self.blocklist.validate() {
+            return invalid(format!("blocklist: {}", e));
+        }
+        if let Err(e) = self.feedback.validate() {
+            return invalid(format!("feedback: {}", e));
+        }
+        if let Err(e) = self.session.validate() {
+            return invalid(format!("session: {}", e));
+        }
+        for stage in &self.postprocess {
+            if let Err(e) = stage.validate() {
+                return invalid(format!("postprocess: {}", e));
+            }
+        }
+        if let Err(e) = self.discord.trigger.validate() {
+            return invalid(format!("discord.trigger: {}", e));
+        }
+        if let Err(e) = self.telegram.trigger.validate() {
+            return invalid(format!("telegram.trigger: {}", e));
+        }
+        if let Err(e) = self.bridge.trigger.validate() {
+            return invalid(format!("bridge.trigger: {}", e));
+        }
+        if self.corpus.max_record_bytes == 0 {
+            return invalid("corpus.max_record_bytes must be at least 1".to_string());
+        }
+        let rate_limit = self.discord.rate_limit_seconds;
+        if !(0.0..=1e9).contains(&rate_limit) {
+            return invalid("discord.rate_limit_seconds must be between 0 and 1e9".to_string());
+        }
+        if self.server.parallelism == 0 {
+            return invalid("server.parallelism must be at least 1".to_string());
+        }
+        if self.fallback.responder == FallbackKind::Canned && self.fallback.replies.is_empty() {
+            return invalid(
+                "fallback.replies must not be empty for the canned responder".to_string(),
+            );
+        }
+        if let Some(Err(e)) = self.clean.as_ref().map(Cleaning::validate) {
+            return invalid(format!("clean: {}", e));
+        }
+        if let Err(e) = self.topic.validate() {
+            return invalid(format!("topic: {}", e));
+        }
+        if let Err(e) = self.compose.validate() {
+            return invalid(format!("compose: {}", e));
+        }
+        if let Err(e) = self.story.validate() {
+            return invalid(format!("story: {}", e));
+        }
+        if self.history.boost.is_nan() || self.history.boost < 0.0 {
+            return invalid("history.boost must not be negative".to_string());
+        }
+        if !(self.history.repeat_heat.is_finite() && self.history.repeat_heat >= 0.0) {
+            return invalid("history.repeat_heat must not be negative".to_string());
+        }
+        if self.history.repeat_retries > 100 {
+            return invalid("history.repeat_retries must be at most 100".to_string());
+        }
+        if self
+            .blend
+            .iter()
+            .any(|b| b.weight.is_nan() || b.weight < 0.0)
+        {
+            return invalid("blend weights must not be negative".to_string());
+        }
+        for (i, persona) in self.persona.iter().enumerate() {
+            if persona.name.is_empty() || persona.name.contains([',', ' ', ':']) {
+                return invalid(format!(
+                    "persona name {:?} must be non-empty without commas, spaces or colons",
+                    persona.name
+                ));
+            }
+            if self.persona[..i].iter().any(|p| p.name == persona.name) {
+                return invalid(format!("persona {} is listed twice", persona.name));
+            }
+            if persona
+                .endings
+                .values()
+                .any(|weight| !(weight.is_finite() && *weight >= 0.0))
+            {
+                return invalid(format!(
+                    "persona {}: endings weights must not be negative",
+                    persona.name
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Look up a bundled preset and check it produces valid options on its own
+fn preset_table(name: &str) -> io::Result<toml::Table> {
+    let Some((_, text)) = PRESETS.iter().find(|(preset, _)| *preset == name) else {
+        let names: Vec<&str> = PRESETS.iter().map(|(preset, _)| *preset).collect();
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown preset {} (available: {})", name, names.join(", ")),
+        ));
+    };
+    let table = parse_toml(text)?;
+    let preset: Config = toml::Value::Table(table.clone())
+        .try_into()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    preset.validate()?;
+    Ok(table)
+}
+
+fn parse_toml(text: &str) -> io::Result<toml::Table> {
+    text.parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Recursively overwrite `base` with the keys set in `overrides`
+fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(value)) => {
+                merge_tables(base, value)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
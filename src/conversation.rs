@@ -0,0 +1,155 @@
+use crate::markov::GenerateOptions;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+};
+
+// **📌 発言者**
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speaker {
+    User,
+    Bot,
+}
+
+// **📌 Turn構造体：1回分の発言**
+#[derive(Debug, Clone)]
+pub struct Turn {
+    pub speaker: Speaker,
+    pub tokens: Vec<String>,
+}
+
+// **📌 Conversation構造体：直近の会話履歴を管理**
+#[derive(Debug, Clone)]
+pub struct Conversation {
+    turns: VecDeque<Turn>,
+    max_turns: usize,
+    /// Hashes of the bot's latest replies, oldest first
+    replies: VecDeque<u64>,
+    max_replies: usize,
+}
+
+impl Conversation {
+    /// History of `max_turns` turns, remembering the last `max_replies` bot
+    /// replies so they are not repeated
+    pub fn new(max_turns: usize, max_replies: usize) -> Self {
+        Self {
+            turns: VecDeque::new(),
+            max_turns,
+            replies: VecDeque::new(),
+            max_replies,
+        }
+    }
+
+    pub fn turns(&self) -> impl Iterator<Item = &Turn> {
+        self.turns.iter()
+    }
+
+    /// Record a turn, dropping the oldest ones beyond `max_turns`
+    pub fn push(&mut self, speaker: Speaker, tokens: Vec<String>) {
+        if speaker == Speaker::Bot && self.max_replies > 0 && !tokens.is_empty() {
+            self.replies.push_back(reply_hash(&tokens));
+            while self.replies.len() > self.max_replies {
+                self.replies.pop_front();
+            }
+        }
+        if self.max_turns == 0 {
+            return;
+        }
+        self.turns.push_back(Turn { speaker, tokens });
+        while self.turns.len() > self.max_turns {
+            self.turns.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.turns.clear();
+        self.replies.clear();
+    }
+
+    /// Whether the bot gave exactly this reply among its recent ones
+    pub fn repeats(&self, reply: &str) -> bool {
+        let tokens: Vec<&str> = reply.split_whitespace().collect();
+        !tokens.is_empty() && self.replies.contains(&reply_hash(&tokens))
+    }
+
+    /// Call `generate` until its reply is not a recent one, at most `retries`
+    /// more times with the temperature raised by `heat` each time; the last
+    /// attempt when every one was a repeat
+    pub fn fresh_reply<F>(
+        &self,
+        opts: &GenerateOptions,
+        retries: usize,
+        heat: f64,
+        mut generate: F,
+    ) -> String
+    where
+        F: FnMut(&GenerateOptions) -> String,
+    {
+        let mut reply = generate(opts);
+        let mut opts = opts.clone();
+        for _ in 0..retries {
+            if !self.repeats(&reply) {
+                break;
+            }
+            opts.temperature += heat;
+            reply = generate(&opts);
+        }
+        reply
+    }
+
+    /// Boost per recent token; the newest turn gets `strength`, older ones less
+    pub fn boosts(&self, strength: f64) -> HashMap<String, f64> {
+        let mut boosts: HashMap<String, f64> = HashMap::new();
+        for (age, turn) in self.turns.iter().rev().enumerate() {
+            let boost = strength / (age + 1) as f64;
+            for token in &turn.tokens {
+                let entry = boosts.entry(token.clone()).or_insert(0.0);
+                *entry = entry.max(boost);
+            }
+        }
+        boosts
+    }
+
+    /// Pick a seed: the most salient known input token (the first of equals),
+    /// otherwise the most recent known history token
+    pub fn seed<F, S>(&self, input_tokens: &[String], is_known: F, salience: S) -> Option<String>
+    where
+        F: Fn(&str) -> bool,
+        S: Fn(&str) -> f64,
+    {
+        if let Some(token) = most_salient(input_tokens, &is_known, salience) {
+            return Some(token.clone());
+        }
+        self.turns
+            .iter()
+            .rev()
+            .flat_map(|turn| turn.tokens.iter().rev())
+            .find(|t| is_known(t))
+            .cloned()
+    }
+}
+
+/// The known token with the highest salience, the first of equals
+pub fn most_salient<F, S>(tokens: &[String], is_known: F, salience: S) -> Option<&String>
+where
+    F: Fn(&str) -> bool,
+    S: Fn(&str) -> f64,
+{
+    let mut best: Option<(&String, f64)> = None;
+    for token in tokens.iter().filter(|t| is_known(t)) {
+        let score = salience(token);
+        if best.is_none_or(|(_, top)| score > top) {
+            best = Some((token, score));
+        }
+    }
+    best.map(|(token, _)| token)
+}
+
+/// Hash of a reply's tokens, so spacing does not tell replies apart
+fn reply_hash<S: AsRef<str>>(tokens: &[S]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for token in tokens {
+        token.as_ref().hash(&mut hasher);
+    }
+    hasher.finish()
+}
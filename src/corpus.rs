@@ -0,0 +1,679 @@
+use crate::{markov::MarkovChain, topic, transcript::TranscriptEntry};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Links removed by `strip_urls`
+static URL: Lazy<Regex> = Lazy::new(|| Regex::new(r"https?://\S+").unwrap());
+
+/// `@name` and Discord-style `<@id>` mentions removed by `strip_mentions`
+static MENTION: Lazy<Regex> = Lazy::new(|| Regex::new(r"<@[!&]?\d+>|@\w+").unwrap());
+
+/// A message of a LINE talk export: time, speaker and text separated by tabs
+static LINE_MESSAGE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?:午前|午後|AM |PM )?\d{1,2}:\d{2}(?: ?[AP]M)?\t([^\t]*)\t(.*)$").unwrap()
+});
+
+/// Stickers, photos and other attachments LINE writes in place of text
+const LINE_PLACEHOLDERS: &[&str] = &[
+    "[スタンプ]",
+    "[写真]",
+    "[動画]",
+    "[ファイル]",
+    "[ボイスメッセージ]",
+    "[アルバム]",
+    "[Sticker]",
+    "[Photo]",
+    "[Video]",
+    "[File]",
+    "[Voice message]",
+    "[Album]",
+];
+
+// **📌 CorpusLimits構造体：信頼できないコーパスを読むときの制限**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CorpusLimits {
+    /// Files larger than this are refused
+    pub max_file_bytes: u64,
+    /// Longer records are treated as malformed
+    pub max_record_bytes: usize,
+    /// Abort on the first malformed record instead of skipping it
+    pub strict: bool,
+}
+
+impl Default for CorpusLimits {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: 256 * 1024 * 1024,
+            max_record_bytes: 64 * 1024,
+            strict: false,
+        }
+    }
+}
+
+// **📌 Corpus構造体：読み込んだレコードと読み飛ばしたレコード**
+#[derive(Debug, Clone, Default)]
+pub struct Corpus {
+    pub records: Vec<String>,
+    pub skipped: Vec<RecordError>,
+    /// Records left out by a cleaning profile
+    pub dropped: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordError {
+    /// 1-based line number, or message number in a JSON export
+    pub line: usize,
+    pub reason: String,
+}
+
+/// Read a plain-text corpus with one record per line, treating the file as untrusted
+pub fn read_corpus(path: &Path, limits: &CorpusLimits) -> io::Result<Corpus> {
+    read_records(path, limits, |text| Ok(text.to_string()))
+}
+
+/// Read the user inputs of a JSON Lines chat transcript as a corpus
+pub fn read_transcript_corpus(path: &Path, limits: &CorpusLimits) -> io::Result<Corpus> {
+    read_records(path, limits, |text| {
+        serde_json::from_str::<TranscriptEntry>(text)
+            .map(|entry| entry.input)
+            .map_err(|e| format!("invalid transcript entry: {}", e))
+    })
+}
+
+/// Read the messages of a LINE talk export (`[LINE] ...のトーク履歴`), dropping the
+/// header, dates, times, names and attachment placeholders. With `speaker`, only
+/// that person's messages are kept
+pub fn read_line_corpus(
+    path: &Path,
+    limits: &CorpusLimits,
+    speaker: Option<&str>,
+) -> io::Result<Corpus> {
+    // **📌 改行を含むメッセージは "..." で囲まれて次の行に続く**
+    let quoted: Cell<Option<bool>> = Cell::new(None);
+    read_records(path, limits, |text| {
+        if let Some(keep) = quoted.get() {
+            let text = match text.strip_suffix('"') {
+                Some(text) => {
+                    quoted.set(None);
+                    text
+                }
+                None => text,
+            };
+            return Ok(if keep {
+                text.to_string()
+            } else {
+                String::new()
+            });
+        }
+        let Some(message) = LINE_MESSAGE.captures(text) else {
+            // **📌 ヘッダーや日付の行、参加などのお知らせは学習しない**
+            return Ok(String::new());
+        };
+        let keep = speaker.is_none_or(|speaker| speaker == &message[1]);
+        let mut body = &message[2];
+        if let Some(rest) = body.strip_prefix('"') {
+            match rest.strip_suffix('"') {
+                Some(rest) => body = rest,
+                None => {
+                    quoted.set(Some(keep));
+                    body = rest;
+                }
+            }
+        }
+        if !keep || LINE_PLACEHOLDERS.contains(&body.trim()) || body.starts_with('☎') {
+            return Ok(String::new());
+        }
+        Ok(body.to_string())
+    })
+}
+
+#[derive(Deserialize)]
+struct DiscordExport {
+    messages: Vec<DiscordMessage>,
+}
+
+#[derive(Deserialize)]
+struct DiscordMessage {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    content: String,
+    author: DiscordAuthor,
+}
+
+#[derive(Deserialize)]
+struct DiscordAuthor {
+    name: String,
+    #[serde(default)]
+    nickname: Option<String>,
+    #[serde(rename = "isBot", default)]
+    is_bot: bool,
+}
+
+/// Read the messages of a Discord channel exported as JSON (DiscordChatExporter's
+/// format), one record per line of each message. Bots and system messages are
+/// left out; with `speaker`, only messages whose author has that name or
+/// nickname are kept
+pub fn read_discord_corpus(
+    path: &Path,
+    limits: &CorpusLimits,
+    speaker: Option<&str>,
+) -> io::Result<Corpus> {
+    if fs::metadata(path)?.len() > limits.max_file_bytes {
+        return Err(too_large(path, limits));
+    }
+    let export: DiscordExport = serde_json::from_slice(&fs::read(path)?).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: not a Discord JSON export: {}", path.display(), e),
+        )
+    })?;
+
+    let mut corpus = Corpus::default();
+    for (index, message) in export.messages.iter().enumerate() {
+        let author = &message.author;
+        let wanted = match speaker {
+            Some(speaker) => author.name == speaker || author.nickname.as_deref() == Some(speaker),
+            None => !author.is_bot,
+        };
+        if !wanted || !matches!(message.kind.as_str(), "" | "Default" | "Reply") {
+            continue;
+        }
+        match parse_record(message.content.as_bytes(), limits) {
+            Ok(content) => corpus.records.extend(
+                content
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(str::to_string),
+            ),
+            Err(reason) if limits.strict => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: message {}: {}", path.display(), index + 1, reason),
+                ));
+            }
+            Err(reason) => corpus.skipped.push(RecordError {
+                line: index + 1,
+                reason,
+            }),
+        }
+    }
+    Ok(corpus)
+}
+
+/// Read one record per line, turning each checked line into a record with `parse`
+fn read_records(
+    path: &Path,
+    limits: &CorpusLimits,
+    parse: impl Fn(&str) -> Result<String, String>,
+) -> io::Result<Corpus> {
+    let file = File::open(path)?;
+    if file.metadata()?.len() > limits.max_file_bytes {
+        return Err(too_large(path, limits));
+    }
+    // **📌 サイズが途中で増えても上限以上は読まない**
+    let mut reader = BufReader::new(file.take(limits.max_file_bytes + 1));
+    let mut corpus = Corpus::default();
+    let mut read = 0;
+    let mut buf = Vec::new();
+
+    for line in 1.. {
+        buf.clear();
+        let n = reader.read_until(b'\n', &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        read += n as u64;
+        if read > limits.max_file_bytes {
+            return Err(too_large(path, limits));
+        }
+
+        match parse_record(&buf, limits).and_then(|text| parse(&text)) {
+            Ok(record) if record.trim().is_empty() => {}
+            Ok(record) => corpus.records.push(record),
+            Err(reason) if limits.strict => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: {}", path.display(), line, reason),
+                ));
+            }
+            Err(reason) => corpus.skipped.push(RecordError { line, reason }),
+        }
+    }
+    Ok(corpus)
+}
+
+/// Check one raw line against the limits and decode it
+pub fn parse_record(bytes: &[u8], limits: &CorpusLimits) -> Result<String, String> {
+    if bytes.len() > limits.max_record_bytes {
+        return Err(format!(
+            "record is longer than {} bytes",
+            limits.max_record_bytes
+        ));
+    }
+    let text = std::str::from_utf8(bytes).map_err(|e| format!("invalid UTF-8: {}", e))?;
+    // **📌 NUL などの制御文字はバイナリファイルの印**
+    if text
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'))
+    {
+        return Err("contains control characters".to_string());
+    }
+    Ok(text.trim_end_matches(['\n', '\r']).to_string())
+}
+
+fn too_large(path: &Path, limits: &CorpusLimits) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "{} is larger than {} bytes",
+            path.display(),
+            limits.max_file_bytes
+        ),
+    )
+}
+
+// **📌 CorpusManifest構造体：corpus.toml に書いた学習の手順**
+#[derive(Debug, Clone, Deserialize)]
+pub struct CorpusManifest {
+    /// Where the model is saved instead of model.path
+    pub out: Option<PathBuf>,
+    /// Markov order instead of model.order
+    pub order: Option<usize>,
+    /// Named cleaning profiles the sources refer to
+    #[serde(default)]
+    pub cleaning: HashMap<String, Cleaning>,
+    #[serde(rename = "source")]
+    pub sources: Vec<SourceEntry>,
+}
+
+// **📌 SourceEntry構造体：学習元のファイル1つ**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SourceEntry {
+    pub path: PathBuf,
+    #[serde(rename = "type")]
+    pub kind: SourceKind,
+    /// Label recorded with the source in the model's manifest
+    pub tag: Option<String>,
+    /// Each record is learned with this count instead of 1
+    pub weight: f64,
+    /// Name of the cleaning profile applied to every record
+    pub cleaning: Option<String>,
+    /// Only learn this person's messages, for `line` and `discord-json` sources
+    pub speaker: Option<String>,
+    /// Also learn each record as the answer to the one before it, see
+    /// [`MarkovChain::learn_pair`](crate::markov::MarkovChain::learn_pair)
+    pub pairs: bool,
+    /// Remember what the source taught under its [`id`](Self::id), so
+    /// `forget --source` can remove it from the model
+    pub track: bool,
+    /// Topic every record is learned under; `#topic` lines in a text source
+    /// switch it for the lines below, see [`topic::marker`](crate::topic::marker)
+    pub topic: Option<String>,
+}
+
+impl Default for SourceEntry {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::new(),
+            kind: SourceKind::Text,
+            tag: None,
+            weight: 1.0,
+            cleaning: None,
+            speaker: None,
+            pairs: false,
+            track: false,
+            topic: None,
+        }
+    }
+}
+
+impl SourceEntry {
+    /// Name the source is tracked under: its tag, else its path
+    pub fn id(&self) -> String {
+        self.tag
+            .clone()
+            .unwrap_or_else(|| self.path.display().to_string())
+    }
+
+    /// Records with the topic each is learned under, leaving out the `#topic`
+    /// lines of a text source
+    pub fn sections<'a>(&'a self, records: &'a [String]) -> Vec<(Option<&'a str>, &'a str)> {
+        let mut topic = self.topic.as_deref();
+        let mut sections = Vec::new();
+        for record in records {
+            if self.kind == SourceKind::Text {
+                if let Some(marker) = topic::marker(record) {
+                    topic = marker.or(self.topic.as_deref());
+                    continue;
+                }
+            }
+            sections.push((topic, record.as_str()));
+        }
+        sections
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SourceKind {
+    /// Plain text with one record per line
+    #[default]
+    #[serde(alias = "plain")]
+    Text,
+    /// JSON Lines transcript written by chat.transcript; the user inputs are learned
+    Transcript,
+    /// LINE talk history exported as text
+    Line,
+    /// Discord channel exported as JSON
+    DiscordJson,
+}
+
+impl SourceKind {
+    /// Whether messages in this format have speakers to filter by
+    pub fn has_speakers(self) -> bool {
+        matches!(self, SourceKind::Line | SourceKind::DiscordJson)
+    }
+}
+
+impl FromStr for SourceKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" | "text" => Ok(SourceKind::Text),
+            "transcript" => Ok(SourceKind::Transcript),
+            "line" => Ok(SourceKind::Line),
+            "discord-json" => Ok(SourceKind::DiscordJson),
+            _ => Err(format!(
+                "unknown format {:?} (plain, line, discord-json, transcript)",
+                s
+            )),
+        }
+    }
+}
+
+// **📌 WeightedPath構造体：`file.txt:2.5` のように重みを付けたファイル**
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeightedPath {
+    pub path: PathBuf,
+    /// Count each record is learned with, 1 unless given after a colon
+    pub weight: f64,
+}
+
+impl FromStr for WeightedPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // **📌 コロンの後ろが数でなければパスの一部として扱う**
+        let Some((path, weight)) = s
+            .rsplit_once(':')
+            .and_then(|(path, weight)| Some((path, weight.parse::<f64>().ok()?)))
+        else {
+            return Ok(Self {
+                path: PathBuf::from(s),
+                weight: 1.0,
+            });
+        };
+        if !(weight.is_finite() && weight > 0.0) {
+            return Err(format!("{}: weight must be positive", path));
+        }
+        Ok(Self {
+            path: PathBuf::from(path),
+            weight,
+        })
+    }
+}
+
+/// Name of the profile `train --clean` records for the config's `[clean]` steps
+pub const CLEAN_PROFILE: &str = "--clean";
+
+// **📌 Cleaning構造体：学習前にレコードを整える設定**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Cleaning {
+    /// Remove http(s) links
+    pub strip_urls: bool,
+    /// Remove @name and Discord-style <@id> mentions
+    pub strip_mentions: bool,
+    /// Collapse runs of whitespace into one space and trim the ends
+    pub normalize_whitespace: bool,
+    /// Drop records already seen earlier in the same source, after cleaning
+    pub dedup: bool,
+    /// Records shorter than this many characters after cleaning are dropped
+    pub min_chars: usize,
+    /// Records longer than this many characters after cleaning are dropped,
+    /// e.g. pasted logs
+    pub max_chars: Option<usize>,
+    /// Records matching any of these regexes are dropped
+    pub drop: Vec<String>,
+}
+
+impl Default for Cleaning {
+    fn default() -> Self {
+        Self {
+            strip_urls: false,
+            strip_mentions: false,
+            normalize_whitespace: true,
+            dedup: false,
+            min_chars: 0,
+            max_chars: None,
+            drop: Vec::new(),
+        }
+    }
+}
+
+impl CorpusManifest {
+    /// Load a manifest; relative source and output paths are taken from its folder
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let invalid = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), message),
+            )
+        };
+        let mut manifest: Self =
+            toml::from_str(&fs::read_to_string(path)?).map_err(|e| invalid(e.to_string()))?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        manifest.out = manifest.out.map(|out| dir.join(out));
+        if manifest.order == Some(0) {
+            return Err(invalid("order must be at least 1".to_string()));
+        }
+        if manifest.sources.is_empty() {
+            return Err(invalid("no [[source]] listed".to_string()));
+        }
+        for source in &mut manifest.sources {
+            if source.path.as_os_str().is_empty() {
+                return Err(invalid("every source needs a path".to_string()));
+            }
+            source.path = dir.join(&source.path);
+            if !(source.weight.is_finite() && source.weight > 0.0) {
+                return Err(invalid(format!(
+                    "{}: weight must be positive",
+                    source.path.display()
+                )));
+            }
+            if source.speaker.is_some() && !source.kind.has_speakers() {
+                return Err(invalid(format!(
+                    "{}: speaker needs a line or discord-json source",
+                    source.path.display()
+                )));
+            }
+            if source.pairs && source.speaker.is_some() {
+                return Err(invalid(format!(
+                    "{}: pairs need the whole conversation, not one speaker",
+                    source.path.display()
+                )));
+            }
+            if let Some(name) = &source.cleaning {
+                if !manifest.cleaning.contains_key(name) {
+                    return Err(invalid(format!("unknown cleaning profile {:?}", name)));
+                }
+            }
+        }
+        for (name, cleaning) in &manifest.cleaning {
+            if let Err(e) = cleaning.validate() {
+                return Err(invalid(format!("cleaning.{}: {}", name, e)));
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Read a source and apply its cleaning profile
+    pub fn read(&self, source: &SourceEntry, limits: &CorpusLimits) -> io::Result<Corpus> {
+        let mut corpus = match source.kind {
+            SourceKind::Text => read_corpus(&source.path, limits)?,
+            SourceKind::Transcript => read_transcript_corpus(&source.path, limits)?,
+            SourceKind::Line => read_line_corpus(&source.path, limits, source.speaker.as_deref())?,
+            SourceKind::DiscordJson => {
+                read_discord_corpus(&source.path, limits, source.speaker.as_deref())?
+            }
+        };
+        if let Some(cleaning) = source
+            .cleaning
+            .as_ref()
+            .and_then(|name| self.cleaning.get(name))
+        {
+            let before = corpus.records.len();
+            corpus.records = cleaning.apply(corpus.records);
+            corpus.dropped = before - corpus.records.len();
+        }
+        Ok(corpus)
+    }
+
+    /// Read a source and learn its records into `chain` with the source's
+    /// weight, and its response pairs if it asks for them
+    pub fn learn(
+        &self,
+        source: &SourceEntry,
+        chain: &mut MarkovChain,
+        limits: &CorpusLimits,
+    ) -> io::Result<Corpus> {
+        let corpus = self.read(source, limits)?;
+        let id = source.track.then(|| source.id());
+        let sections = source.sections(&corpus.records);
+        for (topic, record) in &sections {
+            chain.learn_tagged(record, source.weight, id.as_deref(), *topic);
+        }
+        if source.pairs {
+            let records: Vec<&str> = sections.iter().map(|(_, record)| *record).collect();
+            for pair in records.windows(2) {
+                match &id {
+                    Some(id) => chain.learn_pair_from(id, pair[0], pair[1], source.weight),
+                    None => chain.learn_pair(pair[0], pair[1], source.weight),
+                }
+            }
+        }
+        Ok(corpus)
+    }
+
+    /// Read a source again and take back what [`learn`](Self::learn) learned
+    /// from it; the file must not have changed in between
+    pub fn unlearn(
+        &self,
+        source: &SourceEntry,
+        chain: &mut MarkovChain,
+        limits: &CorpusLimits,
+    ) -> io::Result<Corpus> {
+        let corpus = self.read(source, limits)?;
+        let sections = source.sections(&corpus.records);
+        let mut by_topic: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (topic, record) in &sections {
+            if let Some(topic) = topic {
+                by_topic.entry(topic).or_default().push(record);
+            }
+        }
+        for (topic, records) in &by_topic {
+            chain.unlearn_topic(topic, records, source.weight);
+        }
+        // **📌 追跡していた情報源は記録した回数をそのまま引く**
+        if chain.unlearn_source(&source.id()).is_some() {
+            return Ok(corpus);
+        }
+        let records: Vec<&str> = sections.iter().map(|(_, record)| *record).collect();
+        if source.pairs {
+            for pair in records.windows(2) {
+                chain.unlearn_pair(pair[0], pair[1], source.weight);
+            }
+        }
+        chain.unlearn(&records, source.weight);
+        Ok(corpus)
+    }
+}
+
+impl Cleaning {
+    /// Steps `train --clean` applies when the config has no `[clean]`
+    /// section: links and mentions stripped, whitespace collapsed, repeated
+    /// records and records over 500 characters dropped
+    pub fn recommended() -> Self {
+        Self {
+            strip_urls: true,
+            strip_mentions: true,
+            dedup: true,
+            max_chars: Some(500),
+            ..Self::default()
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self
+            .max_chars
+            .is_some_and(|max| max < self.min_chars.max(1))
+        {
+            return Err("max_chars must be at least min_chars and 1".to_string());
+        }
+        for pattern in &self.drop {
+            Regex::new(pattern).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Clean every record, leaving out the ones that should be dropped
+    pub fn apply(&self, records: Vec<String>) -> Vec<String> {
+        // **📌 validate で検査済みなので正規表現は必ずコンパイルできる**
+        let drop: Vec<Regex> = self
+            .drop
+            .iter()
+            .filter_map(|p| Regex::new(p).ok())
+            .collect();
+        let mut seen: HashSet<String> = HashSet::new();
+
+        records
+            .into_iter()
+            .filter(|record| !drop.iter().any(|re| re.is_match(record)))
+            .map(|record| {
+                let mut record = record;
+                if self.strip_urls {
+                    record = URL.replace_all(&record, "").into_owned();
+                }
+                if self.strip_mentions {
+                    record = MENTION.replace_all(&record, "").into_owned();
+                }
+                if self.normalize_whitespace {
+                    record = record.split_whitespace().collect::<Vec<_>>().join(" ");
+                }
+                record
+            })
+            .filter(|record| {
+                let chars = record.chars().count();
+                !record.trim().is_empty()
+                    && chars >= self.min_chars
+                    && self.max_chars.is_none_or(|max| chars <= max)
+            })
+            .filter(|record| !self.dedup || seen.insert(record.clone()))
+            .collect()
+    }
+}
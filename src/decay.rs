@@ -0,0 +1,62 @@
+use crate::markov::MarkovChain;
+use serde::Deserialize;
+
+// **📌 DecayConfig構造体：会話から学び続けるときの減衰の設定**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DecayConfig {
+    /// Learned messages after which earlier counts weigh half as much; unset disables decay
+    pub half_life: Option<f64>,
+    /// Learned messages between two decay passes
+    pub every: usize,
+}
+
+impl Default for DecayConfig {
+    fn default() -> Self {
+        Self {
+            half_life: None,
+            every: 10,
+        }
+    }
+}
+
+impl DecayConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.half_life.is_some_and(|h| h.is_nan() || h <= 0.0) {
+            return Err("half_life must be positive".to_string());
+        }
+        if self.every == 0 {
+            return Err("every must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+// **📌 Decay構造体：学習したメッセージを数えて定期的に減衰させる**
+#[derive(Debug, Clone)]
+pub struct Decay {
+    config: DecayConfig,
+    /// Messages learned since the last decay pass
+    learned: usize,
+}
+
+impl Decay {
+    pub fn new(config: &DecayConfig) -> Self {
+        Self {
+            config: config.clone(),
+            learned: 0,
+        }
+    }
+
+    /// Count one learned message, decaying `chain` once every `every` messages
+    pub fn tick(&mut self, chain: &mut MarkovChain) {
+        let Some(half_life) = self.config.half_life else {
+            return;
+        };
+        self.learned += 1;
+        if self.learned >= self.config.every {
+            chain.decay(0.5f64.powf(self.learned as f64 / half_life));
+            self.learned = 0;
+        }
+    }
+}
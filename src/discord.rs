@@ -0,0 +1,287 @@
+use crate::server;
+use serenity::{
+    async_trait,
+    model::{
+        channel::{Message, ReactionType},
+        gateway::Ready,
+        id::{ChannelId, GuildId},
+    },
+    prelude::*,
+};
+use std::{
+    collections::HashMap,
+    env, io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+use wordora_ai::{
+    config::Config,
+    decay::Decay,
+    guard::LearnGuard,
+    layered::LayeredModel,
+    reaction::{suggest_reaction, ReactionMode},
+    recent::RecentChain,
+    trigger::{Incoming, Triggers},
+};
+
+/// Name of the overlay holding what was learned in a guild
+const GUILD: &str = "guild";
+
+// **📌 GuildModel構造体：サーバーごとのモデル**
+struct GuildModel {
+    model: LayeredModel,
+    path: Option<PathBuf>,
+    /// Messages learned since the overlay was last saved
+    unsaved: usize,
+    decay: Decay,
+    guard: LearnGuard,
+}
+
+// **📌 Handler構造体：Discordのイベントを処理**
+struct Handler {
+    config: Config,
+    /// Base model and configured overlays shared by every guild
+    model: LayeredModel,
+    /// Shared with the auto-save task and the Ctrl-C handler
+    guilds: Arc<Mutex<HashMap<GuildId, GuildModel>>>,
+    last_reply: Mutex<HashMap<ChannelId, Instant>>,
+    /// Latest messages of each channel, when `[recent]` is enabled
+    recent: Mutex<HashMap<ChannelId, RecentChain>>,
+    triggers: Triggers,
+}
+
+impl Handler {
+    /// Stack the guild's saved overlay, if any, on the shared model
+    fn load_guild(&self, guild_id: GuildId) -> GuildModel {
+        let path = self
+            .config
+            .discord
+            .guild_models
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.bin", guild_id)));
+        // **📌 読み込めないモデルは空のレイヤーから始め、元のファイルは上書きしない**
+        let saved = path
+            .as_deref()
+            .map(|path| server::load_overlay(path, "guild model"));
+        let (chain, writable) = saved.map_or((None, true), |saved| (saved.chain, saved.writable));
+        let mut model = self.model.clone();
+        model.push_overlay(GUILD, chain);
+        GuildModel {
+            model,
+            path: path.filter(|_| writable),
+            unsaved: 0,
+            decay: Decay::new(&self.config.decay),
+            guard: LearnGuard::new(&self.config.guard),
+        }
+    }
+
+    /// Whether the channel's cooldown has passed; starts a new one if so
+    fn allow(&self, channel_id: ChannelId) -> bool {
+        let cooldown = Duration::from_secs_f64(self.config.discord.rate_limit_seconds);
+        let mut last_reply = self.last_reply.lock().unwrap();
+        let now = Instant::now();
+        if last_reply
+            .get(&channel_id)
+            .is_some_and(|last| now.duration_since(*last) < cooldown)
+        {
+            return false;
+        }
+        last_reply.insert(channel_id, now);
+        true
+    }
+
+    fn learn(&self, guild: &mut GuildModel, author: &str, text: &str) {
+        let chain = guild.model.overlay_mut(GUILD).expect("guild overlay");
+        if self.config.discord.track_authors {
+            chain.learn_from(author, text, 1.0);
+        } else {
+            chain.learn(text);
+        }
+        guild.decay.tick(chain);
+        if let Some(max_vocab) = self.config.model.max_vocab {
+            chain.evict(max_vocab);
+        }
+        guild.unsaved += 1;
+        if guild.unsaved >= self.config.discord.save_every {
+            guild.save();
+        }
+    }
+}
+
+impl GuildModel {
+    /// Save a copy of the guild's overlay in the background if it has a path
+    /// and unsaved messages
+    fn save(&mut self) -> Option<JoinHandle<()>> {
+        if self.unsaved == 0 {
+            return None;
+        }
+        let path = self.path.clone()?;
+        let chain = self.model.overlay(GUILD)?.clone();
+        self.unsaved = 0;
+        Some(server::save_overlay(path, chain, "guild model"))
+    }
+}
+
+/// Save every guild with unsaved messages, holding the guild map only while
+/// their overlays are copied
+async fn save_guilds(guilds: &Mutex<HashMap<GuildId, GuildModel>>) {
+    let saves: Vec<JoinHandle<()>> = guilds
+        .lock()
+        .unwrap()
+        .values_mut()
+        .filter_map(GuildModel::save)
+        .collect();
+    for save in saves {
+        if let Err(e) = save.await {
+            eprintln!("⚠️ Failed to save guild model: {}", e);
+        }
+    }
+}
+
+#[async_trait]
+impl EventHandler for Handler {
+    async fn message(&self, ctx: Context, msg: Message) {
+        if msg.author.bot {
+            return;
+        }
+        let Some(guild_id) = msg.guild_id else {
+            return;
+        };
+        let me = ctx.cache.current_user().id;
+        let incoming = Incoming {
+            text: &msg.content,
+            mentioned: msg.mentions_user_id(me),
+            reply_to_bot: msg
+                .referenced_message
+                .as_ref()
+                .is_some_and(|replied| replied.author.id == me),
+        };
+        let triggered = self.triggers.check(&incoming).is_some();
+
+        // **📌 メンションを取り除いた本文**
+        let text: Vec<&str> = msg
+            .content
+            .split_whitespace()
+            .filter(|word| !word.starts_with("<@"))
+            .collect();
+        let text = text.join(" ");
+
+        let (reaction, reply) = {
+            let mut guilds = self.guilds.lock().unwrap();
+            let guild = guilds
+                .entry(guild_id)
+                .or_insert_with(|| self.load_guild(guild_id));
+            if self.config.discord.learn && !guild.model.is_read_only() && !text.is_empty() {
+                let tokenizer = guild.model.base().tokenizer();
+                match guild
+                    .guard
+                    .check(&msg.author.id.to_string(), &text, tokenizer)
+                {
+                    Ok(()) => self.learn(guild, &msg.author.id.to_string(), &text),
+                    Err(reason) => {
+                        eprintln!("🛡️ not learning from {}: {}", msg.author.name, reason)
+                    }
+                }
+            }
+            if self.config.recent.enabled() && !text.is_empty() {
+                let base = guild.model.base();
+                self.recent
+                    .lock()
+                    .unwrap()
+                    .entry(msg.channel_id)
+                    .or_insert_with(|| {
+                        RecentChain::new(
+                            &self.config.recent,
+                            base.order(),
+                            base.tokenizer().clone(),
+                        )
+                    })
+                    .push(&text);
+            }
+            if !(triggered && self.allow(msg.channel_id)) {
+                return;
+            }
+
+            // **📌 リアクションだけで済ませるか、返信もするか**
+            let mode = self.config.discord.reaction;
+            let reaction = (mode != ReactionMode::Off)
+                .then(|| suggest_reaction(&guild.model, &text))
+                .flatten();
+            let recent = self.recent.lock().unwrap();
+            let reply = (mode != ReactionMode::Instead || reaction.is_none()).then(|| {
+                let recent = recent.get(&msg.channel_id);
+                server::reply(&self.config, &guild.model, recent, &text)
+            });
+            (reaction, reply)
+        };
+
+        if let Some(emoji) = reaction {
+            if let Err(e) = msg.react(&ctx, ReactionType::Unicode(emoji)).await {
+                eprintln!("⚠️ Failed to add reaction: {}", e);
+            }
+        }
+        if let Some(reply) = reply.filter(|reply| !reply.is_empty()) {
+            // **📌 返信の長さに合わせて入力中の表示を出してから送る**
+            if let Some(delay) = self.config.typing.delay_for(&reply) {
+                let _typing = msg.channel_id.start_typing(&ctx.http);
+                tokio::time::sleep(delay).await;
+            }
+            if let Err(e) = msg.reply(&ctx, reply).await {
+                eprintln!("⚠️ Failed to send reply: {}", e);
+            }
+        }
+    }
+
+    async fn ready(&self, _: Context, ready: Ready) {
+        println!("🤖 connected to Discord as {}", ready.user.name);
+    }
+}
+
+// **🤖 Discordでチャット**
+pub async fn run(config: &Config, model: LayeredModel) -> io::Result<()> {
+    let token = env::var(&config.discord.token_env).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("set {} to the bot token", config.discord.token_env),
+        )
+    })?;
+    let guilds = Arc::new(Mutex::new(HashMap::new()));
+    let handler = Handler {
+        config: config.clone(),
+        model,
+        guilds: guilds.clone(),
+        last_reply: Mutex::new(HashMap::new()),
+        recent: Mutex::new(HashMap::new()),
+        triggers: Triggers::new(&config.discord.trigger)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+    };
+
+    let intents = GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
+    let mut client = Client::builder(&token, intents)
+        .event_handler(handler)
+        .await
+        .map_err(io::Error::other)?;
+
+    // **📌 設定した間隔で未保存のサーバーのモデルを保存**
+    if let Some(seconds) = config.model.autosave_seconds {
+        let guilds = guilds.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs_f64(seconds));
+            loop {
+                interval.tick().await;
+                save_guilds(&guilds).await;
+            }
+        });
+    }
+    // **📌 Ctrl-C で止めるときも学習した分を保存してから終了**
+    tokio::select! {
+        result = client.start() => result.map_err(io::Error::other),
+        _ = tokio::signal::ctrl_c() => {
+            println!("🛑 interrupted, saving guild models");
+            save_guilds(&guilds).await;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{
+    markov::{GenerateOptions, MarkovChain},
+    tokenizer::TokenType,
+};
+use rand::seq::IndexedRandom;
+
+// **📌 DuelTurn構造体：2つのモデルの会話の1回分**
+#[derive(Debug, Clone)]
+pub struct DuelTurn {
+    /// 0 for the first model, 1 for the second
+    pub speaker: usize,
+    pub tokens: Vec<String>,
+}
+
+// **📌 DuelSummary構造体：会話の出来を測る簡単な数字**
+#[derive(Debug, Clone, Copy)]
+pub struct DuelSummary {
+    pub turns: usize,
+    /// Turns that produced nothing
+    pub empty: usize,
+    pub average_tokens: f64,
+    /// Share of turns repeating an earlier turn word for word
+    pub repeated_share: f64,
+}
+
+/// Let two models talk for `turns` turns, the first one opening with `opener`
+/// (or its fallback word). Each reply is seeded by a random token of the
+/// previous one that the answering model knows
+pub fn duel(
+    models: [&MarkovChain; 2],
+    opener: Option<&str>,
+    turns: usize,
+    opts: &GenerateOptions,
+) -> Vec<DuelTurn> {
+    let mut rng = rand::rng();
+    let mut dialogue: Vec<DuelTurn> = Vec::new();
+    for turn in 0..turns {
+        let speaker = turn % 2;
+        let model = models[speaker];
+
+        // **📌 相手の返事の中から知っている単語を選んで続ける**
+        let seed = match dialogue.last() {
+            Some(previous) => {
+                let known: Vec<&String> = previous
+                    .tokens
+                    .iter()
+                    .filter(|token| !TokenType::of(token).is_punctuation())
+                    .filter(|token| model.contains(token))
+                    .collect();
+                known
+                    .choose(&mut rng)
+                    .map_or(opts.fallback_word.clone(), |token| token.to_string())
+            }
+            None => opener.unwrap_or(&opts.fallback_word).to_string(),
+        };
+        let reply = model.generate(&seed, opts);
+        dialogue.push(DuelTurn {
+            speaker,
+            tokens: reply.split_whitespace().map(str::to_string).collect(),
+        });
+    }
+    dialogue
+}
+
+/// Summarize a dialogue written by [`duel`]
+pub fn summarize(dialogue: &[DuelTurn]) -> DuelSummary {
+    let turns = dialogue.len();
+    let empty = dialogue
+        .iter()
+        .filter(|turn| turn.tokens.is_empty())
+        .count();
+    let tokens: usize = dialogue.iter().map(|turn| turn.tokens.len()).sum();
+    let repeated = dialogue
+        .iter()
+        .enumerate()
+        .filter(|(i, turn)| {
+            !turn.tokens.is_empty() && dialogue[..*i].iter().any(|t| t.tokens == turn.tokens)
+        })
+        .count();
+    DuelSummary {
+        turns,
+        empty,
+        average_tokens: tokens as f64 / turns.max(1) as f64,
+        repeated_share: repeated as f64 / turns.max(1) as f64,
+    }
+}
@@ -0,0 +1,154 @@
+use encoding_rs::{EncoderResult, SHIFT_JIS};
+use serde::Deserialize;
+use std::{borrow::Cow, str::FromStr};
+
+// **📌 TextEncoding：標準入出力の文字コード**
+/// Encoding of the text read from stdin and written to stdout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum TextEncoding {
+    /// Read UTF-8, or Shift_JIS when a line is not valid UTF-8; write UTF-8
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "utf-8", alias = "utf8")]
+    Utf8,
+    /// Shift_JIS as Windows writes it (code page 932, CP932), which covers
+    /// plain Shift_JIS
+    #[serde(rename = "shift_jis", alias = "cp932", alias = "sjis")]
+    ShiftJis,
+}
+
+impl TextEncoding {
+    /// Text of one line of input, without a byte order mark
+    pub fn decode(self, bytes: &[u8]) -> String {
+        let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+        match self {
+            TextEncoding::Auto => match std::str::from_utf8(bytes) {
+                Ok(text) => text.to_string(),
+                Err(_) => SHIFT_JIS.decode_without_bom_handling(bytes).0.into_owned(),
+            },
+            TextEncoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            TextEncoding::ShiftJis => SHIFT_JIS.decode_without_bom_handling(bytes).0.into_owned(),
+        }
+    }
+
+    /// Bytes of `text` in this encoding; characters Shift_JIS cannot hold,
+    /// such as emoji, become `?`
+    pub fn encode(self, text: &str) -> Cow<'_, [u8]> {
+        if self != TextEncoding::ShiftJis {
+            return Cow::Borrowed(text.as_bytes());
+        }
+        let mut encoder = SHIFT_JIS.new_encoder();
+        let mut out = Vec::with_capacity(text.len());
+        let mut rest = text;
+        loop {
+            let (result, read) =
+                encoder.encode_from_utf8_to_vec_without_replacement(rest, &mut out, true);
+            rest = &rest[read..];
+            match result {
+                EncoderResult::InputEmpty => break,
+                EncoderResult::OutputFull => out.reserve(rest.len() * 2 + 4),
+                EncoderResult::Unmappable(_) => out.push(b'?'),
+            }
+        }
+        Cow::Owned(out)
+    }
+
+    /// The encoding to write stdout in. `is_terminal` tells whether stdout is
+    /// a terminal: a Windows console is always written in UTF-8, which Rust
+    /// turns into the console's own wide characters
+    pub fn for_output(self, is_terminal: bool) -> TextEncoding {
+        if cfg!(windows) && is_terminal {
+            return TextEncoding::Utf8;
+        }
+        match self {
+            TextEncoding::Auto => TextEncoding::Utf8,
+            encoding => encoding,
+        }
+    }
+}
+
+impl FromStr for TextEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(TextEncoding::Auto),
+            "utf-8" | "utf8" => Ok(TextEncoding::Utf8),
+            "shift_jis" | "shift-jis" | "sjis" | "cp932" => Ok(TextEncoding::ShiftJis),
+            _ => Err(format!(
+                "unknown encoding {:?} (auto, utf-8, shift_jis, cp932)",
+                s
+            )),
+        }
+    }
+}
+
+// **📌 Utf8Console構造体：コンソールの文字コードを戻すためのガード**
+/// Code pages the Windows console had before [`enable_utf8_console`]
+/// switched it, put back when dropped
+pub struct Utf8Console {
+    /// Input and output code pages, `None` when nothing was switched
+    previous: Option<(u32, u32)>,
+}
+
+impl Drop for Utf8Console {
+    fn drop(&mut self) {
+        if let Some((input, output)) = self.previous {
+            console::restore(input, output);
+        }
+    }
+}
+
+/// Switch the Windows console to UTF-8 (code page 65001) for input and
+/// output, so Japanese typed or printed is not garbled by a legacy code page.
+/// The previous code pages come back when the returned guard is dropped
+pub fn enable_utf8_console() -> Utf8Console {
+    Utf8Console {
+        previous: console::enable_utf8(),
+    }
+}
+
+#[cfg(windows)]
+mod console {
+    use windows_sys::Win32::System::Console::{
+        GetConsoleCP, GetConsoleOutputCP, SetConsoleCP, SetConsoleOutputCP,
+    };
+
+    const CP_UTF8: u32 = 65001;
+
+    /// Switch both code pages to UTF-8, returning the ones replaced; `None`
+    /// without a console or when it refused
+    pub fn enable_utf8() -> Option<(u32, u32)> {
+        // SAFETY: the calls take plain integers and only read or change the
+        // console attached to this process, failing with 0 when there is none
+        unsafe {
+            let previous = (GetConsoleCP(), GetConsoleOutputCP());
+            if previous.0 == 0 || previous.1 == 0 {
+                return None;
+            }
+            if SetConsoleCP(CP_UTF8) == 0 || SetConsoleOutputCP(CP_UTF8) == 0 {
+                restore(previous.0, previous.1);
+                return None;
+            }
+            Some(previous)
+        }
+    }
+
+    pub fn restore(input: u32, output: u32) {
+        // SAFETY: as in `enable_utf8`
+        unsafe {
+            SetConsoleCP(input);
+            SetConsoleOutputCP(output);
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod console {
+    pub fn enable_utf8() -> Option<(u32, u32)> {
+        None
+    }
+
+    pub fn restore(_input: u32, _output: u32) {}
+}
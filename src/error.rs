@@ -0,0 +1,59 @@
+use std::{fmt, io};
+
+/// Result of the library's fallible operations
+pub type Result<T> = std::result::Result<T, WordoraError>;
+
+// **📌 WordoraError：ライブラリのエラー**
+#[derive(Debug)]
+pub enum WordoraError {
+    /// Reading or writing a file failed
+    Io(io::Error),
+    /// The model has not learned anything yet
+    EmptyModel,
+    /// No known token is close to the seed
+    UnknownSeed(String),
+    /// A model file is corrupt, truncated or not a model at all
+    InvalidModelFile(String),
+    /// The tokenizer config cannot be used, e.g. its pattern does not compile
+    Tokenizer(String),
+}
+
+impl fmt::Display for WordoraError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WordoraError::Io(e) => write!(f, "{}", e),
+            WordoraError::EmptyModel => f.write_str("the model is empty"),
+            WordoraError::UnknownSeed(seed) => write!(f, "no known token near {:?}", seed),
+            WordoraError::InvalidModelFile(reason) => write!(f, "invalid model file: {}", reason),
+            WordoraError::Tokenizer(reason) => write!(f, "tokenizer: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for WordoraError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WordoraError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for WordoraError {
+    fn from(e: io::Error) -> Self {
+        WordoraError::Io(e)
+    }
+}
+
+/// Lets code that returns `io::Result` keep using `?` on library calls
+impl From<WordoraError> for io::Error {
+    fn from(e: WordoraError) -> Self {
+        let kind = match e {
+            WordoraError::Io(e) => return e,
+            WordoraError::EmptyModel | WordoraError::UnknownSeed(_) => io::ErrorKind::NotFound,
+            WordoraError::InvalidModelFile(_) => io::ErrorKind::InvalidData,
+            WordoraError::Tokenizer(_) => io::ErrorKind::InvalidInput,
+        };
+        io::Error::new(kind, e)
+    }
+}
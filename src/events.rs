@@ -0,0 +1,52 @@
+use std::{path::Path, time::Duration};
+
+// **📌 Event列挙型：フロントエンドから通知される出来事**
+#[derive(Debug, Clone, Copy)]
+pub enum Event<'a> {
+    /// A user message arrived
+    MessageReceived { text: &'a str },
+    /// A reply was generated for `input`
+    ResponseGenerated {
+        input: &'a str,
+        response: &'a str,
+        /// Time spent generating, not counting output
+        latency: Duration,
+        /// No seed was known, so the reply started from the fallback word
+        fallback: bool,
+    },
+    /// A model file was written
+    ModelSaved { path: &'a Path },
+    /// A changed model file was loaded, or the model retrained from a changed corpus
+    ModelReloaded { path: &'a Path },
+    /// Text was learned into the named layer
+    LearnApplied { layer: &'a str, text: &'a str },
+}
+
+type Subscriber = Box<dyn Fn(&Event) + Send + Sync>;
+
+// **📌 EventBus構造体：イベントを購読者に配る**
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Vec<Subscriber>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `f` for every event published from now on
+    pub fn subscribe<F>(&mut self, f: F)
+    where
+        F: Fn(&Event) + Send + Sync + 'static,
+    {
+        self.subscribers.push(Box::new(f));
+    }
+
+    /// Deliver the event to every subscriber, in subscription order
+    pub fn publish(&self, event: Event) {
+        for subscriber in &self.subscribers {
+            subscriber(&event);
+        }
+    }
+}
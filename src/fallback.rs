@@ -0,0 +1,79 @@
+use rand::seq::IndexedRandom;
+use serde::Deserialize;
+
+/// Called when the chain cannot produce an acceptable reply, e.g. because
+/// nothing in the input is known to the model
+pub trait FallbackResponder: Send + Sync {
+    /// Reply to `input`; `None` means saying nothing
+    fn respond(&self, input: &str) -> Option<String>;
+}
+
+// **📌 Canned：決まった返事から1つ選ぶ**
+pub struct Canned {
+    pub replies: Vec<String>,
+}
+
+impl FallbackResponder for Canned {
+    fn respond(&self, _input: &str) -> Option<String> {
+        self.replies.choose(&mut rand::rng()).cloned()
+    }
+}
+
+// **📌 EchoQuestion：入力をそのまま聞き返す**
+pub struct EchoQuestion;
+
+impl FallbackResponder for EchoQuestion {
+    fn respond(&self, input: &str) -> Option<String> {
+        let input = input
+            .trim()
+            .trim_end_matches(['?', '？', '。', '.', '!', '！']);
+        if input.is_empty() {
+            return None;
+        }
+        Some(format!("「{}」って？", input))
+    }
+}
+
+// **📌 Silence：何も返さない**
+pub struct Silence;
+
+impl FallbackResponder for Silence {
+    fn respond(&self, _input: &str) -> Option<String> {
+        None
+    }
+}
+
+// **📌 フォールバックの種類**
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FallbackKind {
+    /// Generate from `generation.fallback_word` as usual
+    #[default]
+    Chain,
+    Canned,
+    Echo,
+    Silence,
+}
+
+// **📌 フォールバックの設定**
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FallbackConfig {
+    pub responder: FallbackKind,
+    /// Replies used by the canned responder
+    pub replies: Vec<String>,
+}
+
+impl FallbackConfig {
+    /// The configured responder; `None` keeps generating from the fallback word
+    pub fn build(&self) -> Option<Box<dyn FallbackResponder>> {
+        match self.responder {
+            FallbackKind::Chain => None,
+            FallbackKind::Canned => Some(Box::new(Canned {
+                replies: self.replies.clone(),
+            })),
+            FallbackKind::Echo => Some(Box::new(EchoQuestion)),
+            FallbackKind::Silence => Some(Box::new(Silence)),
+        }
+    }
+}
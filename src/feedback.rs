@@ -0,0 +1,47 @@
+use serde::Deserialize;
+
+// **📌 FeedbackConfig構造体：評価で遷移の回数をどれだけ変えるか**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FeedbackConfig {
+    /// Factor applied to every transition of a reply rated good
+    pub good: f64,
+    /// Factor applied to every transition of a reply rated bad
+    pub bad: f64,
+}
+
+impl Default for FeedbackConfig {
+    fn default() -> Self {
+        Self {
+            good: 1.25,
+            bad: 0.5,
+        }
+    }
+}
+
+impl FeedbackConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.good.is_finite() && self.good >= 1.0) {
+            return Err("good must be at least 1".to_string());
+        }
+        if !(self.bad > 0.0 && self.bad <= 1.0) {
+            return Err("bad must be above 0 and at most 1".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn factor(&self, rating: Rating) -> f64 {
+        match rating {
+            Rating::Good => self.good,
+            Rating::Bad => self.bad,
+        }
+    }
+}
+
+// **📌 Rating：返事への評価**
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rating {
+    Good,
+    Bad,
+}
@@ -0,0 +1,105 @@
+use crate::{
+    markov::{GenerateOptions, MarkovChain},
+    postprocess::repair_spacing,
+    tokenizer::TokenizerConfig,
+};
+use std::{
+    ffi::{c_char, c_int, CStr, CString},
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr,
+};
+
+// **📌 WordoraChain構造体：C から使うマルコフ連鎖**
+/// Opaque handle created by `wordora_new` and released by `wordora_free`
+pub struct WordoraChain {
+    chain: MarkovChain,
+    opts: GenerateOptions,
+}
+
+/// Text behind a C string, `None` for a null pointer or invalid UTF-8
+unsafe fn utf8<'a>(ptr: *const c_char) -> Option<&'a str> {
+    if ptr.is_null() {
+        return None;
+    }
+    CStr::from_ptr(ptr).to_str().ok()
+}
+
+/// New empty chain of the given order, null if it could not be created
+#[no_mangle]
+pub extern "C" fn wordora_new(order: usize) -> *mut WordoraChain {
+    catch_unwind(|| {
+        Box::into_raw(Box::new(WordoraChain {
+            chain: MarkovChain::new(order, TokenizerConfig::default()),
+            opts: GenerateOptions::default(),
+        }))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Learn a UTF-8 sentence; 0 on success, -1 for a null pointer, invalid UTF-8
+/// or a panic inside the library
+///
+/// # Safety
+/// `chain` must come from `wordora_new` and `text` must be a NUL-terminated
+/// string, or either may be null
+#[no_mangle]
+pub unsafe extern "C" fn wordora_learn(chain: *mut WordoraChain, text: *const c_char) -> c_int {
+    let (Some(chain), Some(text)) = (chain.as_mut(), utf8(text)) else {
+        return -1;
+    };
+    match catch_unwind(AssertUnwindSafe(|| chain.chain.learn(text))) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Generate up to `len` tokens after `seed`, joined for display; null for a
+/// null pointer, invalid UTF-8 or a panic. The string is owned by the caller
+/// and released with `wordora_string_free`
+///
+/// # Safety
+/// `chain` must come from `wordora_new` and `seed` must be a NUL-terminated
+/// string, or either may be null
+#[no_mangle]
+pub unsafe extern "C" fn wordora_generate(
+    chain: *const WordoraChain,
+    seed: *const c_char,
+    len: usize,
+) -> *mut c_char {
+    let (Some(chain), Some(seed)) = (chain.as_ref(), utf8(seed)) else {
+        return ptr::null_mut();
+    };
+    catch_unwind(AssertUnwindSafe(|| {
+        let opts = GenerateOptions {
+            length: len,
+            ..chain.opts.clone()
+        };
+        let reply = chain.chain.generate(seed, &opts);
+        let reply = repair_spacing(&reply.split_whitespace().collect::<Vec<_>>());
+        // **📌 C の文字列は NUL を含めないので取り除く**
+        CString::new(reply.replace('\0', "")).map_or(ptr::null_mut(), CString::into_raw)
+    }))
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Release a string returned by `wordora_generate`
+///
+/// # Safety
+/// `text` must come from `wordora_generate` and not be freed twice, or be null
+#[no_mangle]
+pub unsafe extern "C" fn wordora_string_free(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+/// Release a chain created by `wordora_new`
+///
+/// # Safety
+/// `chain` must come from `wordora_new` and not be freed twice, or be null
+#[no_mangle]
+pub unsafe extern "C" fn wordora_free(chain: *mut WordoraChain) {
+    if !chain.is_null() {
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(chain))));
+    }
+}
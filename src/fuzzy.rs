@@ -0,0 +1,55 @@
+use std::{collections::BTreeSet, ops::Bound};
+
+/// Known words starting with `prefix`, in sorted order
+pub fn prefix_matches<'a>(
+    index: &'a BTreeSet<String>,
+    prefix: &'a str,
+) -> impl Iterator<Item = &'a str> {
+    index
+        .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+        .map(String::as_str)
+        .take_while(move |word| word.starts_with(prefix))
+}
+
+/// Known words within `max_distance` edits of `word`, closest first
+pub fn near_matches<'a>(
+    index: &'a BTreeSet<String>,
+    word: &str,
+    max_distance: usize,
+) -> Vec<(&'a str, usize)> {
+    let length = word.chars().count();
+    let mut matches: Vec<(&str, usize)> = index
+        .iter()
+        .filter(|candidate| candidate.chars().count().abs_diff(length) <= max_distance)
+        .map(|candidate| (candidate.as_str(), edit_distance(word, candidate)))
+        .filter(|(_, distance)| *distance <= max_distance)
+        .collect();
+    matches.sort_by_key(|(_, distance)| *distance);
+    matches
+}
+
+/// Edits allowed for a word of this many characters
+pub fn max_distance(length: usize) -> usize {
+    match length {
+        0..=1 => 0,
+        2..=4 => 1,
+        _ => 2,
+    }
+}
+
+/// Levenshtein distance counted in characters
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
@@ -0,0 +1,155 @@
+use crate::tokenizer::TokenizerConfig;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// Messages shorter than this are never treated as repetitive
+const MIN_REPEAT_CHARS: usize = 8;
+
+// **📌 GuardConfig構造体：ボットが学習を断る条件**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GuardConfig {
+    /// Refuse messages whose most common character makes up more than this share
+    pub max_repeat_share: Option<f64>,
+    /// Refuse messages with a token longer than this many characters
+    pub max_token_chars: Option<usize>,
+    /// Refuse a user's message once they sent more than this share of the window
+    pub max_user_share: Option<f64>,
+    /// Seconds of learned messages the user share is measured over
+    pub window_seconds: f64,
+    /// Learned messages the window must hold before the user share applies
+    pub min_window_messages: usize,
+}
+
+impl Default for GuardConfig {
+    fn default() -> Self {
+        Self {
+            max_repeat_share: Some(0.5),
+            max_token_chars: Some(40),
+            max_user_share: Some(0.5),
+            window_seconds: 300.0,
+            min_window_messages: 10,
+        }
+    }
+}
+
+impl GuardConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, share) in [
+            ("max_repeat_share", self.max_repeat_share),
+            ("max_user_share", self.max_user_share),
+        ] {
+            if share.is_some_and(|s| !(s > 0.0 && s <= 1.0)) {
+                return Err(format!("{} must be above 0 and at most 1", name));
+            }
+        }
+        if self.max_token_chars == Some(0) {
+            return Err("max_token_chars must be at least 1".to_string());
+        }
+        if !(self.window_seconds.is_finite() && self.window_seconds > 0.0) {
+            return Err("window_seconds must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+// **📌 Rejection：学習を断った理由**
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// Mostly the same character over and over
+    Repetitive,
+    /// A single token is unreasonably long
+    LongToken,
+    /// The user sent most of the recent messages
+    Flooding,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Rejection::Repetitive => "mostly repeated characters",
+            Rejection::LongToken => "a token is too long",
+            Rejection::Flooding => "too many messages from one user",
+        })
+    }
+}
+
+// **📌 LearnGuard構造体：荒らしのような入力から学習しない**
+#[derive(Debug, Clone)]
+pub struct LearnGuard {
+    config: GuardConfig,
+    /// Authors of the messages learned in the window, oldest first
+    recent: VecDeque<(Instant, String)>,
+}
+
+impl LearnGuard {
+    pub fn new(config: &GuardConfig) -> Self {
+        Self {
+            config: config.clone(),
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Check a message of `user` before learning it; accepted messages count
+    /// towards the user's share of the window
+    pub fn check(
+        &mut self,
+        user: &str,
+        text: &str,
+        tokenizer: &TokenizerConfig,
+    ) -> Result<(), Rejection> {
+        if self
+            .config
+            .max_repeat_share
+            .is_some_and(|share| repeat_share(text) > share)
+        {
+            return Err(Rejection::Repetitive);
+        }
+        if let Some(max_chars) = self.config.max_token_chars {
+            let tokens = tokenizer.tokenize(text);
+            if tokens.iter().any(|token| token.chars().count() > max_chars) {
+                return Err(Rejection::LongToken);
+            }
+        }
+
+        // **📌 窓から古いメッセージを外してから割合を見る**
+        let window = Duration::from_secs_f64(self.config.window_seconds);
+        while self
+            .recent
+            .front()
+            .is_some_and(|(at, _)| at.elapsed() > window)
+        {
+            self.recent.pop_front();
+        }
+        if let Some(max_share) = self.config.max_user_share {
+            if self.recent.len() >= self.config.min_window_messages {
+                let sent = self.recent.iter().filter(|(_, u)| u == user).count();
+                if (sent + 1) as f64 / (self.recent.len() + 1) as f64 > max_share {
+                    return Err(Rejection::Flooding);
+                }
+            }
+        }
+        self.recent.push_back((Instant::now(), user.to_string()));
+        Ok(())
+    }
+}
+
+/// Share of the most common non-space character in `text`
+fn repeat_share(text: &str) -> f64 {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        *counts.entry(c).or_insert(0) += 1;
+    }
+    let total: usize = counts.values().sum();
+    if total < MIN_REPEAT_CHARS {
+        return 0.0;
+    }
+    counts
+        .values()
+        .max()
+        .map_or(0.0, |&max| max as f64 / total as f64)
+}
@@ -0,0 +1,199 @@
+use crate::bridge::{self, addressed, Bridge};
+use std::{env, io, sync::Arc};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use tokio_rustls::{
+    rustls::{crypto::ring, pki_types::ServerName, ClientConfig, RootCertStore},
+    TlsConnector,
+};
+use wordora_ai::{config::Config, layered::LayeredModel, trigger::Incoming};
+
+/// Bytes of a reply sent in one message; IRC lines are at most 512 bytes
+/// including the command and the prefix the server adds
+const MAX_REPLY_BYTES: usize = 400;
+
+// **📌 Line構造体：サーバーから届いた1行**
+struct Line<'a> {
+    /// Nick of the sender, when the line has a prefix
+    nick: Option<&'a str>,
+    command: &'a str,
+    params: Vec<&'a str>,
+}
+
+impl<'a> Line<'a> {
+    /// Split `[@tags] [:prefix] COMMAND params... [:trailing]`
+    fn parse(line: &'a str) -> Option<Self> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+        if rest.starts_with('@') {
+            rest = rest.split_once(' ')?.1;
+        }
+        let mut nick = None;
+        if let Some(prefixed) = rest.strip_prefix(':') {
+            let (prefix, after) = prefixed.split_once(' ')?;
+            nick = prefix.split('!').next();
+            rest = after;
+        }
+        let (rest, trailing) = match rest.split_once(" :") {
+            Some((rest, trailing)) => (rest, Some(trailing)),
+            None => (rest, None),
+        };
+        let mut words = rest.split_whitespace();
+        let command = words.next()?;
+        let mut params: Vec<&str> = words.collect();
+        params.extend(trailing);
+        Some(Self {
+            nick,
+            command,
+            params,
+        })
+    }
+}
+
+async fn send<W: AsyncWrite + Unpin>(writer: &mut W, line: &str) -> io::Result<()> {
+    writer.write_all(line.as_bytes()).await?;
+    writer.write_all(b"\r\n").await?;
+    writer.flush().await
+}
+
+/// The reply on one line, cut at a character boundary to fit a message
+fn one_line(reply: &str) -> String {
+    let mut line: String = reply.split_whitespace().collect::<Vec<_>>().join(" ");
+    if line.len() > MAX_REPLY_BYTES {
+        let mut end = MAX_REPLY_BYTES;
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        line.truncate(end);
+    }
+    line
+}
+
+/// TLS connection to `host` over `stream`, checked against the bundled roots
+async fn connect_tls(
+    host: &str,
+    stream: TcpStream,
+) -> io::Result<tokio_rustls::client::TlsStream<TcpStream>> {
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name = ServerName::try_from(host.to_string())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    TlsConnector::from(Arc::new(config))
+        .connect(name, stream)
+        .await
+}
+
+// **🤖 IRCのチャンネルでチャット**
+pub async fn run(config: &Config, model: LayeredModel) -> io::Result<()> {
+    let irc = &config.bridge.irc;
+    let mut bridge = Bridge::new(config, model)?;
+    let stream = TcpStream::connect(&irc.server).await?;
+    let result = if irc.tls {
+        let host = irc
+            .server
+            .rsplit_once(':')
+            .map_or(&*irc.server, |(host, _)| host);
+        let stream = connect_tls(host, stream).await?;
+        session(&mut bridge, stream).await
+    } else {
+        session(&mut bridge, stream).await
+    };
+    bridge.save();
+    result
+}
+
+/// Register, join the channels and answer until the server closes the
+/// connection or Ctrl-C is pressed
+async fn session<S: AsyncRead + AsyncWrite>(bridge: &mut Bridge, stream: S) -> io::Result<()> {
+    let irc = bridge.config().bridge.irc.clone();
+    let typing = bridge.config().typing.clone();
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    if let Ok(password) = env::var(&irc.password_env) {
+        send(&mut writer, &format!("PASS {}", password)).await?;
+    }
+    let mut nick = irc.nick.clone();
+    send(&mut writer, &format!("NICK {}", nick)).await?;
+    send(
+        &mut writer,
+        &format!("USER {} 0 * :Wordora Markov ChatBot", nick),
+    )
+    .await?;
+
+    let mut autosave = bridge::autosave_timer(bridge.config());
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => match line? {
+                Some(line) => line,
+                None => {
+                    println!("🔌 the IRC server closed the connection");
+                    return Ok(());
+                }
+            },
+            _ = bridge::tick(&mut autosave) => {
+                bridge.save();
+                continue;
+            }
+            _ = tokio::signal::ctrl_c() => {
+                println!("🛑 interrupted, saving bridge model");
+                let _ = send(&mut writer, "QUIT :bye").await;
+                return Ok(());
+            }
+        };
+        let Some(line) = Line::parse(&line) else {
+            continue;
+        };
+        match (line.command, line.params.as_slice()) {
+            ("PING", params) => {
+                send(&mut writer, &format!("PONG :{}", params.join(" "))).await?;
+            }
+            // **📌 登録が済んだらチャンネルに入る**
+            ("001", _) => {
+                println!("🤖 connected to IRC as {}", nick);
+                for channel in &irc.channels {
+                    send(&mut writer, &format!("JOIN {}", channel)).await?;
+                }
+            }
+            // **📌 ニックネームが使われていたら _ を足して名乗り直す**
+            ("433", _) => {
+                nick.push('_');
+                send(&mut writer, &format!("NICK {}", nick)).await?;
+            }
+            ("PRIVMSG", [target, text]) => {
+                let Some(sender) = line.nick.filter(|sender| *sender != nick) else {
+                    continue;
+                };
+                // **📌 個別のメッセージには送り主に、チャンネルでは呼びかけた人に答える**
+                let private = target.eq_ignore_ascii_case(&nick);
+                let channel = if private { sender } else { *target };
+                let called = addressed(text, &nick);
+                let incoming = Incoming {
+                    text,
+                    mentioned: private
+                        || called.is_some()
+                        || text.to_lowercase().contains(&nick.to_lowercase()),
+                    reply_to_bot: false,
+                };
+                let Some(reply) = bridge.hear(channel, sender, &incoming, called.unwrap_or(text))
+                else {
+                    continue;
+                };
+                if let Some(delay) = typing.delay_for(&reply) {
+                    tokio::time::sleep(delay).await;
+                }
+                let reply = match (private, called) {
+                    (false, Some(_)) => one_line(&format!("{}: {}", sender, reply)),
+                    _ => one_line(&reply),
+                };
+                send(&mut writer, &format!("PRIVMSG {} :{}", channel, reply)).await?;
+            }
+            _ => {}
+        }
+    }
+}
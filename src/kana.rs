@@ -0,0 +1,244 @@
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Distance between a hiragana and the matching katakana code point
+const KANA_OFFSET: u32 = 0x60;
+
+/// Convert katakana to hiragana; `ヵ`, `ヶ` and the long vowel mark `ー` are kept
+pub fn to_hiragana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'ァ'..='ヴ' | 'ヽ' | 'ヾ' => char::from_u32(c as u32 - KANA_OFFSET).unwrap_or(c),
+            c => c,
+        })
+        .collect()
+}
+
+/// Convert hiragana to katakana
+pub fn to_katakana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'ぁ'..='ゖ' | 'ゝ' | 'ゞ' => char::from_u32(c as u32 + KANA_OFFSET).unwrap_or(c),
+            c => c,
+        })
+        .collect()
+}
+
+/// Convert kana to Hepburn romaji; kanji and other characters are kept
+pub fn to_romaji(text: &str) -> String {
+    let chars: Vec<char> = to_hiragana(text).chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            // **📌 促音は次の子音を重ねる（っち → tchi）**
+            'っ' => {
+                if let Some((next, _)) = romaji_at(&chars[i + 1..]) {
+                    if next.starts_with("ch") {
+                        out.push('t');
+                    } else if let Some(c) = next.chars().next().filter(|c| !is_vowel(*c)) {
+                        out.push(c);
+                    }
+                }
+                i += 1;
+            }
+            // **📌 長音符は直前の母音を伸ばす**
+            'ー' => {
+                if let Some(vowel) = out.chars().last().filter(|c| is_vowel(*c)) {
+                    out.push(vowel);
+                }
+                i += 1;
+            }
+            'ん' => {
+                out.push('n');
+                if romaji_at(&chars[i + 1..])
+                    .is_some_and(|(next, _)| next.starts_with(|c| is_vowel(c) || c == 'y'))
+                {
+                    out.push('\'');
+                }
+                i += 1;
+            }
+            '。' => {
+                out.push('.');
+                i += 1;
+            }
+            '、' => {
+                out.push(',');
+                i += 1;
+            }
+            c => match romaji_at(&chars[i..]) {
+                Some((romaji, len)) => {
+                    out.push_str(&romaji);
+                    i += len;
+                }
+                None => {
+                    out.push(c);
+                    i += 1;
+                }
+            },
+        }
+    }
+    out
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'i' | 'u' | 'e' | 'o')
+}
+
+/// Romaji of the syllable starting at `chars[0]` and how many kana it spans,
+/// joining a following small kana (きゃ → kya, ふぁ → fa)
+fn romaji_at(chars: &[char]) -> Option<(String, usize)> {
+    let base = syllable(*chars.first()?)?;
+    match chars.get(1) {
+        Some(&small @ ('ゃ' | 'ゅ' | 'ょ')) if base.len() > 1 && base.ends_with('i') => {
+            let stem = &base[..base.len() - 1];
+            let glide = if ["sh", "ch", "j"].iter().any(|s| stem.ends_with(s)) {
+                ""
+            } else {
+                "y"
+            };
+            Some((format!("{}{}{}", stem, glide, &syllable(small)?[1..]), 2))
+        }
+        Some(&small @ ('ぁ' | 'ぃ' | 'ぅ' | 'ぇ' | 'ぉ')) if base.len() > 1 || base == "u" => {
+            let stem = match base {
+                "u" => "w",
+                _ => base.trim_end_matches(is_vowel),
+            };
+            Some((format!("{}{}", stem, syllable(small)?), 2))
+        }
+        _ => Some((base.to_string(), 1)),
+    }
+}
+
+/// Hepburn romaji of a single hiragana
+fn syllable(c: char) -> Option<&'static str> {
+    Some(match c {
+        'あ' | 'ぁ' => "a",
+        'い' | 'ぃ' | 'ゐ' => "i",
+        'う' | 'ぅ' => "u",
+        'え' | 'ぇ' | 'ゑ' => "e",
+        'お' | 'ぉ' | 'を' => "o",
+        'か' => "ka",
+        'き' => "ki",
+        'く' => "ku",
+        'け' => "ke",
+        'こ' => "ko",
+        'が' => "ga",
+        'ぎ' => "gi",
+        'ぐ' => "gu",
+        'げ' => "ge",
+        'ご' => "go",
+        'さ' => "sa",
+        'し' => "shi",
+        'す' => "su",
+        'せ' => "se",
+        'そ' => "so",
+        'ざ' => "za",
+        'じ' | 'ぢ' => "ji",
+        'ず' | 'づ' => "zu",
+        'ぜ' => "ze",
+        'ぞ' => "zo",
+        'た' => "ta",
+        'ち' => "chi",
+        'つ' => "tsu",
+        'て' => "te",
+        'と' => "to",
+        'だ' => "da",
+        'で' => "de",
+        'ど' => "do",
+        'な' => "na",
+        'に' => "ni",
+        'ぬ' => "nu",
+        'ね' => "ne",
+        'の' => "no",
+        'は' => "ha",
+        'ひ' => "hi",
+        'ふ' => "fu",
+        'へ' => "he",
+        'ほ' => "ho",
+        'ば' => "ba",
+        'び' => "bi",
+        'ぶ' => "bu",
+        'べ' => "be",
+        'ぼ' => "bo",
+        'ぱ' => "pa",
+        'ぴ' => "pi",
+        'ぷ' => "pu",
+        'ぺ' => "pe",
+        'ぽ' => "po",
+        'ま' => "ma",
+        'み' => "mi",
+        'む' => "mu",
+        'め' => "me",
+        'も' => "mo",
+        'や' | 'ゃ' => "ya",
+        'ゆ' | 'ゅ' => "yu",
+        'よ' | 'ょ' => "yo",
+        'ら' => "ra",
+        'り' => "ri",
+        'る' => "ru",
+        'れ' => "re",
+        'ろ' => "ro",
+        'わ' | 'ゎ' => "wa",
+        'ん' => "n",
+        'ゔ' => "vu",
+        _ => return None,
+    })
+}
+
+// **📌 OutputScript：返答のかなをどの文字で書くか**
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputScript {
+    /// Leave the reply as generated
+    #[default]
+    AsIs,
+    Hiragana,
+    Katakana,
+    /// Hepburn romaji; kanji are left as they are
+    Romaji,
+}
+
+impl OutputScript {
+    /// Rewrite the tokens of a reply in this script. In romaji a token ending in
+    /// a small つ is joined with the next one, so the doubled consonant survives
+    pub fn apply(self, tokens: &[&str]) -> Vec<String> {
+        let convert = |text: &str| match self {
+            OutputScript::AsIs => text.to_string(),
+            OutputScript::Hiragana => to_hiragana(text),
+            OutputScript::Katakana => to_katakana(text),
+            OutputScript::Romaji => to_romaji(text),
+        };
+        let mut out = Vec::new();
+        let mut pending = String::new();
+        for token in tokens {
+            pending.push_str(token);
+            if self == OutputScript::Romaji && pending.ends_with(['っ', 'ッ']) {
+                continue;
+            }
+            out.push(convert(&pending));
+            pending.clear();
+        }
+        if !pending.is_empty() {
+            out.push(convert(&pending));
+        }
+        out
+    }
+}
+
+impl FromStr for OutputScript {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "as-is" => Ok(OutputScript::AsIs),
+            "hiragana" => Ok(OutputScript::Hiragana),
+            "katakana" => Ok(OutputScript::Katakana),
+            "romaji" => Ok(OutputScript::Romaji),
+            _ => Err(format!(
+                "unknown script {:?} (hiragana, katakana, romaji, as-is)",
+                s
+            )),
+        }
+    }
+}
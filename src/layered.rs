@@ -0,0 +1,335 @@
+use crate::markov::{
+    detect_topic_layered, generate_around_layered, generate_layered, generate_with_topic_layered,
+    layered_cached_word, layered_distribution, salience, topics_layered, transitions_along,
+    GenerateOptions, MarkovChain, NextFn, ShortcutFn, TokenStream,
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// Token stream over every layer, see [`LayeredModel::stream`]
+pub type LayeredStream<'a> = TokenStream<NextFn<'a>, ShortcutFn<'a>>;
+
+// **📌 Layer構造体：名前付きの書き込み可能なレイヤー**
+#[derive(Clone)]
+pub struct Layer {
+    pub name: String,
+    pub chain: MarkovChain,
+}
+
+// **📌 LayeredModel構造体：読み取り専用のベースと上に重ねたレイヤー**
+#[derive(Clone)]
+pub struct LayeredModel {
+    base: Arc<MarkovChain>,
+    /// Lowest priority first; the last overlay is consulted first
+    overlays: Vec<Layer>,
+    /// Every layer, including overlays pushed later, refuses to learn
+    read_only: bool,
+}
+
+impl LayeredModel {
+    pub fn new(base: Arc<MarkovChain>) -> Self {
+        Self {
+            base,
+            overlays: Vec::new(),
+            read_only: false,
+        }
+    }
+
+    /// Make the base and every overlay read-only, so nothing can be learned,
+    /// unlearned or merged into the model
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+        if self.base.is_read_only() != read_only {
+            Arc::make_mut(&mut self.base).set_read_only(read_only);
+        }
+        for layer in &mut self.overlays {
+            layer.chain.set_read_only(read_only);
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn base(&self) -> &MarkovChain {
+        &self.base
+    }
+
+    /// Swap in a new base, e.g. one reloaded from disk; the overlays stay
+    pub fn set_base(&mut self, mut base: MarkovChain) {
+        base.set_read_only(self.read_only);
+        self.base = Arc::new(base);
+    }
+
+    /// Swap in a new chain for the named overlay; `false` if there is none
+    pub fn replace_overlay(&mut self, name: &str, mut chain: MarkovChain) -> bool {
+        let Some(layer) = self.overlays.iter_mut().find(|layer| layer.name == name) else {
+            return false;
+        };
+        chain.set_read_only(self.read_only);
+        layer.chain = chain;
+        true
+    }
+
+    pub fn overlays(&self) -> &[Layer] {
+        &self.overlays
+    }
+
+    /// Add a new top-priority overlay; an empty one shares the base's order and tokenizer
+    pub fn push_overlay(&mut self, name: &str, chain: Option<MarkovChain>) -> &mut MarkovChain {
+        let mut chain = chain
+            .unwrap_or_else(|| MarkovChain::new(self.base.order(), self.base.tokenizer().clone()));
+        chain.set_read_only(self.read_only);
+        self.overlays.push(Layer {
+            name: name.to_string(),
+            chain,
+        });
+        &mut self.overlays.last_mut().unwrap().chain
+    }
+
+    pub fn overlay(&self, name: &str) -> Option<&MarkovChain> {
+        self.overlays
+            .iter()
+            .find(|layer| layer.name == name)
+            .map(|layer| &layer.chain)
+    }
+
+    pub fn overlay_mut(&mut self, name: &str) -> Option<&mut MarkovChain> {
+        self.overlays
+            .iter_mut()
+            .find(|layer| layer.name == name)
+            .map(|layer| &mut layer.chain)
+    }
+
+    pub fn remove_overlay(&mut self, name: &str) -> Option<MarkovChain> {
+        let index = self.overlays.iter().position(|layer| layer.name == name)?;
+        Some(self.overlays.remove(index).chain)
+    }
+
+    /// Every layer in lookup order: overlays from the top down, then the base
+    pub fn layers(&self) -> Vec<&MarkovChain> {
+        self.overlays
+            .iter()
+            .rev()
+            .map(|layer| &layer.chain)
+            .chain(std::iter::once(self.base.as_ref()))
+            .collect()
+    }
+
+    /// Whether any layer can continue from this token
+    pub fn contains(&self, word: &str) -> bool {
+        self.layers().iter().any(|chain| chain.contains(word))
+    }
+
+    /// Whether any layer has seen this token, even without a transition from it
+    pub fn knows(&self, word: &str) -> bool {
+        self.layers().iter().any(|chain| chain.word(word).is_some())
+    }
+
+    /// Whether any layer has seen `next` follow `word`
+    pub fn has_transition(&self, word: &str, next: &str) -> bool {
+        self.layers().iter().any(|chain| {
+            chain
+                .word(word)
+                .is_some_and(|word| word.transitions.contains_key(next))
+        })
+    }
+
+    /// Closest seed known to any layer, see [`MarkovChain::resolve_seed`]
+    pub fn resolve_seed<'a>(&'a self, word: &'a str) -> Option<&'a str> {
+        self.layers()
+            .into_iter()
+            .find_map(|chain| chain.resolve_seed(word))
+    }
+
+    /// Salience of `token` over the texts learned by every layer, see
+    /// [`MarkovChain::salience`]
+    pub fn salience(&self, token: &str) -> f64 {
+        let layers = self.layers();
+        salience(
+            layers.iter().map(|chain| chain.documents()).sum(),
+            layers
+                .iter()
+                .map(|chain| chain.document_frequency(token))
+                .sum(),
+        )
+    }
+
+    /// Start of a reply from the top-most layer with learned response pairs,
+    /// see [`MarkovChain::response_seed`]
+    pub fn response_seed<S: AsRef<str>>(&self, tokens: &[S]) -> Option<&str> {
+        self.layers()
+            .into_iter()
+            .find_map(|chain| chain.response_seed(tokens))
+    }
+
+    /// Topics learned in any layer, sorted
+    pub fn topics(&self) -> Vec<&str> {
+        topics_layered(&self.layers())
+    }
+
+    /// Topic of `tokens` over every layer, see [`MarkovChain::detect_topic`]
+    pub fn detect_topic<S: AsRef<str>>(&self, tokens: &[S], min_score: f64) -> Option<&str> {
+        detect_topic_layered(&self.layers(), tokens, min_score)
+    }
+
+    /// Generate leaning towards `topic` as learned in any layer, see
+    /// [`MarkovChain::generate_with_topic`]
+    pub fn generate_with_topic(
+        &self,
+        topic: &str,
+        start_word: &str,
+        opts: &GenerateOptions,
+        weight: f64,
+    ) -> String {
+        generate_with_topic_layered(&self.layers(), topic, start_word, opts, weight)
+    }
+
+    /// Generate around `keyword` over every layer, see
+    /// [`MarkovChain::generate_around`]
+    pub fn generate_around(&self, keyword: &str, opts: &GenerateOptions) -> String {
+        generate_around_layered(&self.layers(), keyword, opts)
+    }
+
+    // **📝 文章を生成**
+    pub fn generate(&self, start_word: &str, opts: &GenerateOptions) -> String {
+        generate_layered(&self.layers(), start_word, opts)
+    }
+
+    /// Like [`generate`](Self::generate), but yields the tokens one at a time
+    pub fn generate_iter<'a>(
+        &'a self,
+        start_word: &str,
+        opts: &GenerateOptions,
+    ) -> impl Iterator<Item = String> + 'a {
+        self.stream(start_word, opts)
+    }
+
+    /// The stream behind [`generate_iter`](Self::generate_iter), which can be
+    /// [traced](TokenStream::traced) to explain a reply
+    pub fn stream<'a>(&'a self, start_word: &str, opts: &GenerateOptions) -> LayeredStream<'a> {
+        let layers = self.layers();
+        let order = layers.iter().map(|chain| chain.order()).max().unwrap_or(1);
+        let start_word = self.resolve_seed(start_word).unwrap_or(start_word);
+        let next_opts = opts.clone();
+        let cached_opts = opts.clone();
+        let cached_layers = layers.clone();
+        let next: NextFn<'a> =
+            Box::new(move |context: &[String]| layered_distribution(&layers, context, &next_opts));
+        let shortcut: ShortcutFn<'a> = Box::new(move |context: &[String]| {
+            layered_cached_word(&cached_layers, context, &cached_opts)
+        });
+        TokenStream::cached(start_word, opts, order, next, shortcut)
+    }
+
+    /// Generate while mixing in a second chain: each step samples from
+    /// `(1 - weight) * p_layers + weight * p_other`
+    pub fn generate_mixed(
+        &self,
+        other: &MarkovChain,
+        weight: f64,
+        start_word: &str,
+        opts: &GenerateOptions,
+    ) -> String {
+        let layers = self.layers();
+        let order = layers
+            .iter()
+            .map(|chain| chain.order())
+            .chain([other.order()])
+            .max()
+            .unwrap_or(1);
+        let start_word = self
+            .resolve_seed(start_word)
+            .or_else(|| other.resolve_seed(start_word))
+            .unwrap_or(start_word);
+        let next = |context: &[String]| {
+            let mut mixture: HashMap<&str, f64> = HashMap::new();
+            let parts = [
+                (layered_distribution(&layers, context, opts), 1.0 - weight),
+                (other.next_distribution(context, opts), weight),
+            ];
+            for (distribution, weight) in parts {
+                for (next, p) in distribution.unwrap_or_default() {
+                    *mixture.entry(next).or_insert(0.0) += weight * p;
+                }
+            }
+            Some(mixture.into_iter().filter(|(_, p)| *p > 0.0).collect())
+        };
+        opts.limits.retry(|| {
+            TokenStream::new(start_word, opts, order, &next)
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+    }
+
+    /// Merge overlay `name` into the layer below it and clear it.
+    ///
+    /// Returns the name of the layer that received the counts, or `None` for the
+    /// base. The base is copied on write, so other holders of it are unaffected.
+    /// Nothing is merged in read-only mode.
+    pub fn commit(&mut self, name: &str) -> Option<Option<String>> {
+        if self.read_only {
+            return None;
+        }
+        let index = self.overlays.iter().position(|layer| layer.name == name)?;
+        let chain = std::mem::replace(
+            &mut self.overlays[index].chain,
+            MarkovChain::new(self.base.order(), self.base.tokenizer().clone()),
+        );
+        if index == 0 {
+            Arc::make_mut(&mut self.base).merge(&chain);
+            Some(None)
+        } else {
+            let below = &mut self.overlays[index - 1];
+            below.chain.merge(&chain);
+            Some(Some(below.name.clone()))
+        }
+    }
+
+    /// [Reinforce](MarkovChain::reinforce) the path of `tokens` through
+    /// overlay `name` alone: each transition along it gets `factor - 1` times
+    /// the count all layers hold, so [committing](Self::commit) the overlay
+    /// leaves that count multiplied by `factor`. An overlay cannot hold less
+    /// than nothing, so weakening only takes back what it learned itself.
+    /// Returns the number of transitions changed, `None` without such an
+    /// overlay or in read-only mode
+    pub fn reinforce<S: AsRef<str>>(
+        &mut self,
+        name: &str,
+        tokens: &[S],
+        factor: f64,
+    ) -> Option<usize> {
+        if self.read_only {
+            return None;
+        }
+        let tokens: Vec<&str> = tokens.iter().map(AsRef::as_ref).collect();
+        let layers = self.layers();
+        let order = layers.iter().map(|chain| chain.order()).max().unwrap_or(1);
+        let changes: Vec<(String, &str, f64)> = transitions_along(&tokens, order)
+            .into_iter()
+            .filter_map(|(key, next)| {
+                let count: f64 = layers
+                    .iter()
+                    .map(|chain| chain.transition_count(&key, next))
+                    .sum();
+                (count > 0.0).then(|| (key, next, count * (factor - 1.0)))
+            })
+            .collect();
+        let overlay = self.overlay_mut(name)?;
+        Some(
+            changes
+                .into_iter()
+                .filter(|(key, next, count)| overlay.add_context_count(key, next, *count))
+                .count(),
+        )
+    }
+
+    /// Merge all layers into a single standalone chain
+    pub fn flatten(&self) -> MarkovChain {
+        let mut chain = self.base.as_ref().clone();
+        chain.set_read_only(false);
+        for layer in &self.overlays {
+            chain.merge(&layer.chain);
+        }
+        chain
+    }
+}
@@ -0,0 +1,47 @@
+pub mod bench;
+pub mod blocklist;
+pub mod compose;
+pub mod compression;
+pub mod config;
+pub mod conversation;
+pub mod corpus;
+pub mod decay;
+pub mod duel;
+pub mod encoding;
+pub mod error;
+pub mod events;
+pub mod fallback;
+pub mod feedback;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fuzzy;
+pub mod guard;
+pub mod kana;
+pub mod layered;
+pub mod manifest;
+#[cfg(feature = "mmap")]
+pub mod mapped;
+pub mod markov;
+pub mod names;
+pub mod persona;
+pub mod portable;
+pub mod postprocess;
+pub mod reaction;
+pub mod recent;
+pub mod rerank;
+pub mod session;
+pub mod storage;
+pub mod story;
+pub mod tokenizer;
+pub mod topic;
+pub mod transcript;
+pub mod trigger;
+pub mod tts;
+pub mod typing;
+pub mod validate;
+pub mod vocab;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "cli")]
+pub mod watch;
+pub mod web;
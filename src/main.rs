@@ -1,305 +1,1202 @@
-use rand::distr::{weighted::WeightedIndex, Distribution};
-use regex::Regex;
+#[cfg(any(feature = "irc", feature = "matrix"))]
+mod bridge;
+mod chat;
+#[cfg(feature = "discord")]
+mod discord;
+#[cfg(feature = "irc")]
+mod irc;
+#[cfg(feature = "matrix")]
+mod matrix;
+mod server;
+#[cfg(feature = "telegram")]
+mod telegram;
+mod tui;
+
+use chat::Chat;
+use clap::{Parser, Subcommand};
+use signal_hook::consts::SIGINT;
 use std::{
-    collections::HashMap,
-    io::{self, Write},
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+#[cfg(feature = "mmap")]
+use wordora_ai::mapped::MappedModel;
+use wordora_ai::postprocess::{apply_filters, repair_spacing};
+#[cfg(feature = "sled")]
+use wordora_ai::storage::{SledStorage, StoredChain};
+use wordora_ai::{
+    bench::bench,
+    blocklist::Blocklist,
+    config::Config,
+    corpus::{
+        parse_record, read_corpus, CorpusLimits, CorpusManifest, SourceEntry, SourceKind,
+        WeightedPath, CLEAN_PROFILE,
+    },
+    duel::{duel, summarize},
+    encoding::{enable_utf8_console, TextEncoding},
+    kana::OutputScript,
+    layered::LayeredModel,
+    manifest::{hash_file, Manifest, Source},
+    markov::{MarkovChain, Strategy},
+    names::{generate_names, NameOptions},
+    persona::Personas,
+    portable,
+    story::generate_story,
+    transcript::{analyze, read_transcript},
+    vocab::{export_vocab, VocabExport},
+    web,
 };
 
-// **📌 Word構造体：単語と遷移を管理**
-#[derive(Default, Clone)]
-struct Word {
-    word: String,
-    transitions: Vec<String>,
-}
+/// Built-in corpus used when no saved model is available
+const DEFAULT_CORPUS: &str = include_str!("default_corpus.txt");
 
-impl Word {
-    fn new(word: String) -> Self {
-        Self {
-            word,
-            transitions: Vec::new(),
-        }
-    }
+// **📌 コマンドライン引数**
+#[derive(Parser)]
+#[command(name = "wordora", version, about = "Wordora Markov ChatBot")]
+struct Cli {
+    /// Config file to use instead of wordora.toml
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Bundled generation preset (coherent, chaotic, short-replies, storyteller)
+    #[arg(long, global = true)]
+    preset: Option<String>,
+    /// Chat in a full-screen terminal UI
+    #[arg(long, global = true)]
+    tui: bool,
+    /// Chat over JSON Lines: read `{"message": ...}` from stdin and write
+    /// `{"reply", "seed", "tokens"}` to stdout
+    #[arg(long, global = true, conflicts_with = "tui")]
+    json: bool,
+    /// Never learn, unlearn or merge into the model (same as model.read_only)
+    #[arg(long, global = true)]
+    read_only: bool,
+    /// Reload the model when its file changes, and retrain it when model.corpus
+    /// changes (same as model.watch)
+    #[arg(long, global = true)]
+    watch: bool,
+    /// Rewrite the kana of replies: hiragana, katakana, romaji or as-is (same as output_script)
+    #[arg(long, global = true)]
+    output_script: Option<OutputScript>,
+    /// How tokens are picked: sample or beam (same as generation.strategy)
+    #[arg(long, global = true)]
+    strategy: Option<Strategy>,
+    /// Continuations kept per step with --strategy beam (same as generation.beam_width)
+    #[arg(long, global = true)]
+    beam_width: Option<usize>,
+    /// File of words replies must never contain (same as blocklist.path)
+    #[arg(long, global = true)]
+    blocklist: Option<PathBuf>,
+    /// Print the chat without colors (same as chat.color = false)
+    #[arg(long, global = true)]
+    no_color: bool,
+    /// Encoding of the chat's stdin and stdout: auto, utf-8, shift_jis or cp932
+    /// (same as chat.encoding)
+    #[arg(long, global = true)]
+    encoding: Option<TextEncoding>,
+    /// List the words of each message the model does not know after the reply
+    /// (same as chat.trainer)
+    #[arg(long, global = true)]
+    trainer: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-    fn add_transition(&mut self, new_transition: String) {
-        self.transitions.push(new_transition);
-    }
+#[cfg(any(feature = "irc", feature = "matrix"))]
+#[derive(Subcommand)]
+enum Network {
+    /// Connect to bridge.irc.server and join bridge.irc.channels
+    #[cfg(feature = "irc")]
+    Irc,
+    /// Sync with bridge.matrix.homeserver and answer in bridge.matrix.rooms
+    /// (access token read from bridge.matrix.token_env)
+    #[cfg(feature = "matrix")]
+    Matrix,
 }
 
-// **📌 MarkovChain構造体：単語と遷移を学習・生成**
-#[derive(Default)]
-struct MarkovChain {
-    words: HashMap<String, Word>,
+#[derive(Subcommand)]
+enum Command {
+    /// Chat with the bot in the terminal (default)
+    Chat,
+    /// Answer prompts over HTTP (POST /generate, /generate/batch and /feedback)
+    Serve {
+        /// Address to listen on instead of server.addr
+        #[arg(long)]
+        addr: Option<String>,
+    },
+    /// Answer mentions in Discord channels (token read from discord.token_env)
+    #[cfg(feature = "discord")]
+    Discord,
+    /// Reply to Telegram messages (token read from telegram.token_env)
+    #[cfg(feature = "telegram")]
+    Telegram,
+    /// Answer when addressed in an IRC channel or Matrix room, set up in [bridge]
+    #[cfg(any(feature = "irc", feature = "matrix"))]
+    Bridge {
+        #[command(subcommand)]
+        network: Network,
+    },
+    /// Invent new words from a character model of the vocabulary
+    Names {
+        #[arg(long, default_value_t = 10)]
+        count: usize,
+        /// Shortest name in characters
+        #[arg(long, default_value_t = 2)]
+        min: usize,
+        /// Longest name in characters
+        #[arg(long, default_value_t = 4)]
+        max: usize,
+        /// Characters of context used by the character model
+        #[arg(long, default_value_t = 2)]
+        order: usize,
+    },
+    /// Write a multi-paragraph story
+    Story {
+        #[arg(long)]
+        paragraphs: Option<usize>,
+        /// Start a new chapter with a header every this many paragraphs
+        #[arg(long)]
+        chapter_every: Option<usize>,
+        /// Weight multiplier per earlier use of a token (1.0 disables it)
+        #[arg(long)]
+        repetition_penalty: Option<f64>,
+    },
+    /// Let two models talk to each other, each reply seeding the next
+    Duel {
+        a: PathBuf,
+        b: PathBuf,
+        #[arg(long, default_value_t = 10)]
+        turns: usize,
+        /// First words of the conversation instead of the fallback word
+        #[arg(long)]
+        seed: Option<String>,
+        /// Also write the dialogue to this file
+        #[arg(long)]
+        log: Option<PathBuf>,
+    },
+    /// Train a new model from plain-text corpus files and save it
+    #[command(visible_alias = "learn")]
+    Train {
+        /// Corpus files, each optionally weighted as `file.txt:2.5`
+        #[arg(required_unless_present_any = ["manifest", "stdin", "corpus"])]
+        files: Vec<WeightedPath>,
+        /// Another corpus file, optionally weighted as `file.txt:2.5`
+        #[arg(long, conflicts_with = "manifest")]
+        corpus: Vec<WeightedPath>,
+        /// corpus.toml listing the sources to learn, instead of files
+        #[arg(long, conflicts_with = "files")]
+        manifest: Option<PathBuf>,
+        /// Format of the files: plain, line (LINE talk export), discord-json or transcript
+        #[arg(long, default_value = "plain", conflicts_with = "manifest")]
+        format: SourceKind,
+        /// Only learn this person's messages from line or discord-json files
+        #[arg(long, conflicts_with = "manifest")]
+        speaker: Option<String>,
+        /// Also learn which words of a line lead into the next one, so replies
+        /// start the way answers did in the corpus
+        #[arg(long, conflicts_with_all = ["manifest", "speaker"])]
+        pairs: bool,
+        /// Remember what each file taught under its path, so `forget --source`
+        /// can remove it later
+        #[arg(long, conflicts_with = "manifest")]
+        track_sources: bool,
+        /// Clean every file first with the config's [clean] steps: links and
+        /// mentions stripped, repeated and overlong lines dropped by default.
+        /// Sources of a manifest keep their own cleaning profile
+        #[arg(long)]
+        clean: bool,
+        /// Learn the files under this topic, which chat leans towards when a
+        /// message is about it; `#topic` lines switch it for the lines below
+        #[arg(long, conflicts_with = "manifest")]
+        topic: Option<String>,
+        /// Where to save the model instead of the manifest's out or model.path;
+        /// `.gz` and `.zst` names are compressed
+        #[arg(long, visible_alias = "model")]
+        out: Option<PathBuf>,
+        /// Abort on the first malformed line instead of skipping it
+        #[arg(long)]
+        strict: bool,
+        /// Compression level (gzip 0-9, zstd 1-22); other names are saved as zstd
+        #[arg(long)]
+        compression_level: Option<i32>,
+        /// Also learn the lines read from stdin, after the files
+        #[arg(long)]
+        stdin: bool,
+        /// Keep learning stdin line by line as it arrives (e.g. from `tail -f`),
+        /// continuing the saved model and saving it as it goes
+        #[arg(long, requires = "stdin")]
+        follow: bool,
+        /// Lines learned between saves with --follow
+        #[arg(long, default_value_t = 20)]
+        save_every: usize,
+    },
+    /// Learn corpus files into an on-disk transition store, for corpora too big for memory
+    #[cfg(feature = "sled")]
+    StoreTrain {
+        /// Store directory, created if missing
+        dir: PathBuf,
+        files: Vec<PathBuf>,
+        /// Abort on the first malformed line instead of skipping it
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Generate a reply from an on-disk transition store
+    #[cfg(feature = "sled")]
+    StoreGenerate { dir: PathBuf, seed: String },
+    /// Remove rarely seen transitions from the model
+    Prune {
+        /// Transitions seen fewer times than this are removed
+        #[arg(long, default_value_t = 2.0)]
+        min_count: f64,
+        /// Review the transitions word by word before pruning
+        #[arg(long)]
+        interactive: bool,
+        /// File of words (one per line) that are never pruned; words
+        /// whitelisted during review are added to it
+        #[arg(long)]
+        whitelist: Option<PathBuf>,
+        /// Where to save the model instead of model.path
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Take what one source taught back out of a model: a tracked source (a
+    /// corpus file learned with --track-sources or a Discord author with
+    /// track_authors) is subtracted, any other source in the model's manifest
+    /// is read again and unlearned
+    #[command(alias = "forget-source")]
+    Forget {
+        /// ID the source was tracked under, or its path or tag as listed in
+        /// the model's manifest
+        #[arg(long, required_unless_present = "list")]
+        source: Option<String>,
+        /// List the tracked sources instead
+        #[arg(long)]
+        list: bool,
+        /// Model to change instead of model.path, e.g. a guild model
+        #[arg(long)]
+        model: Option<PathBuf>,
+        /// corpus.toml the model was trained from, for the source's cleaning profile
+        #[arg(long)]
+        manifest: Option<PathBuf>,
+        /// Where to save the model instead of where it was loaded from
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Check a model against the manifest written when it was trained
+    Verify {
+        /// Model to check instead of model.path
+        model: Option<PathBuf>,
+    },
+    /// Report the model's perplexity on a held-out text file
+    Eval { file: PathBuf },
+    /// Measure training and generation speed on a corpus
+    Bench {
+        /// Corpus to train on instead of the built-in one
+        file: Option<PathBuf>,
+        /// Sentences generated after training
+        #[arg(long, default_value_t = 1000)]
+        sentences: usize,
+    },
+    /// Summarize a chat transcript written with chat.transcript
+    AnalyzeTranscript {
+        file: PathBuf,
+        /// Turns per window when measuring repetition over time
+        #[arg(long, default_value_t = 20)]
+        window: usize,
+    },
+    /// Merge the model and its configured overlays into one model file
+    Flatten { out: PathBuf },
+    /// Write the model and its overlays in the memory-mapped read-only format
+    #[cfg(feature = "mmap")]
+    ExportMapped { out: PathBuf },
+    /// Generate a reply from a memory-mapped model without loading it
+    #[cfg(feature = "mmap")]
+    MappedGenerate { file: PathBuf, seed: String },
+    /// Write a static web demo (HTML page, the wasm build of the library and
+    /// the model) into a folder; needs the wasm32-unknown-unknown target and
+    /// wasm-bindgen-cli
+    ExportWeb {
+        dir: PathBuf,
+        /// Prebuilt module (`cargo build --lib --release --target
+        /// wasm32-unknown-unknown --no-default-features --features wasm`)
+        /// used instead of building one from this crate's sources
+        #[arg(long)]
+        wasm: Option<PathBuf>,
+    },
+    /// Convert a model between the binary format and the portable JSON format,
+    /// chosen by the `.json` extension
+    Convert { input: PathBuf, output: PathBuf },
+    /// Write the learned vocabulary as a TSV file importable as an Anki deck
+    ExportVocab {
+        file: PathBuf,
+        /// Add an example sentence generated from each word
+        #[arg(long)]
+        examples: bool,
+        /// Skip words seen fewer times than this
+        #[arg(long, default_value_t = 1)]
+        min_count: usize,
+    },
 }
 
-impl MarkovChain {
-    // **🔍 学習**
-    fn learn(&mut self, text: &str) {
-        let whitespace_separated: Vec<&str> = text.split_whitespace().collect();
-        let mut japanese_separated: Vec<String> = vec![];
-        let mut separated: Vec<String> = vec![];
+/// Main function
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let preset = cli.preset.as_deref();
+    let mut config = match &cli.config {
+        Some(path) => Config::load(path, preset),
+        None => Config::load_or_default(preset),
+    }
+    .expect("Failed to load config");
+    config.model.read_only |= cli.read_only;
+    config.model.watch |= cli.watch;
+    config.chat.color &= !cli.no_color;
+    config.chat.trainer |= cli.trainer;
+    if let Some(encoding) = cli.encoding {
+        config.chat.encoding = encoding;
+    }
+    if let Some(script) = cli.output_script {
+        config.output_script = script;
+    }
+    if let Some(strategy) = cli.strategy {
+        config.generation.strategy = strategy;
+    }
+    if let Some(width) = cli.beam_width {
+        config.generation.beam_width = width;
+    }
+    if let Some(path) = cli.blocklist {
+        config.blocklist.path = Some(path);
+    }
+    if let Some(path) = &config.blocklist.path {
+        let blocklist = Blocklist::load(path).expect("Failed to load blocklist");
+        config.generation.blocklist = Arc::new(blocklist);
+    }
 
-        // **📌 日本語を「漢字」「ひらがな」「カタカナ」「記号」単位で分割**
-        for word in whitespace_separated {
-            let tokens = separate_tokens(word);
-            japanese_separated.extend(tokens);
-        }
+    // **🔒 読み取り専用ではモデルを書き換えるコマンドを断る**
+    let writes_model = match &cli.command {
+        Some(
+            Command::Train { .. } | Command::Prune { .. } | Command::Forget { list: false, .. },
+        ) => true,
+        #[cfg(feature = "sled")]
+        Some(Command::StoreTrain { .. }) => true,
+        _ => false,
+    };
+    if config.model.read_only && writes_model {
+        eprintln!("🔒 read-only mode: this command would change a model");
+        std::process::exit(1);
+    }
 
-        for word in japanese_separated {
-            separated.extend(chunk_string(&word, 5));
+    match cli.command.unwrap_or(Command::Chat) {
+        Command::Chat => {
+            // **📌 チャットのあいだだけ Windows のコンソールを UTF-8 にする（終われば元に戻す）**
+            let _console = enable_utf8_console();
+            let mut chat = Chat::new(&config, load_layers(&config, load_chain(&config)));
+            if cli.json {
+                chat.run_json().await.expect("Failed to chat over JSON");
+            } else if cli.tui {
+                tui::run(&mut chat).expect("Failed to run terminal UI");
+            } else {
+                chat.run().await;
+            }
         }
-
-        // **📌 マルコフ連鎖に単語を追加**
-        for i in 0..separated.len() {
-            let word_str = separated[i].clone();
-
-            // **📌 HashMap に単語がなければ新規追加**
-            self.words
-                .entry(word_str.clone())
-                .or_insert_with(|| Word::new(word_str.clone()));
-
-            // **📌 遷移を追加**
-            if let Some(next_word) = separated.get(i + 1) {
-                self.words
-                    .get_mut(&word_str)
-                    .unwrap()
-                    .add_transition(next_word.clone());
+        Command::Serve { addr } => {
+            let mut config = config.clone();
+            if let Some(addr) = addr {
+                config.server.addr = addr;
             }
+            // **📌 読み込みはサーバーを立ち上げてから裏で行う**
+            server::run(&config, || {
+                let model = load_layers(&config, load_chain(&config));
+                Ok((model, Personas::load(&config.persona)?))
+            })
+            .expect("Failed to run server");
         }
-    }
-
-    // **📝 文章を生成**
-    fn generate(&self, start_word: &str, length: usize) -> String {
-        let mut rng = rand::rng();
-        let mut result = start_word.to_string();
-        let mut current_word = start_word.to_string();
+        #[cfg(feature = "discord")]
+        Command::Discord => {
+            let model = load_layers(&config, load_chain(&config));
+            discord::run(&config, model)
+                .await
+                .expect("Failed to run Discord bot");
+        }
+        #[cfg(feature = "telegram")]
+        Command::Telegram => {
+            let model = load_layers(&config, load_chain(&config));
+            telegram::run(&config, model)
+                .await
+                .expect("Failed to run Telegram bot");
+        }
+        #[cfg(any(feature = "irc", feature = "matrix"))]
+        Command::Bridge { network } => {
+            let model = load_layers(&config, load_chain(&config));
+            match network {
+                #[cfg(feature = "irc")]
+                Network::Irc => irc::run(&config, model)
+                    .await
+                    .expect("Failed to run IRC bridge"),
+                #[cfg(feature = "matrix")]
+                Network::Matrix => matrix::run(&config, model)
+                    .await
+                    .expect("Failed to run Matrix bridge"),
+            }
+        }
+        Command::Names {
+            count,
+            min,
+            max,
+            order,
+        } => {
+            let opts = NameOptions {
+                count,
+                min,
+                max,
+                order,
+            };
+            for name in generate_names(&load_chain(&config), &opts) {
+                println!("{}", name);
+            }
+        }
+        Command::Story {
+            paragraphs,
+            chapter_every,
+            repetition_penalty,
+        } => {
+            let mut story = config.story.clone();
+            story.paragraphs = paragraphs.unwrap_or(story.paragraphs);
+            story.chapter_every = chapter_every.unwrap_or(story.chapter_every);
+            story.repetition_penalty = repetition_penalty.unwrap_or(story.repetition_penalty);
+            if let Err(e) = story.validate() {
+                eprintln!("⚠️ {}", e);
+                std::process::exit(1);
+            }
+            let chain = load_chain(&config);
+            println!("{}", generate_story(&chain, &config.generation, &story));
+        }
+        Command::Duel {
+            a,
+            b,
+            turns,
+            seed,
+            log,
+        } => {
+            let models =
+                [&a, &b].map(|path| MarkovChain::load(path).expect("Failed to load model"));
+            let names = [&a, &b].map(|path| {
+                path.file_stem().map_or_else(
+                    || path.display().to_string(),
+                    |s| s.to_string_lossy().into(),
+                )
+            });
+            let dialogue = duel(
+                [&models[0], &models[1]],
+                seed.as_deref(),
+                turns,
+                &config.generation,
+            );
+            let mut lines = Vec::new();
+            for turn in &dialogue {
+                let tokens: Vec<&str> = turn.tokens.iter().map(String::as_str).collect();
+                let tokens =
+                    apply_filters(&config.postprocess, config.output_script.apply(&tokens));
+                let text = repair_spacing(&tokens.iter().map(String::as_str).collect::<Vec<_>>());
+                let line = format!("{}: {}", names[turn.speaker], text);
+                println!("{}", line);
+                lines.push(line);
+            }
+            let summary = summarize(&dialogue);
+            println!(
+                "🧾 {} turns, {} empty, {:.1} tokens per turn, {:.0}% repeated",
+                summary.turns,
+                summary.empty,
+                summary.average_tokens,
+                summary.repeated_share * 100.0
+            );
+            if let Some(log) = log {
+                fs::write(&log, lines.join("\n") + "\n").expect("Failed to write log");
+                println!("💾 {}", log.display());
+            }
+        }
+        Command::Train {
+            files,
+            corpus,
+            manifest,
+            format,
+            speaker,
+            pairs,
+            track_sources,
+            clean,
+            topic,
+            out,
+            strict,
+            compression_level,
+            stdin,
+            follow,
+            save_every,
+        } => {
+            // **📌 ファイルを並べただけのときは重み1で --format の形式として扱う**
+            if speaker.is_some() && !format.has_speakers() {
+                eprintln!("⚠️ --speaker needs --format line or discord-json");
+                std::process::exit(1);
+            }
+            let mut plan = match &manifest {
+                Some(path) => CorpusManifest::load(path).expect("Failed to read corpus manifest"),
+                None => CorpusManifest {
+                    out: None,
+                    order: None,
+                    cleaning: Default::default(),
+                    sources: files
+                        .into_iter()
+                        .chain(corpus)
+                        .map(|file| SourceEntry {
+                            path: file.path,
+                            weight: file.weight,
+                            kind: format,
+                            speaker: speaker.clone(),
+                            pairs,
+                            track: track_sources,
+                            topic: topic.clone(),
+                            ..Default::default()
+                        })
+                        .collect(),
+                },
+            };
+            if clean {
+                plan.cleaning
+                    .insert(CLEAN_PROFILE.to_string(), config.cleaning());
+                for source in &mut plan.sources {
+                    source
+                        .cleaning
+                        .get_or_insert_with(|| CLEAN_PROFILE.to_string());
+                }
+            }
+            let Some(out) = out
+                .or_else(|| plan.out.clone())
+                .or_else(|| config.model.path.clone())
+            else {
+                eprintln!("⚠️ no model.path configured; pass --out");
+                std::process::exit(1);
+            };
+            let limits = CorpusLimits {
+                strict: strict || config.corpus.strict,
+                ..config.corpus.clone()
+            };
+            let order = plan.order.unwrap_or(config.model.order);
+            // **📌 ログを追いかけるときは保存済みのモデルに学習を続ける**
+            let mut chain = if follow && out.exists() {
+                println!("📚 continuing {}", out.display());
+                MarkovChain::load(&out).expect("Failed to load model")
+            } else {
+                MarkovChain::new(order, config.tokenizer.clone())
+            };
+            let mut sources = Vec::new();
+            for entry in &plan.sources {
+                let corpus = plan
+                    .learn(entry, &mut chain, &limits)
+                    .expect("Failed to read corpus");
+                let mut source =
+                    Source::new(&entry.path, corpus.records.len(), corpus.skipped.len())
+                        .expect("Failed to hash corpus");
+                source.tag = entry.tag.clone();
+                source.weight = entry.weight;
+                source.kind = entry.kind;
+                source.speaker = entry.speaker.clone();
+                source.pairs = entry.pairs;
+                source.cleaning = entry.cleaning.clone();
+                source.topic = entry.topic.clone();
+                sources.push(source);
+                print!(
+                    "📚 {}: {} lines, {} skipped",
+                    entry.path.display(),
+                    corpus.records.len(),
+                    corpus.skipped.len()
+                );
+                if corpus.dropped > 0 {
+                    print!(", {} dropped by cleaning", corpus.dropped);
+                }
+                println!();
+                for skipped in corpus.skipped.iter().take(5) {
+                    eprintln!("  ⚠️ line {}: {}", skipped.line, skipped.reason);
+                }
+            }
+            if stdin {
+                let save_every = follow.then_some(save_every.max(1));
+                let autosave = config.model.autosave_seconds.map(Duration::from_secs_f64);
+                let learned = learn_stdin(
+                    &mut chain,
+                    &limits,
+                    save_every,
+                    autosave,
+                    &out,
+                    compression_level,
+                )
+                .expect("Failed to learn from stdin");
+                println!("📚 stdin: {} lines", learned);
+            }
+            chain
+                .save_with_level(&out, compression_level)
+                .expect("Failed to save model");
+            println!("💾 {}", out.display());
 
-        for _ in 0..length {
-            if let Some(word) = self.words.get(&current_word) {
-                if !word.transitions.is_empty() {
-                    // **📌 遷移の重みを計算（出現頻度に基づいて重み付け）**
-                    let weights: Vec<_> = word
-                        .transitions
-                        .iter()
-                        .map(|w| word.transitions.iter().filter(|&x| *x == *w).count())
-                        .collect();
+            // **📌 再現用のマニフェストをモデルの隣に書き出す（標準入力は読み直せないので書かない）**
+            if stdin {
+                println!("⚠️ no manifest written: stdin cannot be replayed");
+            } else {
+                let manifest_path = Manifest::path_for(&out);
+                Manifest::new(&out, chain.order(), chain.tokenizer(), sources)
+                    .and_then(|manifest| manifest.save(&manifest_path))
+                    .expect("Failed to write manifest");
+                println!("🧾 {}", manifest_path.display());
+            }
+        }
+        #[cfg(feature = "sled")]
+        Command::StoreTrain { dir, files, strict } => {
+            let limits = CorpusLimits {
+                strict: strict || config.corpus.strict,
+                ..config.corpus.clone()
+            };
+            let storage = SledStorage::open(&dir).expect("Failed to open store");
+            // **📌 既存のストアは学習時の設定を引き継ぐ**
+            let (order, tokenizer) = storage
+                .config()
+                .expect("Failed to read store config")
+                .unwrap_or_else(|| (config.model.order, config.tokenizer.clone()));
+            storage
+                .set_config(order, &tokenizer)
+                .expect("Failed to write store config");
+            let mut chain = StoredChain::new(order, tokenizer, storage);
+            for file in &files {
+                let corpus = read_corpus(file, &limits).expect("Failed to read corpus");
+                for record in &corpus.records {
+                    chain.learn(record).expect("Failed to learn");
+                }
+                println!(
+                    "📚 {}: {} lines, {} skipped",
+                    file.display(),
+                    corpus.records.len(),
+                    corpus.skipped.len()
+                );
+            }
+            chain.flush().expect("Failed to write store");
+            println!("💾 {}", dir.display());
+        }
+        #[cfg(feature = "sled")]
+        Command::StoreGenerate { dir, seed } => {
+            let storage = SledStorage::open(&dir).expect("Failed to open store");
+            let Some((order, tokenizer)) = storage.config().expect("Failed to read store config")
+            else {
+                eprintln!("⚠️ {} is not a trained store", dir.display());
+                std::process::exit(1);
+            };
+            let chain = StoredChain::new(order, tokenizer, storage);
+            let reply = chain
+                .generate(&seed, &config.generation)
+                .expect("Failed to read store");
+            let tokens: Vec<&str> = reply.split_whitespace().collect();
+            println!("{}", repair_spacing(&tokens));
+        }
+        Command::Prune {
+            min_count,
+            interactive,
+            whitelist,
+            out,
+        } => {
+            let Some(out) = out.or_else(|| config.model.path.clone()) else {
+                eprintln!("⚠️ no model.path configured; pass --out");
+                std::process::exit(1);
+            };
+            let mut keep: HashSet<String> = match &whitelist {
+                Some(path) if path.exists() => fs::read_to_string(path)
+                    .expect("Failed to read whitelist")
+                    .lines()
+                    .map(|line| line.trim().to_string())
+                    .filter(|line| !line.is_empty())
+                    .collect(),
+                _ => HashSet::new(),
+            };
+            let mut chain = load_chain(&config);
 
-                    // **📌 WeightedIndexで重み付けしたランダム選択**
-                    let dist = WeightedIndex::new(&weights).unwrap();
-                    let next_word = &word.transitions[dist.sample(&mut rng)];
+            let mut whitelisted = Vec::new();
+            if interactive {
+                let candidates: Vec<_> = chain
+                    .prune_candidates(min_count)
+                    .into_iter()
+                    .filter(|(word, _)| !keep.contains(*word))
+                    .collect();
+                let Some(review) = review_prune(&candidates) else {
+                    println!("🚫 nothing pruned");
+                    return;
+                };
+                keep.extend(review.skipped);
+                keep.extend(review.whitelisted.iter().cloned());
+                whitelisted = review.whitelisted;
+            }
 
-                    result.push_str(" ");
-                    result.push_str(next_word);
-                    current_word = next_word.clone().to_string();
-                } else {
-                    result = self.generate("。", 20);
+            let removed = chain.prune(min_count, &keep);
+            chain.save(&out).expect("Failed to save model");
+            println!("✂️ {} transitions removed", removed);
+            println!("💾 {}", out.display());
+            if let (Some(path), false) = (&whitelist, whitelisted.is_empty()) {
+                let mut file = fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .expect("Failed to open whitelist");
+                for word in &whitelisted {
+                    writeln!(file, "{}", word).expect("Failed to write whitelist");
                 }
+                println!("📝 {}", path.display());
+            }
+        }
+        Command::Forget {
+            source,
+            list,
+            model,
+            manifest,
+            out,
+        } => {
+            let Some(model) = model.or_else(|| config.model.path.clone()) else {
+                eprintln!("⚠️ no model.path configured; pass --model");
+                std::process::exit(1);
+            };
+            if list {
+                let chain = MarkovChain::load(&model).expect("Failed to load model");
+                let mut sources: Vec<&str> = chain.sources().collect();
+                sources.sort_unstable();
+                for source in sources {
+                    println!("{}", source);
+                }
+                return;
+            }
+            let source = source.expect("clap requires --source without --list");
+            let out = out.unwrap_or_else(|| model.clone());
+            forget(&config, &model, &source, manifest.as_deref(), &out);
+        }
+        Command::Verify { model } => {
+            let Some(model) = model.or_else(|| config.model.path.clone()) else {
+                eprintln!("⚠️ no model.path configured; pass the model file");
+                std::process::exit(1);
+            };
+            let manifest =
+                Manifest::load(&Manifest::path_for(&model)).expect("Failed to read manifest");
+            let problems = manifest.verify(&model).expect("Failed to verify model");
+            if problems.is_empty() {
+                println!("✅ {} matches its manifest", model.display());
+            } else {
+                for problem in &problems {
+                    println!("❌ {}", problem);
+                }
+                std::process::exit(1);
+            }
+        }
+        Command::Flatten { out } => {
+            let chain = load_layers(&config, load_chain(&config)).flatten();
+            chain.save(&out).expect("Failed to save model");
+            println!("💾 {}", out.display());
+        }
+        #[cfg(feature = "mmap")]
+        Command::ExportMapped { out } => {
+            let chain = load_layers(&config, load_chain(&config)).flatten();
+            MappedModel::write(&chain, &out).expect("Failed to write mapped model");
+            println!("💾 {}", out.display());
+        }
+        #[cfg(feature = "mmap")]
+        Command::MappedGenerate { file, seed } => {
+            let model = MappedModel::open(&file).expect("Failed to open mapped model");
+            let reply = model.generate(&seed, &config.generation);
+            let tokens: Vec<&str> = reply.split_whitespace().collect();
+            println!("{}", repair_spacing(&tokens));
+        }
+        Command::Eval { file } => {
+            let chain = load_chain(&config);
+            let text = fs::read_to_string(&file).expect("Failed to read file");
+            let eval = chain.evaluate(&text);
+            println!("📊 {}", file.display());
+            println!("  tokens:     {}", eval.tokens);
+            println!(
+                "  unknown:    {} ({:.1}%)",
+                eval.unknown,
+                100.0 * eval.unknown as f64 / eval.tokens.max(1) as f64
+            );
+            println!("  log-prob:   {:.2}", eval.log_prob);
+            println!("  perplexity: {:.2}", eval.perplexity);
+        }
+        Command::Bench { file, sentences } => {
+            let lines = match &file {
+                Some(path) => {
+                    read_corpus(path, &config.corpus)
+                        .expect("Failed to read corpus")
+                        .records
+                }
+                None => DEFAULT_CORPUS
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            };
+            let report = bench(
+                &lines,
+                config.model.order,
+                &config.tokenizer,
+                sentences,
+                &config.generation,
+            );
+            println!(
+                "📊 {}",
+                file.as_ref()
+                    .map_or("built-in corpus".to_string(), |path| path
+                        .display()
+                        .to_string())
+            );
+            println!(
+                "  learn:      {} lines, {} tokens in {:.3}s ({:.0} tokens/s)",
+                report.lines,
+                report.tokens,
+                report.learn_time.as_secs_f64(),
+                report.tokens_per_second()
+            );
+            println!(
+                "  generate:   {} sentences, {} tokens in {:.3}s ({:.0} sentences/s)",
+                report.sentences,
+                report.generated_tokens,
+                report.generate_time.as_secs_f64(),
+                report.sentences_per_second()
+            );
+            println!("  vocabulary: {}", report.vocabulary);
+            match report.peak_memory {
+                Some(bytes) => println!("  peak RSS:   {:.1} MiB", bytes as f64 / 1048576.0),
+                None => println!("  peak RSS:   unknown"),
+            }
+        }
+        Command::AnalyzeTranscript { file, window } => {
+            let reader = BufReader::new(File::open(&file).expect("Failed to open transcript"));
+            let (entries, malformed) = read_transcript(reader).expect("Failed to read transcript");
+            let report = analyze(&entries, window);
+            println!("📊 {}", file.display());
+            println!("  turns:          {}", report.turns);
+            if malformed > 0 {
+                println!("  unreadable:     {}", malformed);
+            }
+            println!(
+                "  latency:        {:.2} ms mean, {:.2} ms max",
+                report.mean_latency_ms, report.max_latency_ms
+            );
+            println!("  reply length:   {:.1} tokens", report.mean_reply_tokens);
+            println!("  fallbacks:      {:.1}%", 100.0 * report.fallback_rate);
+            println!("  repetition by window of {} turns:", window);
+            for (i, rate) in report.repetition.iter().enumerate() {
+                println!(
+                    "    {:>4}-{:<4} {:.1}%",
+                    i * window + 1,
+                    ((i + 1) * window).min(report.turns),
+                    100.0 * rate
+                );
+            }
+        }
+        Command::Convert { input, output } => {
+            let chain = if is_json(&input) {
+                portable::load_json(&input)
+            } else {
+                MarkovChain::load(&input)
+            }
+            .expect("Failed to read model");
+            if is_json(&output) {
+                portable::save_json(&chain, &output)
             } else {
-                // **📌 現在の単語が辞書にない場合、ランダムな単語を選択**
-                result = self.generate("。", 20);
+                chain.save(&output)
             }
+            .expect("Failed to write model");
+            println!("💾 {}", output.display());
+        }
+        Command::ExportWeb { dir, wasm } => {
+            let chain = load_chain(&config);
+            web::export(
+                &chain,
+                &config.generation,
+                &config.prompt,
+                &config.blocklist,
+                wasm.as_deref(),
+                &dir,
+            )
+            .expect("Failed to export web demo");
+            println!("📦 {}", dir.display());
+        }
+        Command::ExportVocab {
+            file,
+            examples,
+            min_count,
+        } => {
+            let export = VocabExport {
+                min_count,
+                examples,
+            };
+            let chain = load_chain(&config);
+            let out = BufWriter::new(File::create(&file).expect("Failed to create file"));
+            export_vocab(&chain, &config.generation, &export, out)
+                .expect("Failed to export vocabulary");
+            println!("📦 {}", file.display());
         }
-        result
     }
 }
 
-fn chunk_string(input: &str, chunk_size: usize) -> Vec<String> {
-    input
-        .chars() // 文字単位で処理
-        .collect::<Vec<char>>() // Vec<char>に変換
-        .chunks(chunk_size) // chunk_sizeごとに区切る
-        .map(|chunk| chunk.iter().collect()) // チャンクを文字列に変換
-        .collect()
+/// Whether a model path is in the portable JSON format
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
 }
 
-/// Separate tokens
-fn separate_tokens(text: &str) -> Vec<String> {
-    let re = Regex::new(r"([一-龯]+|[ぁ-ん]+|[ァ-ヴー]+|[。、a-zA-Z]+)").unwrap();
-    let text: Vec<String> = re.find_iter(text).map(|m| m.as_str().to_string()).collect();
-    // Then split by whitespace
-    text.join(" ").split_whitespace().map(|s| s.to_string()).collect()
+// **📌 PruneReview構造体：確認の結果**
+struct PruneReview {
+    /// Words kept this time only
+    skipped: Vec<String>,
+    /// Words never to be pruned
+    whitelisted: Vec<String>,
 }
 
-/// Main function
-fn main() {
-    let mut chain = MarkovChain::default();
-
-    let text = "今日は天気がいいですね。天気が悪い日もあります。明日はどうなるでしょうか？今日はいい天気ですね。気温も温かくて過ごしやすいです。午後は少し風が強くなるかもしれません。明日はもっと晴れるといいなと思っています。あなたはどうですか？最近は忙しいですか？私も少し忙しくて、いろいろなことを考えてしまいます。でも、少し休憩を取るとリフレッシュできるので、午後はゆっくりしたいです。お昼ご飯は何を食べましたか？私はサンドイッチを食べました。簡単だけど美味しかったです。来週の予定はどうですか？私は友達と会う予定があります。楽しみです。今日は本当に暑いですね。外に出るのが少し嫌になってしまいます。でも、夏は好きだからまあいいか。
-そういえば、最近見た映画がすごく面白かったんです。君も映画はよく観る方ですか？
-あ、でも、天気が悪いときは、家で読書やNetflixを見たりすることが多いかな。
-君は最近、何か面白いことありましたか？
-最近、友達と旅行に行ったんですよ。小旅行だったけど、すごく楽しかったです。
-そういえば、明日の予定は決まってるんですか？
-明日は、午後から少し買い物に行こうかなと思っています。何か欲しいものがあるんです。
-そうそう、先週行ったレストラン、めちゃくちゃ美味しかったんですよ！何を頼んでも絶品でした。
-あ、あなたは外食ってよくしますか？
-最近、運動不足で、少し体がなまってきたかも。運動する時間を作らないとですね。
-昨日の夜は、久しぶりに自分で料理をしました。炒飯を作ったんですけど、美味しくできました！
-それは良いですね！自分で作る料理ってなんだか心がこもってる気がしますよね。
-最近、よく考えているのが、将来のこと。やりたいことがたくさんあるけど、どれを選ぶか迷ってるんです。
-あ、それは分かります。私はやりたいことがいっぱいあって、時間が足りないって感じです。
-ところで、週末はどう過ごす予定ですか？私は友達とアウトドアに出かけるつもりです。
-あ、それいいですね！私も最近、ハイキングがしたいなと思ってたんです。自然の中でリフレッシュしたいです。
-実は来月、海外旅行に行く予定なんです。ずっと行きたかった場所なので、すごく楽しみです。
-海外旅行いいですね！どこに行くんですか？私はまだ行ったことがないところが多いので、羨ましいです。
-そういえば、最近何かハマっている趣味がありますか？私は最近、絵を描くことにはまっているんです。
-絵を描くのは素晴らしい趣味ですね！私は最近、音楽に少し興味を持ち始めて、楽器を少し練習してます。
-それにしても、今年の夏は本当に暑いですね。どうしても冷たいものばかり飲んじゃいます。
-暑い日はやっぱりアイスが恋しくなりますよね。特に抹茶アイスが好きで、よく食べてます。
-あ、アイスといえば、最近食べたフルーツタルトがめちゃくちゃ美味しかったんです。おすすめです！
-最近の天気、どうだった？
-昨日は少し寒かったけど、今日は晴れて気持ちがいいね。
-うん、晴れてる日はなんだか元気が出るよね。
-
-この前、映画見に行ったんだけど、すごく面白かったよ！
-どんな映画だったの？私は最近映画館に行ってないなぁ。
-『インセプション』みたいな頭を使う映画だよ。君は映画館行くとき、ポップコーン派？
-
-ポップコーン派かな。塩味のが一番だね。でも、最近は映画館の料金が高くてあまり行かなくなったな。
-そうだよね、映画料金が高いから家で見ることが多くなっちゃう。家でも十分楽しめるしね。
-
-それに、最近はNetflixとかHuluで見れるから便利だよね。おすすめの映画ある？
-最近見た映画だと『アバター』が良かったよ。視覚的にもすごく迫力があって、感動した！
-
-それは面白そうだね！今度見てみようかな。最近は忙しくて、あまり映画を見る時間が取れてないんだよね。
-わかる。忙しいと、リラックスする時間がなかなか取れないよね。でも、ちょっとした時間に映画やドラマを見るとリフレッシュできるよ。
-
-それ、いいアイディアだね！リラックスしたい時におすすめのドラマとかある？
-『フレンズ』は何度見ても面白いし、気軽に楽しめるからおすすめだよ。明るい雰囲気で癒されるし。
-
-それは確かに癒されそうだね！私も久しぶりに見たくなった。君は最近、どんな趣味を楽しんでるの？
-最近は写真を撮るのが好きで、週末にはよく散歩がてらカメラを持って外に出るよ。風景とか、街の雰囲気を撮るのが楽しいんだ。
-
-素敵だね！私もカメラを持って外に出るのが好きだけど、最近は忙しくてなかなかできてないなぁ。
-そうなんだ。忙しいと趣味の時間も取りづらいよね。でも、少しでも時間を見つけてやると気分がすごく変わるよ。
-
-そうだよね、やっぱり自分の時間も大切にしないと。最近、体調はどう？
-元気だよ！ちょっとした運動をしてるから、体調も良くなったし、心も落ち着いてるよ。
-
-運動かぁ、良いね！私は最近、ウォーキングを始めたんだ。長時間歩くとすっきりするし、気分転換になるよ。
-ウォーキングは体にも優しいし、リラックスできるからいいね。私ももっと歩くようにしようかな。
-
-最近、健康に気を使ってるのかな？
-そうだね、少し前から健康に気をつけるようになって。食事や運動も大事だけど、睡眠も大切だから、よく寝るようにしてるよ。
-
-それ、すごく大事だよね。睡眠不足だと一日がダルく感じるもんね。君は何か健康的な食事を作ったりするの？
-最近はサラダをよく作るよ。アボカドやトマトをたくさん入れて、オリーブオイルと塩で味付けするだけでおいしいんだ。
-
-それ、ヘルシーでおいしそう！私は最近、野菜をたくさん取るようにしてるんだ。スムージーにして毎朝飲んでるよ。
-それ、良い習慣だね！私もスムージーを試してみようかな。野菜と果物を一緒に取れるから栄養満点だしね。
-
-じゃあ、今度一緒に作ってみる？
-いいね！一緒に作ったら楽しそうだし、新しいレシピも試せそうだね。
-
-そういえば、最近読んだ本とかはある？
-最近読んだ本は『ノルウェイの森』だよ。村上春樹の作品は深くて考えさせられるんだよね。
-村上春樹の本って、独特な雰囲気があって面白いよね。私は『海辺のカフカ』が好きだなぁ。
-
-『海辺のカフカ』もいいよね！夢と現実が交錯する感じが好き。物語の進行がどうなるか気になっちゃう。
-わかる！あの不思議な感じがたまらないんだよね。村上春樹の本を読むと、少し現実を忘れて違う世界に浸れる気がする。
-
-そういえば、君は普段音楽は何を聴いてるの？
-最近はジャズにハマってるんだ。リラックスしたいときにジャズを聴くと、気分が落ち着くんだよね。
-
-ジャズか、いいね！私は最近、クラシック音楽をよく聴いてるよ。ベートーヴェンとかモーツァルトの曲が心に響くんだよね。
-クラシックは本当に心に染みるよね。特に、ゆっくりとした曲を聴くと、リラックスできて疲れが取れる気がする。
-
-音楽を聴きながらリラックスする時間って、本当に大切だよね。君は他にどんな趣味があるの？
-私は絵を描くのが好きだよ。風景画を描いたり、ポートレートを描いたりしてるんだ。色を使って表現するのが楽しいんだよね。
-
-素敵だね！私も昔、絵を描くのが好きだったけど、最近はあまり描けてないなぁ。君はどんな道具で絵を描くの？
-私は主にアクリル絵の具を使ってるよ。乾くのが早いし、色の発色が鮮やかだから好きなんだ。
-
-アクリル絵の具は扱いやすいよね！私は水彩が好きだったけど、少し難しいなと思って。やっぱり練習が必要だよね。
-水彩画は難しいけど、その透明感がすごくきれいなんだよね。君もまた挑戦してみたらどう？
-
-そうだね、たまには再挑戦してみようかな。絵を描くとき、集中できて気持ちも落ち着くから、時間を見つけてやってみるよ。
-うん、それがいいと思う！絵を描く時間って、すごく自分と向き合う時間になって心がリフレッシュされるよね。
-
-それにしても、最近忙しくてあまり自分の時間が取れてないんだよね。
-忙しいと本当に時間が足りなく感じるよね。でも、少しの時間でも自分の好きなことをすることが大事だよ。
-
-確かに、少しでもリフレッシュできる時間があればいいよね。君は最近、リフレッシュできた瞬間ってあった？
-この前、友達と公園に行ったんだけど、外でのんびり過ごす時間がすごく気持ちよかったんだ。
-
-それは素敵だね！自然の中で過ごすと、心が落ち着くよね。私もたまには外で散歩したりして、自然を感じたくなるよ。
-外に出ると気分も変わるし、リラックスできるもんね。自然の中にいると、どんな小さなことにも感謝できる気がするよ。
-
-感謝したこととかある？
-最近、友達から手作りのクッキーをもらったんだけど、その気持ちがすごく嬉しくて感謝したなぁ。こんな些細なことでも、思いやりって大事だなって感じたよ。
+/// Ask about each word's rare transitions; `None` if the user quits
+fn review_prune(candidates: &[(&str, Vec<(&str, f64)>)]) -> Option<PruneReview> {
+    let mut review = PruneReview {
+        skipped: Vec::new(),
+        whitelisted: Vec::new(),
+    };
+    let mut lines = io::stdin().lock().lines();
+    let mut pruned = 0;
 
-それは素敵だね！そういう小さな優しさが心に残るよね。私は最近、家族が手伝ってくれて、すごく感謝してる。忙しい時に助けてもらうと、ありがたさがしみるよね。
-本当にそうだよね。家族のサポートって本当に大きいよね。何気ない言葉や行動が、一番ありがたく感じる瞬間だな。
-
-あ、それで思い出したけど、最近何か面白い映画を観た？
-最近は『ジョーカー』を観たよ。すごく考えさせられる映画だった。キャラクターの心理描写が深くて、観た後に余韻が残ったなぁ。
-
-『ジョーカー』は確かに印象に残る映画だよね。暗いけど、その中に人間の複雑さが表現されていて、観ていてドキドキする。君は映画を観るとき、どんなジャンルが好き？
-私はサスペンスやミステリーが好きだな。謎が解ける瞬間にスッキリするんだよね。でも、たまには感動的な映画も観たくなる。
-
-サスペンスやミステリーは本当にハラハラするよね。解決編に向けて、どんどん引き込まれていく感じがたまらない。感動的な映画も心が温かくなるよね。
-映画って、その時の気分によって観るジャンルが変わるから面白いよね。君はどんな映画で感動したことがある？
-
-『グリーンブック』がすごく感動したよ。人種差別や偏見について考えさせられる内容だったけど、最終的には希望を感じさせてくれる。あの映画を観た後、心が温かくなったんだ。
-『グリーンブック』、いい映画だよね。あのストーリーは感動的で、人との絆の大切さを教えてくれる。観て良かったと思える作品だよね。
-
-私もそんな映画を観て、自分がどう生きていくか、少し考えることがある。映画って本当に色々なことを教えてくれるよね。
-本当にそうだよね。映画って、ただ楽しむだけじゃなくて、人生や人間についての深いメッセージを感じることができるから、毎回観た後に自分に何か残る気がする。
-
-それにしても、最近天気が良い日が続いてるね。君は晴れの日は外で何かするのが好き？
-晴れた日は、散歩に出かけるのが好きだな。陽射しが気持ちよくて、何だか心も軽くなる気がする。あと、友達とピクニックにも行くことが多いよ。
-
-ピクニックって、いいよね！自然の中でのんびり過ごすのが楽しいし、美味しいものを食べながらの会話も最高だよね。最近はどうだった？
-この前、友達と近くの公園でピクニックしたんだけど、天気も良くて、本当に楽しかった！手作りのサンドイッチを持って行ったんだけど、みんなで食べるとすごく美味しく感じるんだよね。
+    for (i, (word, transitions)) in candidates.iter().enumerate() {
+        println!("\n{} ({}/{})", word, i + 1, candidates.len());
+        for (next, count) in transitions.iter().take(10) {
+            println!("  → {} ×{}", next, count);
+        }
+        if transitions.len() > 10 {
+            println!("  … {} more", transitions.len() - 10);
+        }
+        print!("[y] prune  [n] skip  [w] whitelist  [a] prune all the rest  [q] quit: ");
+        io::stdout().flush().ok();
 
-それは楽しそうだね！手作りのものって、何か特別感があって、さらに美味しく感じるよね。私もそんな時間を過ごしたくなったなぁ。
-本当にそうだよね。自然の中で、みんなで食べたり話したりする時間って、すごくリフレッシュできるんだよね。
+        let answer = lines.next()?.ok()?;
+        match answer.trim() {
+            "n" => review.skipped.push(word.to_string()),
+            "w" => review.whitelisted.push(word.to_string()),
+            "a" => {
+                pruned += candidates[i..].iter().map(|(_, t)| t.len()).sum::<usize>();
+                break;
+            }
+            "q" => return None,
+            _ => pruned += transitions.len(),
+        }
+    }
 
-最近、自然の中で過ごすことが少なくなっているから、また少し時間を作って外に出てみようかな。
-外に出ると、普段見逃している小さな美しさにも気づけるし、リフレッシュできるよね。君も少しでも時間を作って、自然を感じる時間を持つといいかも。
+    // **📌 最後にまとめて確認**
+    print!("\nPrune {} transitions? [y/N]: ", pruned);
+    io::stdout().flush().ok();
+    let answer = lines.next()?.ok()?;
+    (answer.trim() == "y").then_some(review)
+}
 
-Hello! How are you doing today? 😊 I hope you're having a great day! 🌟
-最近どうですか？元気ですか？😄 素敵な一日を過ごしていますように！🌸
-I just finished reading a fantastic book. 📚 Have you read anything interesting lately?
-最近、面白い本を読み終えました。📖 あなたは最近何か面白い本を読みましたか？
-The weather has been so nice lately! ☀️ Perfect for a walk in the park. 🚶‍♂️
-最近、天気がとても良いですね！☀️ 公園を散歩するのにぴったりです。🌳
-I love listening to music while working. 🎧 It helps me focus. How about you?
-仕事中に音楽を聴くのが好きです。🎶 集中するのに役立ちます。あなたはどうですか？
-最近、友達とカフェに行きました。☕ 美味しいケーキを食べて、とても楽しかったです！🍰
-I went to a new restaurant last weekend. 🍴 The food was amazing! 😋
-先週末、新しいレストランに行きました。🍽️ 料理がとても美味しかったです！😋
-Do you enjoy traveling? ✈️ I recently visited a beautiful place and took lots of photos. 📸
-旅行は好きですか？✈️ 最近、美しい場所を訪れて、たくさん写真を撮りました。📷
-Let's plan something fun for the weekend! 🎉 Maybe a picnic or a movie night? 🎥
-週末に何か楽しいことを計画しましょう！🎊 ピクニックや映画鑑賞はどうですか？🎬
-";
+/// Take what `source` taught back out of the model at `model` and save it to
+/// `out`. A tracked source is subtracted from its recorded counts; any other
+/// source listed in the manifest is read again and unlearned
+fn forget(config: &Config, model: &Path, source: &str, manifest: Option<&Path>, out: &Path) {
+    let record = Manifest::load(&Manifest::path_for(model)).ok();
+    let mut chain = MarkovChain::load(model).expect("Failed to load model");
+    let words = chain.words().count();
+    if let Some(touched) = chain.unlearn_source(source) {
+        println!(
+            "🧹 {}: {} transitions taken back, {} words forgotten",
+            source,
+            touched,
+            words.saturating_sub(chain.words().count())
+        );
+    } else {
+        // **📌 追跡していない出典はマニフェストのファイルを読み直して数え直す**
+        let Some(learned) = record
+            .iter()
+            .flat_map(|record| &record.sources)
+            .find(|s| s.is_named(source))
+        else {
+            eprintln!("⚠️ {} is not a source of {}", source, model.display());
+            std::process::exit(1);
+        };
+        // **📌 学習時と同じ内容でなければ数え直せない**
+        if hash_file(&learned.path).ok().as_ref() != Some(&learned.sha256) {
+            eprintln!(
+                "⚠️ {} changed since training; its counts cannot be taken back",
+                learned.path.display()
+            );
+            std::process::exit(1);
+        }
+        let mut plan = match manifest {
+            Some(path) => CorpusManifest::load(path).expect("Failed to read corpus manifest"),
+            None => CorpusManifest {
+                out: None,
+                order: None,
+                cleaning: Default::default(),
+                sources: Vec::new(),
+            },
+        };
+        // **📌 train --clean で学習したファイルは設定の [clean] で読み直す**
+        plan.cleaning
+            .insert(CLEAN_PROFILE.to_string(), config.cleaning());
+        if let Some(name) = &learned.cleaning {
+            if !plan.cleaning.contains_key(name) {
+                eprintln!(
+                    "⚠️ {} was cleaned with {:?}; pass the corpus.toml defining it with --manifest",
+                    learned.path.display(),
+                    name
+                );
+                std::process::exit(1);
+            }
+        }
+        let entry = SourceEntry {
+            path: learned.path.clone(),
+            kind: learned.kind,
+            tag: learned.tag.clone(),
+            weight: learned.weight,
+            cleaning: learned.cleaning.clone(),
+            speaker: learned.speaker.clone(),
+            pairs: learned.pairs,
+            track: false,
+            topic: learned.topic.clone(),
+        };
+        let corpus = plan
+            .unlearn(&entry, &mut chain, &config.corpus)
+            .expect("Failed to read corpus");
+        println!(
+            "🧹 {}: {} lines taken back, {} words forgotten",
+            learned.path.display(),
+            corpus.records.len(),
+            words.saturating_sub(chain.words().count())
+        );
+    }
+    chain.save(out).expect("Failed to save model");
+    println!("💾 {}", out.display());
 
-    chain.learn(text);
+    // **📌 学習時の記録からも消す（ハッシュも変わるので書き直す）**
+    if let Some(mut record) = record {
+        record.sources.retain(|s| !s.is_named(source));
+        let manifest_path = Manifest::path_for(out);
+        Manifest::new(out, chain.order(), chain.tokenizer(), record.sources)
+            .and_then(|manifest| manifest.save(&manifest_path))
+            .expect("Failed to write manifest");
+        println!("🧾 {}", manifest_path.display());
+    }
+}
 
-    println!("🔹 Wordora Markov ChatBot 🔹");
+/// Load the configured model, or train one from the built-in corpus
+fn load_chain(config: &Config) -> MarkovChain {
+    match &config.model.path {
+        Some(path) if path.exists() => MarkovChain::load(path).expect("Failed to load model"),
+        path => {
+            let mut chain = MarkovChain::new(config.model.order, config.tokenizer.clone());
+            chain.learn(DEFAULT_CORPUS);
+            if let Some(path) = path.as_ref().filter(|_| !config.model.read_only) {
+                chain.save(path).expect("Failed to save model");
+            }
+            chain
+        }
+    }
+}
 
-    loop {
-        print!("あなた: ");
-        io::stdout().flush().unwrap();
+/// Learn the lines read from stdin until it closes or Ctrl-C is pressed; the
+/// caller saves the model afterwards. With `save_every`, it is also saved after
+/// that many lines and, with `autosave`, that often while lines are unsaved
+fn learn_stdin(
+    chain: &mut MarkovChain,
+    limits: &CorpusLimits,
+    save_every: Option<usize>,
+    autosave: Option<Duration>,
+    out: &Path,
+    level: Option<i32>,
+) -> io::Result<usize> {
+    // **📌 1回目の Ctrl-C は読み込みを止めて保存、2回目はすぐに終了**
+    let interrupted = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register_conditional_shutdown(SIGINT, 130, interrupted.clone())?;
+    signal_hook::flag::register(SIGINT, interrupted.clone())?;
 
-        let mut input = String::new();
-        io::stdin()
-            .read_line(&mut input)
-            .expect("Failed to read input");
-        let input = input.trim();
+    // **📌 Ctrl-C や自動保存の時間に気づけるよう、標準入力は別のスレッドで読む**
+    let (send, lines) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = io::stdin().lock();
+        loop {
+            let mut buf = Vec::new();
+            let read = reader.read_until(b'\n', &mut buf);
+            if matches!(read, Ok(0)) || send.send(read.map(|_| buf)).is_err() {
+                break;
+            }
+        }
+    });
 
-        if input == "exit" {
-            break;
+    let mut learned = 0;
+    let mut unsaved = 0;
+    let mut saved_at = Instant::now();
+    let mut line = 0;
+    while !interrupted.load(Ordering::Relaxed) {
+        if save_every.is_some()
+            && unsaved > 0
+            && autosave.is_some_and(|every| saved_at.elapsed() >= every)
+        {
+            save_learned(chain, out, level, learned)?;
+            (unsaved, saved_at) = (0, Instant::now());
         }
+        let buf = match lines.recv_timeout(Duration::from_millis(200)) {
+            Ok(buf) => buf?,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        line += 1;
+        match parse_record(&buf, limits) {
+            Ok(record) if record.trim().is_empty() => continue,
+            Ok(record) => chain.learn(&record),
+            Err(reason) if limits.strict => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("stdin:{}: {}", line, reason),
+                ));
+            }
+            Err(reason) => {
+                eprintln!("  ⚠️ line {}: {}", line, reason);
+                continue;
+            }
+        }
+        learned += 1;
+        unsaved += 1;
+        if save_every.is_some_and(|every| learned % every == 0) {
+            save_learned(chain, out, level, learned)?;
+            (unsaved, saved_at) = (0, Instant::now());
+        }
+    }
+    if interrupted.load(Ordering::Relaxed) {
+        println!("🛑 interrupted, saving what was learned");
+    }
+    Ok(learned)
+}
 
-        let mut tokens = separate_tokens(input);
-        tokens = chunk_string(tokens.join("").as_str(), 3);
-        let start_word = tokens
-            .first()
-            .cloned()
-            .unwrap_or_else(|| "".to_string());
+fn save_learned(
+    chain: &MarkovChain,
+    out: &Path,
+    level: Option<i32>,
+    learned: usize,
+) -> io::Result<()> {
+    chain.save_with_level(out, level)?;
+    println!("💾 {} ({} lines)", out.display(), learned);
+    Ok(())
+}
 
-        let response = chain.generate(&start_word, 20);
-        println!("Bot: {}", response);
+/// Stack the configured overlays on top of the base model
+fn load_layers(config: &Config, base: MarkovChain) -> LayeredModel {
+    // **📌 生成の前に遷移の表をまとめて作っておく**
+    base.finalize();
+    let mut model = LayeredModel::new(Arc::new(base));
+    for path in &config.model.overlays {
+        let chain = MarkovChain::load(path).expect("Failed to load overlay");
+        chain.finalize();
+        model.push_overlay(&path.to_string_lossy(), Some(chain));
     }
+    model.set_read_only(config.model.read_only);
+    model
 }
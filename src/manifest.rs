@@ -0,0 +1,158 @@
+use crate::{corpus::SourceKind, markov::MarkovChain, tokenizer::TokenizerConfig};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+// **📌 Manifest構造体：モデルを再現するための記録**
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of wordora that trained the model
+    pub version: String,
+    /// SHA-256 of the saved model file
+    pub model_sha256: String,
+    pub order: usize,
+    pub tokenizer: TokenizerConfig,
+    /// Corpus files in the order they were learned
+    pub sources: Vec<Source>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Source {
+    pub path: PathBuf,
+    pub sha256: String,
+    /// Records learned from the file
+    pub records: usize,
+    /// Malformed records skipped
+    pub skipped: usize,
+    /// Label given to the source in the corpus manifest
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Count each record was learned with
+    #[serde(default = "Source::default_weight")]
+    pub weight: f64,
+    /// How the file was read, so its counts can be taken back out of the
+    /// model with `forget --source`
+    #[serde(default, rename = "type")]
+    pub kind: SourceKind,
+    #[serde(default)]
+    pub speaker: Option<String>,
+    #[serde(default)]
+    pub pairs: bool,
+    /// Name of the corpus manifest's cleaning profile applied to the file
+    #[serde(default)]
+    pub cleaning: Option<String>,
+    /// Topic the file was learned under, before any `#topic` lines
+    #[serde(default)]
+    pub topic: Option<String>,
+}
+
+impl Source {
+    /// Source learned with weight 1 and no tag, hashing the file
+    pub fn new(path: &Path, records: usize, skipped: usize) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            sha256: hash_file(path)?,
+            records,
+            skipped,
+            tag: None,
+            weight: Self::default_weight(),
+            kind: SourceKind::default(),
+            speaker: None,
+            pairs: false,
+            cleaning: None,
+            topic: None,
+        })
+    }
+
+    fn default_weight() -> f64 {
+        1.0
+    }
+
+    /// Whether `name` is this source's path or tag
+    pub fn is_named(&self, name: &str) -> bool {
+        self.path == Path::new(name) || self.tag.as_deref() == Some(name)
+    }
+}
+
+impl Manifest {
+    /// Manifest for a model trained from `sources`, hashing the model file
+    pub fn new(
+        model: &Path,
+        order: usize,
+        tokenizer: &TokenizerConfig,
+        sources: Vec<Source>,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            model_sha256: hash_file(model)?,
+            order,
+            tokenizer: tokenizer.clone(),
+            sources,
+        })
+    }
+
+    /// Where the manifest of a model is kept: next to it, as `<model>.manifest.json`
+    pub fn path_for(model: &Path) -> PathBuf {
+        let mut name = model.as_os_str().to_owned();
+        name.push(".manifest.json");
+        PathBuf::from(name)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, json + "\n")
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Check the model and the corpus files against the manifest.
+    ///
+    /// Returns one line per mismatch; an empty list means the model verifies.
+    pub fn verify(&self, model: &Path) -> io::Result<Vec<String>> {
+        let mut problems = Vec::new();
+
+        if hash_file(model)? != self.model_sha256 {
+            problems.push(format!("{}: model file differs", model.display()));
+        }
+        let chain = MarkovChain::load(model)?;
+        if chain.order() != self.order {
+            problems.push(format!(
+                "order is {}, manifest says {}",
+                chain.order(),
+                self.order
+            ));
+        }
+        if *chain.tokenizer() != self.tokenizer {
+            problems.push("tokenizer config differs".to_string());
+        }
+        if self.version != env!("CARGO_PKG_VERSION") {
+            problems.push(format!(
+                "trained with version {}, this is {}",
+                self.version,
+                env!("CARGO_PKG_VERSION")
+            ));
+        }
+
+        // **📌 コーパスのファイルが学習時と同じか**
+        for source in &self.sources {
+            match hash_file(&source.path) {
+                Ok(hash) if hash == source.sha256 => {}
+                Ok(_) => problems.push(format!("{}: corpus file differs", source.path.display())),
+                Err(e) => problems.push(format!("{}: {}", source.path.display(), e)),
+            }
+        }
+        Ok(problems)
+    }
+}
+
+/// SHA-256 of a file as lowercase hex
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let digest = Sha256::digest(fs::read(path)?);
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
@@ -0,0 +1,275 @@
+use crate::{
+    markov::{GenerateOptions, MarkovChain, TokenStream},
+    tokenizer::TokenizerConfig,
+};
+use memmap2::Mmap;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+    fs, io,
+    path::Path,
+};
+
+// **📌 ファイルの形式（数値はすべてリトルエンディアン）**
+//
+// header       magic, version u32, order u32, strings u64, transitions u64, tokenizer length u64
+// tokenizer    TokenizerConfig as JSON
+// offsets      (strings + 1) × u64, start of each string in the string bytes
+// bytes        every token and context key, sorted, back to back
+// rows         (strings + 1) × u64, first transition of each string
+// targets      transitions × u32, id of the following token
+// counts       transitions × f64
+
+const MAGIC: &[u8; 8] = b"WDRMMAP\0";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 40;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// **📌 MappedModel構造体：メモリマップしたまま生成に使う読み取り専用モデル**
+pub struct MappedModel {
+    map: Mmap,
+    order: usize,
+    tokenizer: TokenizerConfig,
+    strings: usize,
+    offsets: usize,
+    bytes: usize,
+    rows: usize,
+    targets: usize,
+    counts: usize,
+}
+
+impl MappedModel {
+    /// Write `chain` in the mapped format
+    pub fn write(chain: &MarkovChain, path: &Path) -> io::Result<()> {
+        let entries: Vec<_> = chain.words().chain(chain.contexts()).collect();
+
+        // **📌 キーと遷移先の文字列をまとめて並べる**
+        let mut strings: BTreeSet<&str> = BTreeSet::new();
+        for entry in &entries {
+            strings.insert(&entry.word);
+            strings.extend(entry.transitions.keys().map(String::as_str));
+        }
+        let ids: HashMap<&str, u32> = strings
+            .iter()
+            .enumerate()
+            .map(|(id, s)| (*s, id as u32))
+            .collect();
+        let rows: HashMap<&str, Vec<(u32, f64)>> = entries
+            .iter()
+            .map(|entry| {
+                let mut row: Vec<(u32, f64)> = entry
+                    .transitions
+                    .iter()
+                    .map(|(next, count)| (ids[next.as_str()], *count))
+                    .collect();
+                row.sort_by_key(|(id, _)| *id);
+                (entry.word.as_str(), row)
+            })
+            .collect();
+        let transitions: usize = rows.values().map(Vec::len).sum();
+        let tokenizer = serde_json::to_vec(chain.tokenizer()).map_err(io::Error::other)?;
+
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&(chain.order() as u32).to_le_bytes());
+        out.extend_from_slice(&(strings.len() as u64).to_le_bytes());
+        out.extend_from_slice(&(transitions as u64).to_le_bytes());
+        out.extend_from_slice(&(tokenizer.len() as u64).to_le_bytes());
+        out.extend_from_slice(&tokenizer);
+
+        let mut offset = 0u64;
+        for s in &strings {
+            out.extend_from_slice(&offset.to_le_bytes());
+            offset += s.len() as u64;
+        }
+        out.extend_from_slice(&offset.to_le_bytes());
+        for s in &strings {
+            out.extend_from_slice(s.as_bytes());
+        }
+
+        let mut first = 0u64;
+        for s in &strings {
+            out.extend_from_slice(&first.to_le_bytes());
+            first += rows.get(s).map_or(0, Vec::len) as u64;
+        }
+        out.extend_from_slice(&first.to_le_bytes());
+        for s in &strings {
+            for (id, _) in rows.get(s).into_iter().flatten() {
+                out.extend_from_slice(&id.to_le_bytes());
+            }
+        }
+        for s in &strings {
+            for (_, count) in rows.get(s).into_iter().flatten() {
+                out.extend_from_slice(&count.to_le_bytes());
+            }
+        }
+        fs::write(path, out)
+    }
+
+    /// Map a file written by [`write`](Self::write); only the header is read up front
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = fs::File::open(path)?;
+        // SAFETY: the file is only read, and every access is bounds-checked
+        // against the map, so a truncated or corrupt file cannot read past it.
+        // Modifying the file while it is mapped is not supported.
+        let map = unsafe { Mmap::map(&file)? };
+
+        if map.len() < HEADER_LEN || &map[..8] != MAGIC {
+            return Err(invalid("not a mapped wordora model"));
+        }
+        let u32_at = |pos: usize| u32::from_le_bytes(map[pos..pos + 4].try_into().unwrap());
+        let u64_at = |pos: usize| u64::from_le_bytes(map[pos..pos + 8].try_into().unwrap());
+        if u32_at(8) != VERSION {
+            return Err(invalid("unsupported mapped model version"));
+        }
+        let order = u32_at(12) as usize;
+        let strings = u64_at(16) as usize;
+        let transitions = u64_at(24) as usize;
+        let tokenizer_len = u64_at(32) as usize;
+        if strings.max(transitions).max(tokenizer_len) > map.len() {
+            return Err(invalid("mapped model has the wrong length"));
+        }
+
+        let tokenizer_end = HEADER_LEN + tokenizer_len;
+        let tokenizer = map
+            .get(HEADER_LEN..tokenizer_end)
+            .ok_or_else(|| invalid("truncated mapped model"))?;
+        let tokenizer: TokenizerConfig = serde_json::from_slice(tokenizer)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let offsets = tokenizer_end;
+        let bytes = offsets + (strings + 1) * 8;
+        let mut model = Self {
+            map,
+            order: order.max(1),
+            tokenizer,
+            strings,
+            offsets,
+            bytes,
+            rows: 0,
+            targets: 0,
+            counts: 0,
+        };
+        let bytes_len = model
+            .u64_at(offsets + strings * 8)
+            .filter(|len| *len <= model.map.len() as u64)
+            .ok_or_else(|| invalid("truncated mapped model"))? as usize;
+        model.rows = bytes + bytes_len;
+        model.targets = model.rows + (strings + 1) * 8;
+        model.counts = model.targets + transitions * 4;
+        if model.counts + transitions * 8 != model.map.len() {
+            return Err(invalid("mapped model has the wrong length"));
+        }
+        Ok(model)
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    pub fn tokenizer(&self) -> &TokenizerConfig {
+        &self.tokenizer
+    }
+
+    /// Whether `word` is a known token
+    pub fn contains(&self, word: &str) -> bool {
+        self.find(word).is_some()
+    }
+
+    /// Following tokens of a token or space-joined context, with their counts
+    pub fn transitions(&self, key: &str) -> Option<Vec<(&str, f64)>> {
+        let id = self.find(key)?;
+        let (first, end) = (
+            self.u64_at(self.rows + id * 8)?,
+            self.u64_at(self.rows + id * 8 + 8)?,
+        );
+        let row: Vec<(&str, f64)> = (first as usize..end as usize)
+            .map_while(|i| {
+                let target = self.u32_at(self.targets + i * 4)? as usize;
+                let count = f64::from_bits(self.u64_at(self.counts + i * 8)?);
+                Some((self.string(target)?, count))
+            })
+            .collect();
+        (!row.is_empty()).then_some(row)
+    }
+
+    /// Yield generated tokens one at a time, like [`MarkovChain::generate_iter`].
+    ///
+    /// The length, temperature, boost, ending weight, fallback, punctuation,
+    /// repetition and loop options apply, and so does the blocklist. The
+    /// strategy, smoothing, interpolation, aliases, metadata exclusions and
+    /// keyword mode do not.
+    pub fn generate_iter<'a>(
+        &'a self,
+        start_word: &str,
+        opts: &GenerateOptions,
+    ) -> impl Iterator<Item = String> + 'a {
+        TokenStream::new(start_word, opts, self.order, move |context| {
+            self.distribution(context)
+        })
+    }
+
+    // **📝 文章を生成**
+    pub fn generate(&self, start_word: &str, opts: &GenerateOptions) -> String {
+        opts.limits.retry(|| {
+            self.generate_iter(start_word, opts)
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+    }
+
+    /// Next-token probabilities after the longest known context
+    fn distribution(&self, context: &[String]) -> Option<Vec<(&str, f64)>> {
+        let context = &context[context.len().saturating_sub(self.order)..];
+        let row = (1..=context.len())
+            .rev()
+            .find_map(|n| self.transitions(&context[context.len() - n..].join(" ")))?;
+        let total: f64 = row.iter().map(|(_, count)| count).sum();
+        Some(
+            row.into_iter()
+                .map(|(next, count)| (next, count / total))
+                .collect(),
+        )
+    }
+
+    /// Binary search of the sorted string table
+    fn find(&self, key: &str) -> Option<usize> {
+        let (mut low, mut high) = (0, self.strings);
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.string_bytes(mid)?.cmp(key.as_bytes()) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    fn string(&self, id: usize) -> Option<&str> {
+        std::str::from_utf8(self.string_bytes(id)?).ok()
+    }
+
+    fn string_bytes(&self, id: usize) -> Option<&[u8]> {
+        let start = self.u64_at(self.offsets + id * 8)? as usize;
+        let end = self.u64_at(self.offsets + id * 8 + 8)? as usize;
+        self.map
+            .get(self.bytes.checked_add(start)?..self.bytes.checked_add(end)?)
+    }
+
+    fn u32_at(&self, pos: usize) -> Option<u32> {
+        Some(u32::from_le_bytes(
+            self.map.get(pos..pos + 4)?.try_into().ok()?,
+        ))
+    }
+
+    fn u64_at(&self, pos: usize) -> Option<u64> {
+        Some(u64::from_le_bytes(
+            self.map.get(pos..pos + 8)?.try_into().ok()?,
+        ))
+    }
+}
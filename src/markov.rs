@@ -0,0 +1,2788 @@
+use crate::{
+    blocklist::Blocklist,
+    compression::Compression,
+    error::{self, WordoraError},
+    fuzzy,
+    tokenizer::{TokenType, TokenizerConfig},
+    validate::{Issue, Table, ValidationReport},
+};
+use rand::{
+    distr::{weighted::WeightedIndex, Distribution},
+    rngs::ThreadRng,
+    Rng,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet},
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+// **📌 Word構造体：単語と遷移を管理**
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Word {
+    pub word: String,
+    /// Script class of the token
+    pub kind: TokenType,
+    /// Following tokens and how often each one was seen
+    pub transitions: HashMap<String, f64>,
+    /// Free-form annotations such as a reading, part of speech or an NSFW flag
+    #[serde(default)]
+    pub meta: BTreeMap<String, String>,
+    #[serde(skip)]
+    cache: DistributionCache,
+}
+
+impl Word {
+    pub fn new(word: String) -> Self {
+        Self {
+            kind: TokenType::of(&word),
+            word,
+            transitions: HashMap::new(),
+            meta: BTreeMap::new(),
+            cache: DistributionCache::default(),
+        }
+    }
+
+    pub fn meta(&self, key: &str) -> Option<&str> {
+        self.meta.get(key).map(String::as_str)
+    }
+
+    pub fn add_transition(&mut self, new_transition: String) {
+        self.add_count(new_transition, 1.0);
+    }
+
+    /// Add `count` to the transition to `next`; a negative count unlearns it,
+    /// removing the transition once nothing is left of it
+    fn add_count(&mut self, next: String, count: f64) {
+        match self.transitions.entry(next) {
+            Entry::Occupied(mut seen) => {
+                *seen.get_mut() += count;
+                if count < 0.0 && *seen.get() < FORGET_BELOW {
+                    seen.remove();
+                }
+            }
+            Entry::Vacant(unseen) => {
+                if count > 0.0 {
+                    unseen.insert(count);
+                }
+            }
+        }
+        self.invalidate();
+    }
+
+    /// Drop the cached distribution; call this after changing `transitions`
+    /// directly, as [`add_transition`](Self::add_transition) does
+    pub fn invalidate(&mut self) {
+        self.cache = DistributionCache::default();
+    }
+
+    /// Build the cached distribution now instead of on first use
+    pub fn finalize(&self) {
+        self.cached();
+    }
+
+    /// How often this word was followed by another token
+    pub fn count(&self) -> f64 {
+        self.transitions.values().sum()
+    }
+
+    /// Probability of `next` following this word
+    pub fn probability(&self, next: &str) -> f64 {
+        match self.transitions.get(next) {
+            Some(&count) => count / self.count(),
+            None => 0.0,
+        }
+    }
+
+    /// Transition probabilities, normalized to sum to 1
+    pub fn distribution(&self) -> Vec<(&str, f64)> {
+        self.cached()
+            .next
+            .iter()
+            .map(|(word, p)| (word.as_str(), *p))
+            .collect()
+    }
+
+    /// Sample a following token by its probability, without any of the
+    /// reweighting of [`GenerateOptions`]
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Option<&str> {
+        let cached = self.cached();
+        let index = cached.index.as_ref()?;
+        Some(&cached.next[index.sample(rng)].0)
+    }
+
+    /// Whether the cached distribution, if one was built, still matches the
+    /// counts
+    fn cache_matches(&self) -> bool {
+        let Some(cached) = self.cache.0.get() else {
+            return true;
+        };
+        let total = self.count();
+        cached.next.len() == self.transitions.len()
+            && cached.next.iter().all(|(word, p)| {
+                self.transitions
+                    .get(word)
+                    .is_some_and(|count| close(count / total, *p))
+            })
+    }
+
+    fn cached(&self) -> &CachedDistribution {
+        self.cache.0.get_or_init(|| {
+            let total: f64 = self.transitions.values().sum();
+            let next: Vec<(String, f64)> = self
+                .transitions
+                .iter()
+                .map(|(word, &count)| (word.clone(), count / total))
+                .collect();
+            let index = WeightedIndex::new(next.iter().map(|(_, p)| *p)).ok();
+            CachedDistribution { next, index }
+        })
+    }
+}
+
+// **📌 CachedDistribution構造体：正規化した遷移と重み付き選択の表**
+#[derive(Debug)]
+struct CachedDistribution {
+    next: Vec<(String, f64)>,
+    /// `None` when there is nothing to sample
+    index: Option<WeightedIndex<f64>>,
+}
+
+/// A word's distribution, built on first use and dropped whenever its counts
+/// change. A copied word starts without one
+#[derive(Debug, Default)]
+struct DistributionCache(OnceLock<CachedDistribution>);
+
+impl Clone for DistributionCache {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+// **📌 Strategy：次のトークンの選び方**
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Sample each token at random by its probability
+    #[default]
+    Sample,
+    /// Keep the `beam_width` most probable continuations and return the best
+    /// complete sentence, the same every time
+    Beam,
+}
+
+impl std::str::FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sample" => Ok(Strategy::Sample),
+            "beam" => Ok(Strategy::Beam),
+            _ => Err(format!("unknown strategy {:?} (sample, beam)", s)),
+        }
+    }
+}
+
+// **📌 GenerateOptions構造体：生成の設定を管理**
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenerateOptions {
+    /// Number of tokens appended after the seed
+    pub length: usize,
+    /// Sampling temperature; below 1.0 favors frequent transitions
+    pub temperature: f64,
+    pub strategy: Strategy,
+    /// Continuations kept per step by the beam strategy
+    pub beam_width: usize,
+    /// Seed used when the current word has no transitions
+    pub fallback_word: String,
+    pub fallback_length: usize,
+    /// Add-k pseudo-count for unseen transitions; 0 disables smoothing
+    pub smoothing: f64,
+    /// Never emit two punctuation tokens in a row
+    pub no_double_punctuation: bool,
+    /// Drop punctuation at the start of a reply
+    pub no_leading_punctuation: bool,
+    /// Never emit the token just emitted, unless nothing else can follow
+    pub no_immediate_repeat: bool,
+    /// Weight kept by a candidate per time its n-gram already occurs among the
+    /// last `repetition_window` tokens; 1 disables the penalty
+    pub repetition_penalty: f64,
+    pub repetition_window: usize,
+    /// Length of the n-grams the penalty counts, including the candidate
+    pub repetition_ngram: usize,
+    /// Longest cycle (e.g. `です ね です ね` is 2) a candidate may not close; the
+    /// reply ends when every candidate would. 0 disables loop detection
+    pub loop_window: usize,
+    /// Never emit tokens annotated with any of these metadata keys
+    pub exclude_meta: Vec<String>,
+    /// Groups of synonyms such as `["映画", "ムービー"]` whose transitions are
+    /// pooled when one of them is the current word
+    pub aliases: Vec<Vec<String>>,
+    /// Build the reply around a keyword from the input instead of starting with it
+    pub keyword: bool,
+    /// Linear interpolation across orders: each known context keeps this share
+    /// of the probability and passes the rest down to the next shorter one.
+    /// Unset uses only the longest known context
+    pub interpolation: Option<f64>,
+    /// Factor applied to the probability of sentence-ending tokens such as `!`
+    /// or an emoji, shifting the tone without another corpus
+    pub endings: HashMap<String, f64>,
+    /// Bounds on the length of a reply, retried until it fits
+    pub limits: GenerationParams,
+    /// Extra weight per token, e.g. for words from the recent conversation
+    #[serde(skip)]
+    pub boost: HashMap<String, f64>,
+    /// Words never generated, loaded from `blocklist.path`
+    #[serde(skip)]
+    pub blocklist: Arc<Blocklist>,
+}
+
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        Self {
+            length: 20,
+            temperature: 1.0,
+            strategy: Strategy::Sample,
+            beam_width: 8,
+            fallback_word: "。".to_string(),
+            fallback_length: 20,
+            smoothing: 0.0,
+            no_double_punctuation: true,
+            no_leading_punctuation: true,
+            no_immediate_repeat: true,
+            repetition_penalty: 1.0,
+            repetition_window: 16,
+            repetition_ngram: 2,
+            loop_window: 4,
+            exclude_meta: Vec::new(),
+            aliases: Vec::new(),
+            keyword: false,
+            interpolation: None,
+            endings: HashMap::new(),
+            limits: GenerationParams::default(),
+            boost: HashMap::new(),
+            blocklist: Arc::default(),
+        }
+    }
+}
+
+impl GenerateOptions {
+    /// Check the options can be used for generation
+    pub fn validate(&self) -> Result<(), String> {
+        if self.length == 0 {
+            return Err("length must be at least 1".to_string());
+        }
+        if self.temperature.is_nan() || self.temperature <= 0.0 {
+            return Err("temperature must be positive".to_string());
+        }
+        if self.beam_width == 0 {
+            return Err("beam_width must be at least 1".to_string());
+        }
+        if self.smoothing.is_nan() || self.smoothing < 0.0 {
+            return Err("smoothing must not be negative".to_string());
+        }
+        if self
+            .interpolation
+            .is_some_and(|lambda| !(lambda > 0.0 && lambda <= 1.0))
+        {
+            return Err("interpolation must be above 0 and at most 1".to_string());
+        }
+        if !(self.repetition_penalty > 0.0 && self.repetition_penalty <= 1.0) {
+            return Err("repetition_penalty must be above 0 and at most 1".to_string());
+        }
+        if self.repetition_ngram == 0 {
+            return Err("repetition_ngram must be at least 1".to_string());
+        }
+        if self
+            .endings
+            .values()
+            .any(|weight| !(weight.is_finite() && *weight >= 0.0))
+        {
+            return Err("endings weights must not be negative".to_string());
+        }
+        self.limits.validate()?;
+        Ok(())
+    }
+
+    /// Options for regenerating from the fallback word
+    fn fallback(&self) -> Self {
+        Self {
+            length: self.fallback_length,
+            ..self.clone()
+        }
+    }
+}
+
+// **📌 GenerationParams構造体：返事の長さの範囲と作り直す回数**
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenerationParams {
+    /// Fewest tokens a reply may have, seed included; 0 allows any
+    pub min_tokens: usize,
+    /// Most tokens a reply may have, seed included; unset allows any
+    pub max_tokens: Option<usize>,
+    /// Times a reply outside the window is generated again before the
+    /// closest attempt is used
+    pub max_retries: usize,
+}
+
+impl Default for GenerationParams {
+    fn default() -> Self {
+        Self {
+            min_tokens: 0,
+            max_tokens: None,
+            max_retries: 4,
+        }
+    }
+}
+
+impl GenerationParams {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(max) = self.max_tokens {
+            if max == 0 {
+                return Err("limits: max_tokens must be at least 1".to_string());
+            }
+            if max < self.min_tokens {
+                return Err("limits: max_tokens must not be below min_tokens".to_string());
+            }
+        }
+        if self.max_retries > 100 {
+            return Err("limits: max_retries must be at most 100".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.min_tokens > 0 || self.max_tokens.is_some()
+    }
+
+    /// Tokens a reply of `tokens` tokens is short of or over the window
+    fn distance(&self, tokens: usize) -> usize {
+        let max = self.max_tokens.unwrap_or(usize::MAX);
+        self.min_tokens.saturating_sub(tokens) + tokens.saturating_sub(max)
+    }
+
+    /// Call `generate` until a reply fits the window, at most `max_retries`
+    /// more times; the attempt closest to it when none did
+    pub fn retry<F: FnMut() -> String>(&self, mut generate: F) -> String {
+        let mut best: Option<(usize, String)> = None;
+        for _ in 0..=self.max_retries {
+            let reply = generate();
+            let distance = self.distance(reply.split_whitespace().count());
+            if distance == 0 {
+                return reply;
+            }
+            if best.as_ref().is_none_or(|(closest, _)| distance < *closest) {
+                best = Some((distance, reply));
+            }
+        }
+        best.map(|(_, reply)| reply).unwrap_or_default()
+    }
+}
+
+// **📌 Contribution構造体：1つの情報源が足した回数**
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct Contribution {
+    /// Context, as tokens joined with spaces, to next token and count
+    transitions: HashMap<String, HashMap<String, f64>>,
+    /// Prompt token to answer start and count, for response pairs
+    responses: HashMap<String, HashMap<String, f64>>,
+}
+
+impl Contribution {
+    fn add(table: &mut HashMap<String, HashMap<String, f64>>, key: String, next: &str, count: f64) {
+        *table
+            .entry(key)
+            .or_default()
+            .entry(next.to_string())
+            .or_insert(0.0) += count;
+    }
+
+    /// Count the transitions of tokenized text, for each context length up to
+    /// `order`
+    fn add_text(&mut self, tokens: &[String], order: usize, weight: f64) {
+        for at in 0..tokens.len().saturating_sub(1) {
+            for n in 1..=order.min(at + 1) {
+                let key = tokens[at + 1 - n..=at].join(" ");
+                Self::add(&mut self.transitions, key, &tokens[at + 1], weight);
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.transitions.is_empty() && self.responses.is_empty()
+    }
+
+    fn scale(&mut self, factor: f64) {
+        for table in [&mut self.transitions, &mut self.responses] {
+            for nexts in table.values_mut() {
+                nexts.retain(|_, count| {
+                    *count *= factor;
+                    *count >= FORGET_BELOW
+                });
+            }
+            table.retain(|_, nexts| !nexts.is_empty());
+        }
+    }
+
+    fn merge(&mut self, other: &Contribution) {
+        for (table, other_table) in [
+            (&mut self.transitions, &other.transitions),
+            (&mut self.responses, &other.responses),
+        ] {
+            for (key, nexts) in other_table {
+                for (next, count) in nexts {
+                    Self::add(table, key.clone(), next, *count);
+                }
+            }
+        }
+    }
+}
+
+// **📌 Evaluation構造体：テキストの採点結果**
+#[derive(Debug, Clone, Copy)]
+pub struct Evaluation {
+    pub tokens: usize,
+    /// Tokens missing from the vocabulary
+    pub unknown: usize,
+    /// Natural-log probability of the whole text
+    pub log_prob: f64,
+    pub perplexity: f64,
+}
+
+// **📌 MarkovChain構造体：単語と遷移を学習・生成**
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MarkovChain {
+    order: usize,
+    tokenizer: TokenizerConfig,
+    words: HashMap<String, Word>,
+    /// Contexts of 2..=order tokens, keyed by the tokens joined with spaces
+    contexts: HashMap<String, Word>,
+    /// Reverse index: which tokens precede each token
+    reverse: HashMap<String, Word>,
+    /// Response pairs: for each token of an utterance, the tokens the next
+    /// utterance started with; see [`learn_pair`](Self::learn_pair)
+    #[serde(default)]
+    responses: HashMap<String, Word>,
+    /// What each tracked source added, by source ID; see
+    /// [`learn_from`](Self::learn_from)
+    #[serde(default)]
+    provenance: HashMap<String, Contribution>,
+    /// What was learned under each topic tag, by topic; see
+    /// [`learn_topic`](Self::learn_topic)
+    #[serde(default)]
+    topics: HashMap<String, Contribution>,
+    /// Weighted number of texts learned, and of them the ones containing each
+    /// token; see [`salience`](Self::salience)
+    #[serde(default)]
+    documents: f64,
+    #[serde(default)]
+    document_frequency: HashMap<String, f64>,
+    /// Sorted words with at least one transition, for fuzzy seed lookup
+    #[serde(skip)]
+    index: BTreeSet<String>,
+    /// Learning, unlearning and merging are refused; see [`set_read_only`](Self::set_read_only)
+    #[serde(skip)]
+    read_only: bool,
+}
+
+impl MarkovChain {
+    pub fn new(order: usize, tokenizer: TokenizerConfig) -> Self {
+        Self {
+            order: order.max(1),
+            tokenizer,
+            words: HashMap::new(),
+            contexts: HashMap::new(),
+            reverse: HashMap::new(),
+            responses: HashMap::new(),
+            provenance: HashMap::new(),
+            topics: HashMap::new(),
+            documents: 0.0,
+            document_frequency: HashMap::new(),
+            index: BTreeSet::new(),
+            read_only: false,
+        }
+    }
+
+    /// Freeze the chain: every method that would change its counts or
+    /// annotations does nothing from then on
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    pub fn tokenizer(&self) -> &TokenizerConfig {
+        &self.tokenizer
+    }
+
+    /// Look up a single token
+    pub fn word(&self, word: &str) -> Option<&Word> {
+        self.words.get(word)
+    }
+
+    /// All single-token entries
+    pub fn words(&self) -> impl Iterator<Item = &Word> {
+        self.words.values()
+    }
+
+    /// All entries of 2..=order tokens, keyed by the tokens joined with spaces
+    pub fn contexts(&self) -> impl Iterator<Item = &Word> {
+        self.contexts.values()
+    }
+
+    /// Response-pair entries, keyed by a prompt token
+    pub(crate) fn responses(&self) -> impl Iterator<Item = &Word> {
+        self.responses.values()
+    }
+
+    /// Add a token with its annotations and document frequency, as when
+    /// reading a model back in
+    pub(crate) fn insert_token(
+        &mut self,
+        token: &str,
+        meta: BTreeMap<String, String>,
+        document_frequency: f64,
+    ) {
+        self.words
+            .entry(token.to_string())
+            .or_insert_with(|| Word::new(token.to_string()))
+            .meta
+            .extend(meta);
+        if document_frequency > 0.0 {
+            self.document_frequency
+                .insert(token.to_string(), document_frequency);
+        }
+    }
+
+    /// Add `count` to the transition from `context` (1..=order tokens) to
+    /// `next`, keeping the reverse index in step; call
+    /// [`rebuild_index`](Self::rebuild_index) when done
+    pub(crate) fn insert_transition(&mut self, context: &[String], next: &str, count: f64) {
+        let key = context.join(" ");
+        if let [single] = context {
+            self.reverse
+                .entry(next.to_string())
+                .or_insert_with(|| Word::new(next.to_string()))
+                .add_count(single.clone(), count);
+        }
+        let table = if context.len() == 1 {
+            &mut self.words
+        } else {
+            &mut self.contexts
+        };
+        table
+            .entry(key.clone())
+            .or_insert_with(|| Word::new(key))
+            .add_count(next.to_string(), count);
+    }
+
+    /// Add `count` to the response pair from `token` to the reply start `start`
+    pub(crate) fn insert_response(&mut self, token: &str, start: &str, count: f64) {
+        self.responses
+            .entry(token.to_string())
+            .or_insert_with(|| Word::new(token.to_string()))
+            .add_count(start.to_string(), count);
+    }
+
+    pub(crate) fn set_documents(&mut self, documents: f64) {
+        self.documents = documents;
+    }
+
+    /// Script class of a known token
+    pub fn token_type(&self, word: &str) -> Option<TokenType> {
+        self.words.get(word).map(|word| word.kind)
+    }
+
+    /// Annotate a known token; returns false if the token was never seen
+    pub fn set_meta(&mut self, word: &str, key: &str, value: &str) -> bool {
+        if self.read_only {
+            return false;
+        }
+        match self.words.get_mut(word) {
+            Some(word) => {
+                word.meta.insert(key.to_string(), value.to_string());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn meta(&self, word: &str, key: &str) -> Option<&str> {
+        self.words.get(word)?.meta(key)
+    }
+
+    pub fn remove_meta(&mut self, word: &str, key: &str) -> Option<String> {
+        if self.read_only {
+            return None;
+        }
+        self.words.get_mut(word)?.meta.remove(key)
+    }
+
+    /// Tokens annotated with `key`, optionally only those with the given value
+    pub fn words_with_meta<'a>(
+        &'a self,
+        key: &'a str,
+        value: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a Word> {
+        self.words.values().filter(move |word| {
+            word.meta(key)
+                .is_some_and(|v| value.is_none_or(|value| v == value))
+        })
+    }
+
+    /// Whether generation can continue from this token
+    pub fn contains(&self, word: &str) -> bool {
+        self.words
+            .get(word)
+            .is_some_and(|word| !word.transitions.is_empty())
+    }
+
+    // **🔍 学習**
+    pub fn learn(&mut self, text: &str) {
+        self.learn_weighted(text, 1.0);
+    }
+
+    /// Learn `text` as if it had been seen `weight` times
+    pub fn learn_weighted(&mut self, text: &str, weight: f64) {
+        if self.read_only {
+            return;
+        }
+        let separated = self.tokenizer.tokenize(text);
+        self.learn_tokens(&separated, weight);
+    }
+
+    /// Learn `text` like [`learn_weighted`](Self::learn_weighted), also
+    /// remembering what it added under `source` (a user or corpus ID) so
+    /// [`unlearn_source`](Self::unlearn_source) can take it back later
+    pub fn learn_from(&mut self, source: &str, text: &str, weight: f64) {
+        self.learn_tagged(text, weight, Some(source), None);
+    }
+
+    /// Learn `text` like [`learn_weighted`](Self::learn_weighted), also
+    /// counting its transitions towards `topic` for
+    /// [`generate_with_topic`](Self::generate_with_topic)
+    pub fn learn_topic(&mut self, topic: &str, text: &str, weight: f64) {
+        self.learn_tagged(text, weight, None, Some(topic));
+    }
+
+    /// Learn `text`, remembering it under `source` like
+    /// [`learn_from`](Self::learn_from) and counting it towards `topic` like
+    /// [`learn_topic`](Self::learn_topic), each when given
+    pub fn learn_tagged(
+        &mut self,
+        text: &str,
+        weight: f64,
+        source: Option<&str>,
+        topic: Option<&str>,
+    ) {
+        if self.read_only {
+            return;
+        }
+        let separated = self.tokenizer.tokenize(text);
+        self.learn_tokens(&separated, weight);
+        for (tags, name) in [(&mut self.provenance, source), (&mut self.topics, topic)] {
+            if let Some(name) = name {
+                tags.entry(name.to_string())
+                    .or_default()
+                    .add_text(&separated, self.order, weight);
+            }
+        }
+    }
+
+    /// Take back records learned under `topic` with `weight` from the topic
+    /// only; [`unlearn`](Self::unlearn) takes them out of the chain
+    pub fn unlearn_topic<S: AsRef<str>>(&mut self, topic: &str, records: &[S], weight: f64) {
+        if self.read_only {
+            return;
+        }
+        let Some(contribution) = self.topics.get_mut(topic) else {
+            return;
+        };
+        for record in records {
+            let separated = self.tokenizer.tokenize(record.as_ref());
+            contribution.add_text(&separated, self.order, -weight);
+        }
+        // **📌 倍率1で縮めると消えかけた遷移だけが取り除かれる**
+        contribution.scale(1.0);
+        if contribution.is_empty() {
+            self.topics.remove(topic);
+        }
+    }
+
+    /// Topics learned with [`learn_topic`](Self::learn_topic)
+    pub fn topics(&self) -> impl Iterator<Item = &str> {
+        self.topics.keys().map(String::as_str)
+    }
+
+    /// Topic whose words `tokens` stand out in the most: each known token
+    /// scores the log of how much more often it is seen in the topic than
+    /// in the whole chain. A token naming a topic picks it outright; `None`
+    /// when no topic scores `min_score`
+    pub fn detect_topic<S: AsRef<str>>(&self, tokens: &[S], min_score: f64) -> Option<&str> {
+        detect_topic_layered(&[self], tokens, min_score)
+    }
+
+    /// Learn a response pair like [`learn_pair`](Self::learn_pair), tracked
+    /// under `source` like [`learn_from`](Self::learn_from)
+    pub fn learn_pair_from(&mut self, source: &str, prompt: &str, answer: &str, weight: f64) {
+        if self.read_only {
+            return;
+        }
+        let Some(start) = self.tokenizer.tokenize(answer).into_iter().next() else {
+            return;
+        };
+        self.learn_pair(prompt, answer, weight);
+        let contribution = self.provenance.entry(source.to_string()).or_default();
+        let prompt: HashSet<String> = self
+            .tokenizer
+            .tokenize(prompt)
+            .into_iter()
+            .filter(|token| !TokenType::of(token).is_punctuation())
+            .collect();
+        for token in prompt {
+            Contribution::add(&mut contribution.responses, token, &start, weight);
+        }
+    }
+
+    /// IDs of the sources learned with [`learn_from`](Self::learn_from)
+    pub fn sources(&self) -> impl Iterator<Item = &str> {
+        self.provenance.keys().map(String::as_str)
+    }
+
+    /// Subtract everything `source` added and forget the words left without
+    /// transitions. Returns the number of transitions touched, `None` if the
+    /// source was never tracked. Counts decay along with what they added;
+    /// after pruning or eviction only what is still there is taken back
+    pub fn unlearn_source(&mut self, source: &str) -> Option<usize> {
+        if self.read_only {
+            return None;
+        }
+        let contribution = self.provenance.remove(source)?;
+        let mut touched = 0;
+        for (key, nexts) in &contribution.transitions {
+            let single = !key.contains(' ');
+            for (next, count) in nexts {
+                let table = if single {
+                    &mut self.words
+                } else {
+                    &mut self.contexts
+                };
+                if let Some(word) = table.get_mut(key) {
+                    word.add_count(next.clone(), -count);
+                    touched += 1;
+                }
+                if single {
+                    if let Some(word) = self.reverse.get_mut(next) {
+                        word.add_count(key.clone(), -count);
+                    }
+                }
+            }
+        }
+        for (token, starts) in &contribution.responses {
+            if let Some(word) = self.responses.get_mut(token) {
+                for (start, count) in starts {
+                    word.add_count(start.clone(), -count);
+                    touched += 1;
+                }
+            }
+        }
+        self.drop_forgotten();
+        Some(touched)
+    }
+
+    /// Add the transitions of already tokenized text
+    fn learn_tokens(&mut self, separated: &[String], weight: f64) {
+        self.count_document(separated, weight);
+        // **📌 マルコフ連鎖に単語を追加**
+        for i in 0..separated.len() {
+            let word_str = separated[i].clone();
+
+            // **📌 HashMap に単語がなければ新規追加**
+            self.words
+                .entry(word_str.clone())
+                .or_insert_with(|| Word::new(word_str.clone()));
+
+            // **📌 遷移を追加**
+            if i + 1 < separated.len() {
+                self.add_transition_at(separated, i, weight, 1);
+            }
+        }
+    }
+
+    /// Take back records learned with `weight`, as when removing a corpus
+    /// source; words left without any transition are forgotten
+    pub fn unlearn<S: AsRef<str>>(&mut self, records: &[S], weight: f64) {
+        if self.read_only {
+            return;
+        }
+        for record in records {
+            let separated = self.tokenizer.tokenize(record.as_ref());
+            self.count_document(&separated, -weight);
+            for i in 0..separated.len().saturating_sub(1) {
+                self.add_transition_at(&separated, i, -weight, 1);
+            }
+        }
+        self.drop_forgotten();
+    }
+
+    /// Take back a response pair learned with [`learn_pair`](Self::learn_pair)
+    pub fn unlearn_pair(&mut self, prompt: &str, answer: &str, weight: f64) {
+        self.learn_pair(prompt, answer, -weight);
+        self.responses
+            .retain(|_, word| !word.transitions.is_empty());
+    }
+
+    /// Remove what unlearning emptied: contexts and index entries without
+    /// transitions, and words nothing leads to or from anymore
+    fn drop_forgotten(&mut self) {
+        for table in [&mut self.contexts, &mut self.reverse, &mut self.responses] {
+            table.retain(|_, word| !word.transitions.is_empty());
+        }
+        let reverse = &self.reverse;
+        self.words.retain(|key, word| {
+            !word.transitions.is_empty() || !word.meta.is_empty() || reverse.contains_key(key)
+        });
+        let words = &self.words;
+        self.document_frequency
+            .retain(|token, _| words.contains_key(token));
+        self.rebuild_index();
+    }
+
+    /// Count one text of `tokens` seen `weight` times (negative to take it
+    /// back) towards the document frequencies
+    fn count_document(&mut self, tokens: &[String], weight: f64) {
+        if tokens.is_empty() {
+            return;
+        }
+        self.documents = (self.documents + weight).max(0.0);
+        let distinct: HashSet<&String> = tokens.iter().collect();
+        for token in distinct {
+            let frequency = self.document_frequency.entry(token.clone()).or_insert(0.0);
+            *frequency += weight;
+            if *frequency < FORGET_BELOW {
+                self.document_frequency.remove(token);
+            }
+        }
+    }
+
+    /// Weighted number of learned texts
+    pub fn documents(&self) -> f64 {
+        self.documents
+    }
+
+    /// Weighted number of learned texts containing `token`
+    pub fn document_frequency(&self, token: &str) -> f64 {
+        self.document_frequency.get(token).copied().unwrap_or(0.0)
+    }
+
+    /// How much `token` tells about a text it appears in: the inverse
+    /// document frequency, low for particles like `は` found almost
+    /// everywhere and high for content words. 0 before anything is learned
+    pub fn salience(&self, token: &str) -> f64 {
+        salience(self.documents, self.document_frequency(token))
+    }
+
+    /// Learn that `answer` followed `prompt` in a conversation: every word of
+    /// the prompt is linked to the first token of the answer, which
+    /// [`response_seed`](Self::response_seed) later starts replies from
+    pub fn learn_pair(&mut self, prompt: &str, answer: &str, weight: f64) {
+        if self.read_only {
+            return;
+        }
+        let Some(start) = self.tokenizer.tokenize(answer).into_iter().next() else {
+            return;
+        };
+        let prompt: HashSet<String> = self
+            .tokenizer
+            .tokenize(prompt)
+            .into_iter()
+            .filter(|token| !TokenType::of(token).is_punctuation())
+            .collect();
+        for token in prompt {
+            self.responses
+                .entry(token.clone())
+                .or_insert_with(|| Word::new(token))
+                .add_count(start.clone(), weight);
+        }
+    }
+
+    /// Whether any response pairs have been learned
+    pub fn has_responses(&self) -> bool {
+        !self.responses.is_empty()
+    }
+
+    /// Pick the first token of a reply to `tokens` from the learned response
+    /// pairs. Each known input token votes with its own distribution, so a
+    /// frequent word does not drown out the rest; `None` if no token is known
+    pub fn response_seed<S: AsRef<str>>(&self, tokens: &[S]) -> Option<&str> {
+        let mut votes: HashMap<&str, f64> = HashMap::new();
+        let mut seen = HashSet::new();
+        for token in tokens {
+            let token = token.as_ref();
+            let Some(word) = self.responses.get(token).filter(|_| seen.insert(token)) else {
+                continue;
+            };
+            for (start, p) in word.distribution() {
+                if self.contains(start) {
+                    *votes.entry(start).or_insert(0.0) += p;
+                }
+            }
+        }
+        let mut votes: Vec<(&str, f64)> = votes.into_iter().collect();
+        votes.sort_by(|a, b| a.0.cmp(b.0));
+        let index = WeightedIndex::new(votes.iter().map(|(_, p)| *p)).ok()?;
+        Some(votes[index.sample(&mut rand::rng())].0)
+    }
+
+    /// Count the transition from `tokens[at]` to `tokens[at + 1]` for contexts of
+    /// `min_len..=order` tokens ending at `at`; length 1 also updates the reverse index
+    fn add_transition_at(&mut self, tokens: &[String], at: usize, count: f64, min_len: usize) {
+        let next_word = &tokens[at + 1];
+        for n in min_len.max(1)..=self.order.min(at + 1) {
+            let key = tokens[at + 1 - n..=at].join(" ");
+            let table = if n == 1 {
+                &mut self.words
+            } else {
+                &mut self.contexts
+            };
+            table
+                .entry(key.clone())
+                .or_insert_with(|| Word::new(key))
+                .add_count(next_word.clone(), count);
+        }
+
+        if min_len <= 1 {
+            let word_str = &tokens[at];
+            if !self.index.contains(word_str) {
+                self.index.insert(word_str.clone());
+            }
+
+            // **📌 逆方向の遷移も記録**
+            self.reverse
+                .entry(next_word.clone())
+                .or_insert_with(|| Word::new(next_word.clone()))
+                .add_count(word_str.clone(), count);
+        }
+    }
+
+    /// Copy of this chain with every token re-split by `tokenizer`.
+    ///
+    /// A transition `a → b` seen `n` times becomes the transitions inside the
+    /// re-split `a` plus the one from its last piece to the first piece of `b`,
+    /// each seen `n` times. Longer contexts are re-split the same way.
+    pub fn retokenized(&self, tokenizer: &TokenizerConfig) -> MarkovChain {
+        let mut chain = MarkovChain::new(self.order, tokenizer.clone());
+        let pieces: HashMap<&str, Vec<String>> = self
+            .words
+            .keys()
+            .map(|key| (key.as_str(), tokenizer.tokenize(key)))
+            .collect();
+        let split = |token: &str| {
+            pieces
+                .get(token)
+                .cloned()
+                .unwrap_or_else(|| tokenizer.tokenize(token))
+        };
+
+        for (key, word) in &self.words {
+            let from = split(key);
+            for piece in &from {
+                chain
+                    .words
+                    .entry(piece.clone())
+                    .or_insert_with(|| Word::new(piece.clone()));
+            }
+            // **📌 分割し直しても1トークンならメタデータを引き継ぐ**
+            if let [piece] = from.as_slice() {
+                let meta = word.meta.clone();
+                chain.words.get_mut(piece).unwrap().meta.extend(meta);
+            }
+            for (next, &count) in &word.transitions {
+                let mut tokens = from.clone();
+                tokens.extend(split(next));
+                for at in 0..from.len().min(tokens.len().saturating_sub(1)) {
+                    chain.add_transition_at(&tokens, at, count, 1);
+                }
+            }
+        }
+
+        // **📌 元の区切りをまたぐ長い文脈だけを追加（短いものは上で数えた）**
+        for (key, word) in &self.contexts {
+            let mut tokens: Vec<String> = Vec::new();
+            let mut last = 0;
+            for token in key.split(' ') {
+                let piece = split(token);
+                last = piece.len();
+                tokens.extend(piece);
+            }
+            let Some(at) = tokens.len().checked_sub(1) else {
+                continue;
+            };
+            for (next, &count) in &word.transitions {
+                let mut tokens = tokens.clone();
+                tokens.extend(split(next));
+                if at + 1 < tokens.len() {
+                    chain.add_transition_at(&tokens, at, count, last + 1);
+                }
+            }
+        }
+
+        // **📌 文書頻度は分割した欠片それぞれに引き継ぐ**
+        chain.documents = self.documents;
+        for (token, &frequency) in &self.document_frequency {
+            for piece in split(token) {
+                let entry = chain.document_frequency.entry(piece).or_insert(0.0);
+                *entry = entry.max(frequency);
+            }
+        }
+
+        // **📌 応答ペアは返事の最初の欠片に付け替える**
+        for (key, word) in &self.responses {
+            for (start, &count) in &word.transitions {
+                let Some(start) = split(start).into_iter().next() else {
+                    continue;
+                };
+                for piece in split(key) {
+                    chain
+                        .responses
+                        .entry(piece.clone())
+                        .or_insert_with(|| Word::new(piece))
+                        .add_count(start.clone(), count);
+                }
+            }
+        }
+        chain
+    }
+
+    // **📝 文章を生成**
+    pub fn generate(&self, start_word: &str, opts: &GenerateOptions) -> String {
+        let start_word = self.resolve_seed(start_word).unwrap_or(start_word);
+        run_with(
+            start_word,
+            opts,
+            self.order,
+            |context| self.next_distribution(context, opts),
+            Some(|context: &[String]| self.cached_word(context, opts)),
+        )
+    }
+
+    /// Like [`generate`](Self::generate), but fails instead of falling back
+    /// when the model is empty or knows nothing close to the seed
+    pub fn try_generate(&self, start_word: &str, opts: &GenerateOptions) -> error::Result<String> {
+        if self.is_empty() {
+            return Err(WordoraError::EmptyModel);
+        }
+        let Some(start_word) = self.resolve_seed(start_word) else {
+            return Err(WordoraError::UnknownSeed(start_word.to_string()));
+        };
+        Ok(self.generate(start_word, opts))
+    }
+
+    /// Like [`generate`](Self::generate), but yields the tokens one at a time
+    pub fn generate_iter<'a>(
+        &'a self,
+        start_word: &str,
+        opts: &GenerateOptions,
+    ) -> impl Iterator<Item = String> + 'a {
+        let start_word = self.resolve_seed(start_word).unwrap_or(start_word);
+        let next_opts = opts.clone();
+        let cached_opts = opts.clone();
+        TokenStream::cached(
+            start_word,
+            opts,
+            self.order,
+            move |context| self.next_distribution(context, &next_opts),
+            move |context| self.cached_word(context, &cached_opts),
+        )
+    }
+
+    /// Like [`generate`](Self::generate), leaning towards what was learned
+    /// under `topic`: each step mixes in the topic's own distribution for the
+    /// longest context it knows with `weight` (0 to 1). An unknown topic
+    /// generates as usual
+    pub fn generate_with_topic(
+        &self,
+        topic: &str,
+        start_word: &str,
+        opts: &GenerateOptions,
+        weight: f64,
+    ) -> String {
+        generate_with_topic_layered(&[self], topic, start_word, opts, weight)
+    }
+
+    /// Closest token generation can start from. Tries, in order: the word itself,
+    /// its longest known prefix, the shortest known word it is a prefix of, its
+    /// longest known suffix, and finally the nearest word by edit distance.
+    pub fn resolve_seed<'a>(&'a self, word: &'a str) -> Option<&'a str> {
+        if self.contains(word) {
+            return Some(word);
+        }
+        let boundaries: Vec<usize> = word.char_indices().map(|(i, _)| i).skip(1).collect();
+
+        // **📌 既知の前方一致（映画館 → 映画）**
+        if let Some(prefix) = boundaries
+            .iter()
+            .rev()
+            .map(|&i| &word[..i])
+            .find(|part| self.contains(part))
+        {
+            return Some(prefix);
+        }
+
+        // **📌 入力で始まる既知の単語（映 → 映画）**
+        let count = |w: &str| self.words.get(w).map_or(0.0, Word::count);
+        if let Some(longer) = fuzzy::prefix_matches(&self.index, word)
+            .min_by_key(|w| (w.chars().count(), std::cmp::Reverse(count(w).to_bits())))
+        {
+            return Some(longer);
+        }
+
+        // **📌 既知の後方一致**
+        if let Some(suffix) = boundaries
+            .iter()
+            .map(|&i| &word[i..])
+            .find(|part| self.contains(part))
+        {
+            return Some(suffix);
+        }
+
+        // **📌 編集距離が小さい既知の単語**
+        let max_distance = fuzzy::max_distance(word.chars().count());
+        fuzzy::near_matches(&self.index, word, max_distance)
+            .into_iter()
+            .min_by_key(|(w, distance)| (*distance, std::cmp::Reverse(count(w).to_bits())))
+            .map(|(w, _)| w)
+    }
+
+    /// Add every count of `other` to this chain; a chain split by a different
+    /// tokenizer is [retokenized](Self::retokenized) first
+    pub fn merge(&mut self, other: &MarkovChain) {
+        if self.read_only {
+            return;
+        }
+        if other.tokenizer != self.tokenizer {
+            return self.merge(&other.retokenized(&self.tokenizer));
+        }
+        for (table, other_table) in [
+            (&mut self.words, &other.words),
+            (&mut self.contexts, &other.contexts),
+            (&mut self.reverse, &other.reverse),
+            (&mut self.responses, &other.responses),
+        ] {
+            for (key, other_word) in other_table {
+                let word = table
+                    .entry(key.clone())
+                    .or_insert_with(|| Word::new(key.clone()));
+                for (next, count) in &other_word.transitions {
+                    *word.transitions.entry(next.clone()).or_insert(0.0) += count;
+                }
+                // **📌 メタデータは取り込む側の値で上書き**
+                word.meta.extend(other_word.meta.clone());
+            }
+        }
+        for (tags, other_tags) in [
+            (&mut self.provenance, &other.provenance),
+            (&mut self.topics, &other.topics),
+        ] {
+            for (name, contribution) in other_tags {
+                tags.entry(name.clone()).or_default().merge(contribution);
+            }
+        }
+        self.documents += other.documents;
+        for (token, frequency) in &other.document_frequency {
+            *self.document_frequency.entry(token.clone()).or_insert(0.0) += frequency;
+        }
+        self.order = self.order.max(other.order);
+        self.rebuild_index();
+    }
+
+    /// Multiply every count by `factor`, forgetting transitions that fade below
+    /// a hundredth of an occurrence
+    pub fn decay(&mut self, factor: f64) {
+        if self.read_only {
+            return;
+        }
+        for table in [
+            &mut self.words,
+            &mut self.contexts,
+            &mut self.reverse,
+            &mut self.responses,
+        ] {
+            for word in table.values_mut() {
+                word.transitions.retain(|_, count| {
+                    *count *= factor;
+                    *count >= FORGET_BELOW
+                });
+            }
+        }
+        self.contexts
+            .retain(|_, context| !context.transitions.is_empty());
+        self.responses
+            .retain(|_, response| !response.transitions.is_empty());
+        for contribution in self.provenance.values_mut().chain(self.topics.values_mut()) {
+            contribution.scale(factor);
+        }
+        self.topics
+            .retain(|_, contribution| !contribution.is_empty());
+        self.documents *= factor;
+        self.document_frequency.retain(|_, frequency| {
+            *frequency *= factor;
+            *frequency >= FORGET_BELOW
+        });
+        self.rebuild_index();
+    }
+
+    /// Multiply the counts of every transition along `tokens`, for each
+    /// context length, by `factor`: above 1 to reinforce a reply users liked,
+    /// below 1 to weaken one they did not. Transitions fading below a
+    /// hundredth of an occurrence are forgotten. Returns the number of
+    /// transitions found along the path
+    pub fn reinforce<S: AsRef<str>>(&mut self, tokens: &[S], factor: f64) -> usize {
+        if self.read_only {
+            return 0;
+        }
+        let tokens: Vec<&str> = tokens.iter().map(AsRef::as_ref).collect();
+        let mut changed = 0;
+        for (key, next) in transitions_along(&tokens, self.order) {
+            let count = self.transition_count(&key, next);
+            if count > 0.0 && self.add_context_count(&key, next, count * (factor - 1.0)) {
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    /// Count of the transition from `key`, one or more tokens joined with
+    /// spaces, to `next`
+    pub(crate) fn transition_count(&self, key: &str, next: &str) -> f64 {
+        let table = if key.contains(' ') {
+            &self.contexts
+        } else {
+            &self.words
+        };
+        table
+            .get(key)
+            .and_then(|word| word.transitions.get(next))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// Add `count` to the transition from `key`, one or more tokens joined
+    /// with spaces, to `next`; a negative count takes it back and cannot go
+    /// below nothing. Only the entries touched drop their cached
+    /// distributions. Returns whether there was anything to change
+    pub(crate) fn add_context_count(&mut self, key: &str, next: &str, count: f64) -> bool {
+        if self.read_only || (count <= 0.0 && self.transition_count(key, next) <= 0.0) {
+            return false;
+        }
+        let single = !key.contains(' ');
+        let table = if single {
+            &mut self.words
+        } else {
+            &mut self.contexts
+        };
+        let word = table
+            .entry(key.to_string())
+            .or_insert_with(|| Word::new(key.to_string()));
+        word.add_count(next.to_string(), count);
+        let emptied = word.transitions.is_empty();
+        if !single {
+            if emptied {
+                self.contexts.remove(key);
+            }
+            return true;
+        }
+        // **📌 1単語の文脈は索引と逆方向の遷移も合わせる**
+        if emptied {
+            self.index.remove(key);
+        } else if !self.index.contains(key) {
+            self.index.insert(key.to_string());
+        }
+        self.words
+            .entry(next.to_string())
+            .or_insert_with(|| Word::new(next.to_string()));
+        self.reverse
+            .entry(next.to_string())
+            .or_insert_with(|| Word::new(next.to_string()))
+            .add_count(key.to_string(), count);
+        true
+    }
+
+    /// Evict the least frequent tokens, and every transition from or to them,
+    /// once the vocabulary grows past `max_vocab`. It is cut down to 90% of the
+    /// cap so eviction does not run again on every new token. Returns the number
+    /// of tokens evicted.
+    pub fn evict(&mut self, max_vocab: usize) -> usize {
+        if self.read_only || self.words.len() <= max_vocab {
+            return 0;
+        }
+        let target = max_vocab - max_vocab / 10;
+        let frequency =
+            |word: &Word| word.count() + self.reverse.get(&word.word).map_or(0.0, Word::count);
+        let mut ranked: Vec<(&str, f64)> = self
+            .words
+            .values()
+            .map(|word| (word.word.as_str(), frequency(word)))
+            .collect();
+        ranked.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(b.0)));
+        let evicted: HashSet<String> = ranked[..self.words.len() - target]
+            .iter()
+            .map(|(word, _)| word.to_string())
+            .collect();
+
+        // **📌 追い出す単語を含む遷移と文脈をまとめて取り除く**
+        for table in [&mut self.words, &mut self.reverse] {
+            table.retain(|key, _| !evicted.contains(key));
+            for word in table.values_mut() {
+                word.transitions.retain(|next, _| !evicted.contains(next));
+            }
+        }
+        self.contexts.retain(|key, context| {
+            context
+                .transitions
+                .retain(|next, _| !evicted.contains(next));
+            !context.transitions.is_empty() && !key.split(' ').any(|t| evicted.contains(t))
+        });
+        self.rebuild_index();
+        evicted.len()
+    }
+
+    /// Transitions seen fewer than `min_count` times, grouped by word and sorted
+    pub fn prune_candidates(&self, min_count: f64) -> Vec<(&str, Vec<(&str, f64)>)> {
+        let mut candidates: Vec<(&str, Vec<(&str, f64)>)> = self
+            .words
+            .values()
+            .filter_map(|word| {
+                let mut rare: Vec<(&str, f64)> = word
+                    .transitions
+                    .iter()
+                    .filter(|(_, &count)| count < min_count)
+                    .map(|(next, &count)| (next.as_str(), count))
+                    .collect();
+                rare.sort_by(|a, b| a.0.cmp(b.0));
+                (!rare.is_empty()).then_some((word.word.as_str(), rare))
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.0.cmp(b.0));
+        candidates
+    }
+
+    /// Remove transitions seen fewer than `min_count` times, except those from
+    /// the tokens in `keep`. Contexts ending in a kept token are kept too.
+    /// Returns the number of transitions removed from single tokens.
+    pub fn prune(&mut self, min_count: f64, keep: &HashSet<String>) -> usize {
+        if self.read_only {
+            return 0;
+        }
+        let mut removed = Vec::new();
+        for word in self.words.values_mut() {
+            if keep.contains(&word.word) {
+                continue;
+            }
+            word.transitions.retain(|next, &mut count| {
+                if count < min_count {
+                    removed.push((word.word.clone(), next.clone()));
+                }
+                count >= min_count
+            });
+        }
+
+        // **📌 逆方向の遷移と長い文脈からも取り除く**
+        for (from, next) in &removed {
+            if let Some(word) = self.reverse.get_mut(next) {
+                word.transitions.remove(from);
+            }
+        }
+        for context in self.contexts.values_mut() {
+            let last = context.word.rsplit(' ').next().unwrap_or_default();
+            if !keep.contains(last) {
+                context
+                    .transitions
+                    .retain(|_, &mut count| count >= min_count);
+            }
+        }
+        self.contexts
+            .retain(|_, context| !context.transitions.is_empty());
+        self.rebuild_index();
+        removed.len()
+    }
+
+    /// Rebuild the index of words with transitions after counts changed in
+    /// bulk, dropping every cached distribution
+    pub(crate) fn rebuild_index(&mut self) {
+        for table in [
+            &mut self.words,
+            &mut self.contexts,
+            &mut self.reverse,
+            &mut self.responses,
+        ] {
+            table.values_mut().for_each(Word::invalidate);
+        }
+        self.index = self
+            .words
+            .values()
+            .filter(|word| !word.transitions.is_empty())
+            .map(|word| word.word.clone())
+            .collect();
+    }
+
+    /// Build every word's distribution up front, e.g. after bulk training or
+    /// loading, so generation does not pay for it on first use
+    pub fn finalize(&self) {
+        for table in [&self.words, &self.contexts, &self.reverse, &self.responses] {
+            table.values().for_each(Word::finalize);
+        }
+    }
+
+    /// Whether nothing has been learned yet
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        if self.read_only {
+            return;
+        }
+        self.words.clear();
+        self.contexts.clear();
+        self.reverse.clear();
+        self.responses.clear();
+        self.provenance.clear();
+        self.topics.clear();
+        self.documents = 0.0;
+        self.document_frequency.clear();
+        self.index.clear();
+    }
+
+    /// Generate one sentence containing `keyword`.
+    ///
+    /// The sentence is grown forward from the keyword and backward through the
+    /// reverse index, each half stopping at a sentence terminator.
+    pub fn generate_around(&self, keyword: &str, opts: &GenerateOptions) -> String {
+        generate_around_layered(&[self], keyword, opts)
+    }
+
+    /// Generate `length` tokens leading up to `end_word`, which ends the result
+    pub fn generate_backward(&self, end_word: &str, length: usize) -> String {
+        let mut rng = rand::rng();
+        walk_backward(
+            &[self],
+            end_word,
+            length,
+            &GenerateOptions::default(),
+            false,
+            &mut rng,
+        )
+        .join(" ")
+    }
+
+    /// Tokens seen directly before `word`, most frequent first
+    pub fn predecessors(&self, word: &str) -> Vec<(&str, f64)> {
+        let mut predecessors: Vec<(&str, f64)> = self
+            .reverse
+            .get(word)
+            .map(|word| {
+                word.transitions
+                    .iter()
+                    .map(|(prev, &count)| (prev.as_str(), count))
+                    .collect()
+            })
+            .unwrap_or_default();
+        predecessors.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        predecessors
+    }
+
+    /// Log-probability of `text` under the chain, with add-k smoothing for unseen transitions
+    pub fn score(&self, text: &str) -> f64 {
+        self.evaluate(text).log_prob
+    }
+
+    /// Log-probability, perplexity and unknown-token count of `text`
+    pub fn evaluate(&self, text: &str) -> Evaluation {
+        let tokens = self.tokenizer.tokenize(text);
+        let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+        let log_prob = self.sequence_log_prob(&tokens);
+        let predictions = tokens.len().saturating_sub(1);
+
+        Evaluation {
+            tokens: tokens.len(),
+            unknown: tokens
+                .iter()
+                .filter(|t| !self.words.contains_key(**t))
+                .count(),
+            log_prob,
+            perplexity: if predictions == 0 {
+                f64::NAN
+            } else {
+                (-log_prob / predictions as f64).exp()
+            },
+        }
+    }
+
+    /// Average log-probability per transition of a tokenized text
+    pub fn average_log_prob(&self, tokens: &[&str]) -> f64 {
+        if tokens.len() < 2 {
+            return 0.0;
+        }
+        self.sequence_log_prob(tokens) / (tokens.len() - 1) as f64
+    }
+
+    /// Sum of smoothed log-probabilities of every transition in `tokens`
+    fn sequence_log_prob(&self, tokens: &[&str]) -> f64 {
+        let mut total = 0.0;
+        for i in 1..tokens.len() {
+            let context: Vec<String> = tokens[i.saturating_sub(self.order)..i]
+                .iter()
+                .map(|s| s.to_string())
+                .collect();
+            total += self.smoothed_probability(&context, tokens[i]).ln();
+        }
+        total
+    }
+
+    /// Add-k smoothed `p(next | context)`; unknown contexts get a uniform distribution
+    fn smoothed_probability(&self, context: &[String], next: &str) -> f64 {
+        // **📌 語彙にない単語の分も1つ数える**
+        let vocabulary = (self.words.len() + 1) as f64;
+        match self.lookup(context) {
+            Some(word) => {
+                let count = word.transitions.get(next).copied().unwrap_or(0.0);
+                (count + SMOOTHING_K) / (word.count() + SMOOTHING_K * vocabulary)
+            }
+            None => 1.0 / vocabulary,
+        }
+    }
+
+    /// Distribution of the next token. With `smoothing > 0` every vocabulary word
+    /// gets add-k mass, and contexts without a continuation back off to word frequencies.
+    /// Tokens carrying an excluded metadata key are left out.
+    pub(crate) fn next_distribution(
+        &self,
+        context: &[String],
+        opts: &GenerateOptions,
+    ) -> Option<Vec<(&str, f64)>> {
+        let smoothing = opts.smoothing;
+        let aliased = context
+            .last()
+            .and_then(|token| self.aliased_word(token, opts));
+        let mut distribution = match (self.lookup(context), &aliased) {
+            (Some(_), _) if opts.interpolation.is_some() => {
+                self.interpolated_distribution(context, aliased.as_ref(), opts)?
+            }
+            // **📌 1単語の文脈では別名の遷移もまとめて使う**
+            (Some(word), Some(merged)) if !word.word.contains(' ') => {
+                self.pooled_distribution(merged, smoothing)
+            }
+            (None, Some(merged)) => self.pooled_distribution(merged, smoothing),
+            (Some(word), _) if smoothing > 0.0 => self.smoothed_distribution(word, smoothing),
+            (Some(word), _) => word.distribution(),
+            (None, _) if smoothing > 0.0 => self.unigram_distribution(),
+            (None, _) => return None,
+        };
+        if !opts.exclude_meta.is_empty() {
+            distribution.retain(|(next, _)| {
+                self.words.get(*next).is_none_or(|word| {
+                    !opts
+                        .exclude_meta
+                        .iter()
+                        .any(|key| word.meta.contains_key(key))
+                })
+            });
+        }
+        Some(distribution)
+    }
+
+    /// The word whose cached table [`next_distribution`](Self::next_distribution)
+    /// returns unchanged for `context`, `None` when the options rebuild it
+    pub(crate) fn cached_word(&self, context: &[String], opts: &GenerateOptions) -> Option<&Word> {
+        if opts.smoothing > 0.0 || opts.interpolation.is_some() || !opts.exclude_meta.is_empty() {
+            return None;
+        }
+        let word = self.lookup(context)?;
+        // **📌 別名の遷移をまとめる1単語の文脈は除く**
+        let aliased =
+            !word.word.contains(' ') && opts.aliases.iter().any(|group| group.contains(&word.word));
+        (!aliased).then_some(word)
+    }
+
+    /// Mix the distributions of every known context from the longest down, each
+    /// keeping `opts.interpolation` of what the longer ones left over. The single
+    /// last token, or its pooled `aliased` word, takes whatever remains.
+    fn interpolated_distribution(
+        &self,
+        context: &[String],
+        aliased: Option<&Word>,
+        opts: &GenerateOptions,
+    ) -> Option<Vec<(&str, f64)>> {
+        let lambda = opts.interpolation.unwrap_or(1.0);
+        let context = &context[context.len().saturating_sub(self.order)..];
+        let mut mixture: HashMap<&str, f64> = HashMap::new();
+        let mut remaining = 1.0;
+        for n in (1..=context.len()).rev() {
+            let distribution = if n == 1 {
+                match aliased {
+                    Some(merged) => self.pooled_distribution(merged, 0.0),
+                    None => self
+                        .words
+                        .get(&context[context.len() - 1])
+                        .map_or_else(Vec::new, Word::distribution),
+                }
+            } else {
+                match self.contexts.get(&context[context.len() - n..].join(" ")) {
+                    Some(word) => word.distribution(),
+                    None => continue,
+                }
+            };
+            // **📌 知らない文脈の分はそのまま短い文脈に回す**
+            if distribution.is_empty() {
+                continue;
+            }
+            let share = if n == 1 {
+                remaining
+            } else {
+                remaining * lambda
+            };
+            for (next, p) in distribution {
+                *mixture.entry(next).or_insert(0.0) += share * p;
+            }
+            remaining -= share;
+        }
+        (!mixture.is_empty()).then(|| mixture.into_iter().collect())
+    }
+
+    /// Transitions of `token` and its aliases added together, if it has any aliases
+    fn aliased_word(&self, token: &str, opts: &GenerateOptions) -> Option<Word> {
+        let group = opts
+            .aliases
+            .iter()
+            .find(|group| group.iter().any(|alias| alias == token))?;
+        let mut merged = Word::new(token.to_string());
+        for word in group.iter().filter_map(|alias| self.words.get(alias)) {
+            for (next, count) in &word.transitions {
+                *merged.transitions.entry(next.clone()).or_insert(0.0) += count;
+            }
+        }
+        (!merged.transitions.is_empty()).then_some(merged)
+    }
+
+    /// Distribution of a word built outside the chain, borrowing the chain's tokens
+    fn pooled_distribution(&self, word: &Word, smoothing: f64) -> Vec<(&str, f64)> {
+        if smoothing > 0.0 {
+            return self.smoothed_distribution(word, smoothing);
+        }
+        word.distribution()
+            .into_iter()
+            .filter_map(|(next, p)| Some((self.words.get_key_value(next)?.0.as_str(), p)))
+            .collect()
+    }
+
+    /// Add-k smoothed transitions of `word` over the whole vocabulary
+    fn smoothed_distribution(&self, word: &Word, k: f64) -> Vec<(&str, f64)> {
+        let total = word.count() + k * self.words.len() as f64;
+        self.words
+            .keys()
+            .map(|next| {
+                let count = word.transitions.get(next).copied().unwrap_or(0.0);
+                (next.as_str(), (count + k) / total)
+            })
+            .collect()
+    }
+
+    /// Order-0 back-off: how often each word was seen
+    fn unigram_distribution(&self) -> Vec<(&str, f64)> {
+        let total: f64 = self.words.values().map(Word::count).sum();
+        self.words
+            .values()
+            .filter(|word| word.count() > 0.0)
+            .map(|word| (word.word.as_str(), word.count() / total))
+            .collect()
+    }
+
+    /// Find the longest known context with at least one transition
+    fn lookup(&self, context: &[String]) -> Option<&Word> {
+        let context = &context[context.len().saturating_sub(self.order)..];
+        for n in (2..=context.len()).rev() {
+            let key = context[context.len() - n..].join(" ");
+            if let Some(word) = self.contexts.get(&key) {
+                return Some(word);
+            }
+        }
+        self.words
+            .get(context.last()?)
+            .filter(|word| !word.transitions.is_empty())
+    }
+
+    // **🩺 モデルの検査**
+    /// Check the invariants learning keeps: a nonzero order, positive counts,
+    /// entries stored under their own token, transitions and contexts made of
+    /// known tokens, contexts of 2..=order tokens, a reverse index counting
+    /// the same transitions as the words, and cached distributions matching
+    /// the counts
+    pub fn validate(&self) -> ValidationReport {
+        let mut issues = Vec::new();
+        if self.order == 0 {
+            issues.push(Issue::ZeroOrder);
+        }
+        let known = |token: &str| self.words.contains_key(token);
+        for (table, entries) in [
+            (Table::Words, &self.words),
+            (Table::Contexts, &self.contexts),
+            (Table::Reverse, &self.reverse),
+            (Table::Responses, &self.responses),
+        ] {
+            for (key, word) in entries {
+                if word.word != *key {
+                    issues.push(Issue::MisnamedEntry {
+                        table,
+                        key: key.clone(),
+                        word: word.word.clone(),
+                    });
+                }
+                if !word.cache_matches() {
+                    issues.push(Issue::StaleCache {
+                        table,
+                        key: key.clone(),
+                    });
+                }
+                for (next, &count) in &word.transitions {
+                    if !(count.is_finite() && count > 0.0) {
+                        issues.push(Issue::BadCount {
+                            table,
+                            key: key.clone(),
+                            next: next.clone(),
+                            count,
+                        });
+                    }
+                }
+                // **📌 返事の始まりは学習していない単語でもよい**
+                if table == Table::Responses {
+                    continue;
+                }
+                let key_tokens: Vec<&str> = match table {
+                    Table::Contexts => key.split(' ').collect(),
+                    _ => vec![key.as_str()],
+                };
+                let tokens = key_tokens
+                    .into_iter()
+                    .chain(word.transitions.keys().map(String::as_str));
+                for token in tokens.filter(|token| !known(token)) {
+                    issues.push(Issue::UnknownToken {
+                        table,
+                        key: key.clone(),
+                        token: token.to_string(),
+                    });
+                }
+            }
+        }
+        for key in self.contexts.keys() {
+            let tokens = key.split(' ').count();
+            if !(2..=self.order).contains(&tokens) {
+                issues.push(Issue::ContextLength {
+                    key: key.clone(),
+                    tokens,
+                });
+            }
+        }
+
+        // **📌 逆方向の遷移は順方向と同じ回数のはず**
+        let count = |table: &HashMap<String, Word>, key: &str, next: &str| {
+            table
+                .get(key)
+                .and_then(|word| word.transitions.get(next))
+                .copied()
+        };
+        for (from, word) in &self.words {
+            for (to, &forward) in &word.transitions {
+                let reverse = count(&self.reverse, to, from).unwrap_or(0.0);
+                if !close(forward, reverse) {
+                    issues.push(Issue::ReverseMismatch {
+                        from: from.clone(),
+                        to: to.clone(),
+                        forward,
+                        reverse,
+                    });
+                }
+            }
+        }
+        for (to, word) in &self.reverse {
+            for (from, &reverse) in &word.transitions {
+                if count(&self.words, from, to).is_none() {
+                    issues.push(Issue::ReverseMismatch {
+                        from: from.clone(),
+                        to: to.clone(),
+                        forward: 0.0,
+                        reverse,
+                    });
+                }
+            }
+        }
+        ValidationReport { issues }
+    }
+
+    // **💾 モデルを保存**
+    /// Compressed with gzip or zstd when the file is named `.gz` or `.zst`
+    pub fn save(&self, path: &Path) -> error::Result<()> {
+        self.save_with_level(path, None)
+    }
+
+    /// Save compressed at `level`; a plain file name gets zstd.
+    ///
+    /// The model is written to a temporary file next to `path` and renamed
+    /// over it, so a crash while saving leaves the previous file intact.
+    pub fn save_with_level(&self, path: &Path, level: Option<i32>) -> error::Result<()> {
+        let compression = match Compression::for_path(path) {
+            Compression::None if level.is_some() => Compression::Zstd,
+            compression => compression,
+        };
+        let bytes = compression.compress(&self.to_bytes()?, level)?;
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".tmp");
+        let temp = path.with_file_name(name);
+        let written = File::create(&temp).and_then(|mut file| {
+            file.write_all(&bytes)?;
+            file.sync_all()
+        });
+        if let Err(e) = written.and_then(|()| fs::rename(&temp, path)) {
+            let _ = fs::remove_file(&temp);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    // **📂 モデルを読み込み**
+    /// Compressed files are recognized by their magic bytes
+    pub fn load(path: &Path) -> error::Result<Self> {
+        Self::from_bytes(&Compression::decompress(fs::read(path)?)?)
+    }
+
+    /// Serialized model, as written by [`save`](Self::save)
+    pub fn to_bytes(&self) -> error::Result<Vec<u8>> {
+        bincode::serialize(self).map_err(|e| WordoraError::InvalidModelFile(e.to_string()))
+    }
+
+    /// Read a model written by [`to_bytes`](Self::to_bytes), checking that its
+    /// tokenizer config can be used and that it passes
+    /// [`validate`](Self::validate)
+    pub fn from_bytes(bytes: &[u8]) -> error::Result<Self> {
+        let mut chain: Self = bincode::deserialize(bytes)
+            .map_err(|e| WordoraError::InvalidModelFile(e.to_string()))?;
+        chain.tokenizer.validate()?;
+        chain.validate().into_result()?;
+        chain.rebuild_index();
+        Ok(chain)
+    }
+}
+
+/// Inverse document frequency of a token found in `frequency` of `documents`
+/// texts, smoothed so unseen tokens and empty chains stay finite
+pub fn salience(documents: f64, frequency: f64) -> f64 {
+    ((documents + 1.0) / (frequency + 1.0)).ln().max(0.0)
+}
+
+/// Salience of `token` over the texts learned by every persona of a blend
+pub fn blend_salience(personas: &[(&MarkovChain, f64)], token: &str) -> f64 {
+    salience(
+        personas.iter().map(|(chain, _)| chain.documents()).sum(),
+        personas
+            .iter()
+            .map(|(chain, _)| chain.document_frequency(token))
+            .sum(),
+    )
+}
+
+/// Generate from a weighted mixture of several models.
+///
+/// Each step samples from `Σ weight * p(next | context)` over the personas that
+/// know the current context, so the models never have to be merged.
+pub fn generate_blend(
+    personas: &[(&MarkovChain, f64)],
+    start_word: &str,
+    opts: &GenerateOptions,
+) -> String {
+    let max_order = personas
+        .iter()
+        .map(|(chain, _)| chain.order)
+        .max()
+        .unwrap_or(1);
+
+    run(start_word, opts, max_order, |context| {
+        // **📌 各ペルソナの分布を重み付きで混ぜる**
+        let mut mixture: HashMap<&str, f64> = HashMap::new();
+        for (chain, weight) in personas {
+            for (next, p) in chain.next_distribution(context, opts).unwrap_or_default() {
+                *mixture.entry(next).or_insert(0.0) += weight * p;
+            }
+        }
+        Some(mixture.into_iter().filter(|(_, p)| *p > 0.0).collect())
+    })
+}
+
+/// Generate from stacked layers; the first layer that knows the context is used
+pub fn generate_layered(
+    layers: &[&MarkovChain],
+    start_word: &str,
+    opts: &GenerateOptions,
+) -> String {
+    let max_order = layers.iter().map(|chain| chain.order).max().unwrap_or(1);
+
+    let start_word = layers
+        .iter()
+        .find_map(|chain| chain.resolve_seed(start_word))
+        .unwrap_or(start_word);
+
+    run_with(
+        start_word,
+        opts,
+        max_order,
+        |context| layered_distribution(layers, context, opts),
+        Some(|context: &[String]| layered_cached_word(layers, context, opts)),
+    )
+}
+
+/// Topic learned in any of `layers`, see [`MarkovChain::detect_topic`]. A
+/// topic's counts and the overall word counts are summed over the layers
+pub(crate) fn detect_topic_layered<'a, S: AsRef<str>>(
+    layers: &[&'a MarkovChain],
+    tokens: &[S],
+    min_score: f64,
+) -> Option<&'a str> {
+    if let Some(name) = tokens.iter().find_map(|token| {
+        layers
+            .iter()
+            .find_map(|chain| chain.topics.get_key_value(token.as_ref()))
+            .map(|(name, _)| name.as_str())
+    }) {
+        return Some(name);
+    }
+    let total: f64 = layers
+        .iter()
+        .flat_map(|chain| chain.words.values())
+        .map(Word::count)
+        .sum();
+    let overall = |token: &str| -> f64 {
+        layers
+            .iter()
+            .filter_map(|chain| chain.words.get(token))
+            .map(Word::count)
+            .sum()
+    };
+    let distinct: HashSet<&str> = tokens.iter().map(AsRef::as_ref).collect();
+    let single = |contribution: &Contribution, token: &str| -> f64 {
+        contribution
+            .transitions
+            .get(token)
+            .map_or(0.0, |nexts| nexts.values().sum())
+    };
+    topics_layered(layers)
+        .into_iter()
+        .map(|name| {
+            let contributions: Vec<&Contribution> = layers
+                .iter()
+                .filter_map(|chain| chain.topics.get(name))
+                .collect();
+            let topic_total: f64 = contributions
+                .iter()
+                .flat_map(|contribution| &contribution.transitions)
+                .filter(|(key, _)| !key.contains(' '))
+                .flat_map(|(_, nexts)| nexts.values())
+                .sum();
+            let score: f64 = distinct
+                .iter()
+                .filter_map(|token| {
+                    let in_topic: f64 = contributions.iter().map(|c| single(c, token)).sum();
+                    let overall = overall(token);
+                    (in_topic > 0.0 && overall > 0.0)
+                        .then(|| ((in_topic / topic_total) / (overall / total)).ln().max(0.0))
+                })
+                .sum();
+            (name, score)
+        })
+        .filter(|(_, score)| *score >= min_score)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(name, _)| name)
+}
+
+/// Topics learned in any of `layers`, sorted
+pub(crate) fn topics_layered<'a>(layers: &[&'a MarkovChain]) -> Vec<&'a str> {
+    let topics: BTreeSet<&str> = layers.iter().flat_map(|chain| chain.topics()).collect();
+    topics.into_iter().collect()
+}
+
+/// Generate from stacked layers leaning towards `topic`, see
+/// [`MarkovChain::generate_with_topic`]; what each layer learned under the
+/// topic is pooled
+pub(crate) fn generate_with_topic_layered(
+    layers: &[&MarkovChain],
+    topic: &str,
+    start_word: &str,
+    opts: &GenerateOptions,
+    weight: f64,
+) -> String {
+    let contributions: Vec<&Contribution> = layers
+        .iter()
+        .filter_map(|chain| chain.topics.get(topic))
+        .collect();
+    if contributions.is_empty() {
+        return generate_layered(layers, start_word, opts);
+    }
+    let max_order = layers.iter().map(|chain| chain.order).max().unwrap_or(1);
+    let start_word = layers
+        .iter()
+        .find_map(|chain| chain.resolve_seed(start_word))
+        .unwrap_or(start_word);
+    // **📌 各レイヤーがこの話題で数えた遷移をまとめる**
+    let topic_nexts = |key: &str| -> Option<HashMap<&str, f64>> {
+        let mut nexts: HashMap<&str, f64> = HashMap::new();
+        for table in contributions.iter().filter_map(|c| c.transitions.get(key)) {
+            for (next, count) in table {
+                *nexts.entry(next.as_str()).or_insert(0.0) += count;
+            }
+        }
+        (!nexts.is_empty()).then_some(nexts)
+    };
+    run(start_word, opts, max_order, |context| {
+        let distribution = layered_distribution(layers, context, opts)?;
+        // **📌 話題が知っている一番長い文脈の分布を混ぜる（候補は元の分布の中だけ）**
+        let Some(nexts) = (1..=max_order.min(context.len()))
+            .rev()
+            .find_map(|n| topic_nexts(&context[context.len() - n..].join(" ")))
+        else {
+            return Some(distribution);
+        };
+        let total: f64 = nexts.values().sum();
+        Some(
+            distribution
+                .into_iter()
+                .map(|(next, p)| {
+                    let in_topic = nexts.get(next).copied().unwrap_or(0.0) / total;
+                    (next, (1.0 - weight) * p + weight * in_topic)
+                })
+                .collect(),
+        )
+    })
+}
+
+/// Generate around `keyword` from stacked layers, see
+/// [`MarkovChain::generate_around`]; each step uses the top-most layer that
+/// knows the context
+pub(crate) fn generate_around_layered(
+    layers: &[&MarkovChain],
+    keyword: &str,
+    opts: &GenerateOptions,
+) -> String {
+    if !layers
+        .iter()
+        .any(|chain| chain.contains(keyword) || chain.reverse.contains_key(keyword))
+    {
+        return generate_layered(layers, keyword, opts);
+    }
+    let order = layers.iter().map(|chain| chain.order).max().unwrap_or(1);
+    let mut rng = rand::rng();
+    let half = opts.length.div_ceil(2);
+
+    // **📌 後ろ向きに生成**
+    let before = walk_backward(layers, keyword, half, opts, true, &mut rng);
+
+    // **📌 前向きに生成**
+    let mut after: Vec<String> = vec![];
+    let mut context = vec![keyword.to_string()];
+    for _ in 0..half {
+        let Some(word) = layers.iter().find_map(|chain| chain.lookup(&context)) else {
+            break;
+        };
+        let distribution = constrain(word.distribution(), context.last(), opts);
+        let Some(next) = sample(&distribution, opts, &mut rng) else {
+            break;
+        };
+        after.push(next.to_string());
+        if is_terminator(next) {
+            break;
+        }
+        context.push(next.to_string());
+        if context.len() > order {
+            context.remove(0);
+        }
+    }
+
+    // **📌 前半と後半をつなげる**
+    let mut tokens = before;
+    tokens.extend(after.iter().map(String::as_str));
+    tokens.join(" ")
+}
+
+/// Walk the reverse index of the top-most layer that knows each token from
+/// `end_word`, returning tokens in reading order
+fn walk_backward<'a, R: Rng>(
+    layers: &[&'a MarkovChain],
+    end_word: &'a str,
+    steps: usize,
+    opts: &GenerateOptions,
+    stop_at_terminator: bool,
+    rng: &mut R,
+) -> Vec<&'a str> {
+    let mut tokens = vec![end_word];
+    let mut current = end_word;
+    for _ in 0..steps {
+        let Some(word) = layers.iter().find_map(|chain| chain.reverse.get(current)) else {
+            break;
+        };
+        // **📌 重みを変えないときはキャッシュした表から選ぶ**
+        let prev = if reweights(opts) {
+            sample(&word.distribution(), opts, rng)
+        } else {
+            word.sample(rng)
+        };
+        let Some(prev) = prev else {
+            break;
+        };
+        if stop_at_terminator && is_terminator(prev) {
+            break;
+        }
+        tokens.push(prev);
+        current = prev;
+    }
+    tokens.reverse();
+    tokens
+}
+
+/// Next tokens with their weights, `None` when the context is unknown
+pub type Candidates<'a> = Option<Vec<(&'a str, f64)>>;
+
+/// Candidates following a context, the `next` of a [`TokenStream`]
+pub type NextFn<'a> = Box<dyn Fn(&[String]) -> Candidates<'a> + 'a>;
+
+/// Word whose cached table a [`NextFn`] would return unchanged for a context,
+/// the shortcut of a [`TokenStream`]
+pub type ShortcutFn<'a> = Box<dyn Fn(&[String]) -> Option<&'a Word> + 'a>;
+
+/// Distribution from the first layer that knows the context
+pub(crate) fn layered_distribution<'a>(
+    layers: &[&'a MarkovChain],
+    context: &[String],
+    opts: &GenerateOptions,
+) -> Candidates<'a> {
+    // **📌 文脈を知っている一番上のレイヤー、なければベースで平滑化**
+    let chain = layers
+        .iter()
+        .find(|chain| chain.lookup(context).is_some())
+        .or(layers.last())?;
+    chain.next_distribution(context, opts)
+}
+
+/// Word whose cached table [`layered_distribution`] would return unchanged
+pub(crate) fn layered_cached_word<'a>(
+    layers: &[&'a MarkovChain],
+    context: &[String],
+    opts: &GenerateOptions,
+) -> Option<&'a Word> {
+    let chain = layers
+        .iter()
+        .find(|chain| chain.lookup(context).is_some())?;
+    chain.cached_word(context, opts)
+}
+
+/// Shared generation loop; `next` gives the distribution for the current context
+fn run<'a, F>(start_word: &str, opts: &GenerateOptions, order: usize, next: F) -> String
+where
+    F: Fn(&[String]) -> Option<Vec<(&'a str, f64)>>,
+{
+    run_with(start_word, opts, order, next, None::<NoShortcut>)
+}
+
+/// [`run`], sampling from the cached table of the word `shortcut` finds
+/// whenever `next` would return it as is
+fn run_with<'a, 'w, F, W>(
+    start_word: &str,
+    opts: &GenerateOptions,
+    order: usize,
+    next: F,
+    shortcut: Option<W>,
+) -> String
+where
+    F: Fn(&[String]) -> Option<Vec<(&'a str, f64)>>,
+    W: Fn(&[String]) -> Option<&'w Word>,
+{
+    if opts.strategy == Strategy::Beam {
+        if let Some(tokens) = beam_search(start_word, opts, order, &next) {
+            return tokens.join(" ");
+        }
+    }
+    // **📌 長さが範囲から外れたら別の標本で作り直す**
+    opts.limits.retry(|| {
+        TokenStream::build(start_word, opts, order, &next, shortcut.as_ref())
+            .collect::<Vec<_>>()
+            .join(" ")
+    })
+}
+
+// **📌 Beam構造体：ビームサーチの途中の候補**
+struct Beam {
+    tokens: Vec<String>,
+    log_prob: f64,
+    /// Ended with a terminator or at a token nothing follows
+    done: bool,
+}
+
+impl Beam {
+    /// Average log probability per generated token, so long replies are not
+    /// penalized just for being long
+    fn score(&self) -> f64 {
+        self.log_prob / (self.tokens.len() - 1).max(1) as f64
+    }
+}
+
+/// Expand the `beam_width` most probable continuations per step and return
+/// the best-scoring one, preferring those that end a sentence. `None` when the
+/// start word leads nowhere, so the caller can fall back to sampling
+fn beam_search<'a, F>(
+    start_word: &str,
+    opts: &GenerateOptions,
+    order: usize,
+    next: &F,
+) -> Option<Vec<String>>
+where
+    F: Fn(&[String]) -> Option<Vec<(&'a str, f64)>>,
+{
+    let mut beams = vec![Beam {
+        tokens: vec![start_word.to_string()],
+        log_prob: 0.0,
+        done: false,
+    }];
+    for _ in 0..opts.length {
+        if beams.iter().all(|beam| beam.done) {
+            break;
+        }
+        let mut expanded = Vec::new();
+        for beam in beams {
+            if beam.done {
+                expanded.push(beam);
+                continue;
+            }
+            let context = &beam.tokens[beam.tokens.len().saturating_sub(order)..];
+            let distribution = next(context).unwrap_or_default();
+            let distribution = constrain(distribution, beam.tokens.last(), opts);
+            let mut distribution = discourage_repeats(distribution, &beam.tokens, opts);
+            if distribution.is_empty() {
+                expanded.push(Beam { done: true, ..beam });
+                continue;
+            }
+
+            // **📌 ブーストや語尾の重みを掛けてから上位だけを残す**
+            for (word, p) in &mut distribution {
+                *p *= weight_of(word, opts);
+            }
+            let total: f64 = distribution.iter().map(|(_, p)| p).sum();
+            distribution.retain(|(_, p)| *p > 0.0);
+            distribution.sort_by(|a, b| b.1.total_cmp(&a.1));
+            distribution.truncate(opts.beam_width);
+            for (word, p) in distribution {
+                let mut tokens = beam.tokens.clone();
+                tokens.push(word.to_string());
+                expanded.push(Beam {
+                    tokens,
+                    log_prob: beam.log_prob + (p / total).ln(),
+                    done: is_terminator(word),
+                });
+            }
+        }
+        expanded.sort_by(|a, b| b.score().total_cmp(&a.score()));
+        expanded.truncate(opts.beam_width);
+        beams = expanded;
+    }
+
+    // **📌 文を終えた候補があればその中から一番良いものを選ぶ**
+    let ended = |beam: &Beam| beam.tokens.last().is_some_and(|t| is_terminator(t));
+    let best = beams
+        .iter()
+        .filter(|beam| ended(beam))
+        .max_by(|a, b| a.score().total_cmp(&b.score()))
+        .or_else(|| beams.iter().max_by(|a, b| a.score().total_cmp(&b.score())))?;
+    if best.tokens.len() < 2 {
+        return None;
+    }
+    let mut tokens = best.tokens.clone();
+    if opts.no_leading_punctuation {
+        let leading = tokens
+            .iter()
+            .take_while(|token| TokenType::of(token).is_punctuation())
+            .count();
+        tokens.drain(..leading);
+    }
+    if tokens
+        .first()
+        .is_some_and(|token| opts.blocklist.blocks(token))
+    {
+        tokens.remove(0);
+    }
+    Some(tokens)
+}
+
+// **📌 TraceStep：生成の途中で下した判断の記録**
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum TraceStep {
+    /// `chosen` was sampled from `candidates`, each with its chance after the
+    /// constraints, repetition rules, weights and temperature, most likely first
+    Sampled {
+        context: Vec<String>,
+        candidates: Vec<(String, f64)>,
+        chosen: String,
+    },
+    /// Nothing followed `context`, so generation restarted from `word`
+    Fallback { context: Vec<String>, word: String },
+    /// Generation ended early at `context`
+    Stopped {
+        context: Vec<String>,
+        reason: &'static str,
+    },
+}
+
+/// Shortcut of a [`TokenStream`] built without one
+pub type NoShortcut = fn(&[String]) -> Option<&'static Word>;
+
+// **📌 TokenStream構造体：生成したトークンを1つずつ返すイテレーター**
+pub struct TokenStream<F, W = NoShortcut> {
+    next: F,
+    /// Word whose cached table `next` would return unchanged for a context,
+    /// to sample from without copying it
+    shortcut: Option<W>,
+    opts: GenerateOptions,
+    order: usize,
+    context: Vec<String>,
+    /// Every token emitted so far, for repetition control
+    history: Vec<String>,
+    /// Tokens still to generate
+    remaining: usize,
+    /// Start word, held back until it is known to lead somewhere
+    pending: Option<String>,
+    /// Whether a token other than leading punctuation has been yielded
+    started: bool,
+    fell_back: bool,
+    rng: ThreadRng,
+    /// Decisions recorded since [`traced`](Self::traced) was called
+    trace: Option<Vec<TraceStep>>,
+    /// Running totals of the last sampled weights, kept to reuse the buffer
+    cumulative: Vec<f64>,
+}
+
+impl<F, S> TokenStream<F>
+where
+    F: Fn(&[String]) -> Option<Vec<(S, f64)>>,
+    S: AsRef<str>,
+{
+    pub(crate) fn new(start_word: &str, opts: &GenerateOptions, order: usize, next: F) -> Self {
+        TokenStream::build(start_word, opts, order, next, None)
+    }
+}
+
+impl<'a, F, S, W> TokenStream<F, W>
+where
+    F: Fn(&[String]) -> Option<Vec<(S, f64)>>,
+    S: AsRef<str>,
+    W: Fn(&[String]) -> Option<&'a Word>,
+{
+    /// Like [`new`](TokenStream::new), sampling straight from the cached table
+    /// of the word `shortcut` finds whenever nothing would change its chances
+    pub(crate) fn cached(
+        start_word: &str,
+        opts: &GenerateOptions,
+        order: usize,
+        next: F,
+        shortcut: W,
+    ) -> Self {
+        Self::build(start_word, opts, order, next, Some(shortcut))
+    }
+
+    fn build(
+        start_word: &str,
+        opts: &GenerateOptions,
+        order: usize,
+        next: F,
+        shortcut: Option<W>,
+    ) -> Self {
+        Self {
+            next,
+            shortcut,
+            opts: opts.clone(),
+            order,
+            context: vec![start_word.to_string()],
+            history: vec![start_word.to_string()],
+            remaining: opts.length,
+            pending: Some(start_word.to_string()),
+            started: false,
+            fell_back: start_word == opts.fallback_word,
+            rng: rand::rng(),
+            trace: None,
+            cumulative: Vec::new(),
+        }
+    }
+
+    /// Record every decision, to be read with [`take_trace`](Self::take_trace)
+    pub fn traced(mut self) -> Self {
+        self.trace = Some(Vec::new());
+        self
+    }
+
+    /// Decisions recorded so far; empty unless the stream is [`traced`](Self::traced)
+    pub fn take_trace(&mut self) -> Vec<TraceStep> {
+        self.trace.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn record(&mut self, step: impl FnOnce(Vec<String>) -> TraceStep) {
+        if let Some(trace) = &mut self.trace {
+            trace.push(step(self.context.clone()));
+        }
+    }
+
+    /// Produce the next token, including leading punctuation
+    fn step(&mut self) -> Option<String> {
+        if let Some(start_word) = self.pending.take() {
+            // **📌 開始単語は続きがある場合だけ返す（禁止語なら続きだけ）**
+            if (self.remaining == 0 || self.peek_known())
+                && !self.opts.blocklist.blocks(&start_word)
+            {
+                return Some(start_word);
+            }
+            self.context = vec![start_word];
+        }
+        if self.remaining == 0 {
+            return None;
+        }
+        // **📌 分布を変えないときはキャッシュした表から選ぶ**
+        if let Some(next_word) = self.sample_cached() {
+            return Some(self.advance(next_word));
+        }
+
+        match (self.next)(&self.context) {
+            Some(distribution) if !distribution.is_empty() => {
+                // **📌 遷移の重みで重み付けしたランダム選択**
+                let distribution = distribution
+                    .iter()
+                    .map(|(word, p)| (word.as_ref(), *p))
+                    .collect();
+                let distribution = constrain(distribution, self.context.last(), &self.opts);
+                let distribution = discourage_repeats(distribution, &self.history, &self.opts);
+                // **📌 どの候補でもループになるならそこで終える**
+                if distribution.is_empty() {
+                    self.record(|context| TraceStep::Stopped {
+                        context,
+                        reason: "every candidate would repeat a loop",
+                    });
+                    return None;
+                }
+                let next_word = sample_with(
+                    &distribution,
+                    &self.opts,
+                    &mut self.rng,
+                    &mut self.cumulative,
+                )?
+                .to_string();
+                if self.trace.is_some() {
+                    let candidates = chances(&distribution, &self.opts);
+                    let chosen = next_word.clone();
+                    self.record(|context| TraceStep::Sampled {
+                        context,
+                        candidates,
+                        chosen,
+                    });
+                }
+                Some(self.advance(next_word))
+            }
+            // **📌 行き止まりになったらフォールバックの単語から続ける**
+            _ if !self.fell_back => {
+                let word = self.opts.fallback_word.clone();
+                self.record(|context| TraceStep::Fallback { context, word });
+                self.fell_back = true;
+                self.opts = self.opts.fallback();
+                self.remaining = self.opts.length;
+                self.context = vec![self.opts.fallback_word.clone()];
+                self.pending = Some(self.opts.fallback_word.clone());
+                self.step()
+            }
+            _ => {
+                self.record(|context| TraceStep::Stopped {
+                    context,
+                    reason: "nothing follows, even after the fallback word",
+                });
+                None
+            }
+        }
+    }
+
+    /// Sample from the cached table of the context's word, without copying
+    /// it, when no weight, temperature or penalty changes the chances. `None`
+    /// when there is no such table or the token drawn is one the constraints
+    /// or repetition rules remove; drawing again from what is left then keeps
+    /// the chances exact
+    fn sample_cached(&mut self) -> Option<String> {
+        if self.trace.is_some() || reweights(&self.opts) || self.opts.repetition_penalty < 1.0 {
+            return None;
+        }
+        let word = (self.shortcut.as_ref()?)(&self.context)?;
+        let next_word = word.sample(&mut self.rng)?;
+        allowed(next_word, self.context.last(), &self.history, &self.opts)
+            .then(|| next_word.to_string())
+    }
+
+    /// Count `next_word` as generated and move the context past it
+    fn advance(&mut self, next_word: String) -> String {
+        self.remaining -= 1;
+        self.history.push(next_word.clone());
+        self.context.push(next_word.clone());
+        if self.context.len() > self.order {
+            self.context.remove(0);
+        }
+        next_word
+    }
+
+    fn peek_known(&self) -> bool {
+        (self.next)(&self.context).is_some_and(|distribution| !distribution.is_empty())
+    }
+}
+
+impl<'a, F, S, W> Iterator for TokenStream<F, W>
+where
+    F: Fn(&[String]) -> Option<Vec<(S, f64)>>,
+    S: AsRef<str>,
+    W: Fn(&[String]) -> Option<&'a Word>,
+{
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        loop {
+            let token = self.step()?;
+            // **📌 文頭の記号を取り除く**
+            if self.started
+                || !self.opts.no_leading_punctuation
+                || !TokenType::of(&token).is_punctuation()
+            {
+                self.started = true;
+                return Some(token);
+            }
+        }
+    }
+}
+
+/// Remove banned candidates, then those the type constraints forbid after
+/// `previous` unless that would leave nothing to sample
+fn constrain<'a>(
+    mut distribution: Vec<(&'a str, f64)>,
+    previous: Option<&String>,
+    opts: &GenerateOptions,
+) -> Vec<(&'a str, f64)> {
+    if !opts.blocklist.is_empty() {
+        distribution.retain(|(word, _)| !opts.blocklist.blocks(word));
+    }
+    let after_punctuation = previous.is_some_and(|w| TokenType::of(w).is_punctuation());
+    if !(opts.no_double_punctuation && after_punctuation) {
+        return distribution;
+    }
+    let allowed: Vec<(&str, f64)> = distribution
+        .iter()
+        .filter(|(word, _)| !TokenType::of(word).is_punctuation())
+        .copied()
+        .collect();
+    if allowed.is_empty() {
+        distribution
+    } else {
+        allowed
+    }
+}
+
+/// Apply the repetition rules of `opts` to the candidates following `history`:
+/// drop an immediate repeat and candidates that close a loop, and penalize
+/// n-grams seen recently. Empty when every candidate would close a loop
+fn discourage_repeats<'a>(
+    distribution: Vec<(&'a str, f64)>,
+    history: &[String],
+    opts: &GenerateOptions,
+) -> Vec<(&'a str, f64)> {
+    let mut distribution = distribution;
+    if opts.no_immediate_repeat {
+        if let Some(last) = history.last() {
+            if distribution.iter().any(|(word, _)| word != last) {
+                distribution.retain(|(word, _)| word != last);
+            }
+        }
+    }
+
+    // **📌 直前の並びをそのまま繰り返す候補は選ばない**
+    distribution.retain(|(word, _)| !closes_loop(word, history, opts.loop_window));
+
+    if opts.repetition_penalty < 1.0 {
+        let n = opts.repetition_ngram;
+        let window = &history[history.len().saturating_sub(opts.repetition_window)..];
+        let prefix = &history[history.len().saturating_sub(n - 1)..];
+        if prefix.len() == n - 1 {
+            for (word, p) in &mut distribution {
+                let seen = window
+                    .windows(n)
+                    .filter(|gram| gram[..n - 1] == *prefix && gram[n - 1] == *word)
+                    .count();
+                *p *= opts.repetition_penalty.powi(seen as i32);
+            }
+        }
+    }
+    distribution
+}
+
+/// Whether appending `word` to `history` repeats its last 2 to `loop_window`
+/// tokens right away
+fn closes_loop(word: &str, history: &[String], loop_window: usize) -> bool {
+    (2..=loop_window).any(|period| {
+        let len = history.len() + 1;
+        if len < 2 * period {
+            return false;
+        }
+        let at = |i: usize| history.get(i).map_or(word, String::as_str);
+        (0..period).all(|i| at(len - 1 - i) == at(len - 1 - period - i))
+    })
+}
+
+/// Whether `word` passes, on its own, every rule of [`constrain`] and
+/// [`discourage_repeats`] that removes candidates. Those rules only keep a
+/// rejected candidate when all of them would be rejected
+fn allowed(
+    word: &str,
+    previous: Option<&String>,
+    history: &[String],
+    opts: &GenerateOptions,
+) -> bool {
+    let after_punctuation = previous.is_some_and(|w| TokenType::of(w).is_punctuation());
+    let double_punctuation =
+        opts.no_double_punctuation && after_punctuation && TokenType::of(word).is_punctuation();
+    let repeat = opts.no_immediate_repeat && history.last().is_some_and(|last| last == word);
+    !opts.blocklist.blocks(word)
+        && !double_punctuation
+        && !repeat
+        && !closes_loop(word, history, opts.loop_window)
+}
+
+/// Decayed or unlearned counts smaller than this are dropped
+const FORGET_BELOW: f64 = 0.01;
+
+/// Every context along `tokens`, from one up to `order` tokens joined with
+/// spaces, with the token following it
+pub(crate) fn transitions_along<'a>(tokens: &[&'a str], order: usize) -> Vec<(String, &'a str)> {
+    (0..tokens.len().saturating_sub(1))
+        .flat_map(|at| {
+            (1..=order.min(at + 1))
+                .map(move |n| (tokens[at + 1 - n..=at].join(" "), tokens[at + 1]))
+        })
+        .collect()
+}
+
+/// Whether two counts or probabilities are equal up to rounding
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.0)
+}
+
+/// Pseudo-count added to every transition when scoring text
+const SMOOTHING_K: f64 = 0.1;
+
+/// Sentence-final tokens
+const TERMINATORS: &[&str] = &["。", "！", "？", "!", "?", ".", "…", "♪"];
+
+/// Whether the token ends a sentence
+pub fn is_terminator(token: &str) -> bool {
+    TERMINATORS.contains(&token)
+}
+
+/// Whether `opts` change the probabilities a token is sampled with
+fn reweights(opts: &GenerateOptions) -> bool {
+    opts.temperature != 1.0 || !opts.boost.is_empty() || !opts.endings.is_empty()
+}
+
+/// Factor the boosts and ending weights of `opts` apply to a candidate
+fn weight_of(word: &str, opts: &GenerateOptions) -> f64 {
+    let boost = 1.0 + opts.boost.get(word).copied().unwrap_or(0.0);
+    let ending = opts.endings.get(word).copied().unwrap_or(1.0);
+    boost * ending
+}
+
+/// Chance of each candidate being sampled, most likely first
+fn chances(distribution: &[(&str, f64)], opts: &GenerateOptions) -> Vec<(String, f64)> {
+    let mut weights: Vec<f64> = distribution
+        .iter()
+        .map(|(word, p)| (p * weight_of(word, opts)).powf(1.0 / opts.temperature))
+        .collect();
+    // **📌 sample と同じく、重みが全部0なら元の確率で**
+    if weights.iter().sum::<f64>() <= 0.0 {
+        weights = distribution
+            .iter()
+            .map(|(_, p)| p.powf(1.0 / opts.temperature))
+            .collect();
+    }
+    let total: f64 = weights.iter().sum();
+    let mut chances: Vec<(String, f64)> = distribution
+        .iter()
+        .zip(&weights)
+        .map(|((word, _), weight)| (word.to_string(), weight / total))
+        .collect();
+    chances.sort_by(|a, b| b.1.total_cmp(&a.1));
+    chances
+}
+
+/// Sample one token after applying the boosts, ending weights and temperature
+/// from `opts`; `None` when no candidate has any weight left
+fn sample<'a, R: Rng>(
+    distribution: &[(&'a str, f64)],
+    opts: &GenerateOptions,
+    rng: &mut R,
+) -> Option<&'a str> {
+    sample_with(distribution, opts, rng, &mut Vec::new())
+}
+
+/// [`sample`], keeping the running totals of the weights in `cumulative` so
+/// a caller sampling step after step reuses one buffer
+fn sample_with<'a, R: Rng>(
+    distribution: &[(&'a str, f64)],
+    opts: &GenerateOptions,
+    rng: &mut R,
+    cumulative: &mut Vec<f64>,
+) -> Option<&'a str> {
+    let exponent = 1.0 / opts.temperature;
+    let weighted = distribution
+        .iter()
+        .map(|(word, p)| (p * weight_of(word, opts)).powf(exponent));
+
+    // **📌 累積の重みから二分探索で選ぶ（語尾の重みで全部0になったら元の確率で）**
+    if !accumulate(cumulative, weighted)
+        && !accumulate(
+            cumulative,
+            distribution.iter().map(|(_, p)| p.powf(exponent)),
+        )
+    {
+        return None;
+    }
+    let target = rng.random::<f64>() * cumulative.last()?;
+    let index = cumulative.partition_point(|&total| total <= target);
+    Some(distribution[index.min(distribution.len() - 1)].0)
+}
+
+/// Fill `cumulative` with the running totals of `weights`; `false` when one
+/// is negative or not finite or they add up to nothing, as `WeightedIndex`
+/// would refuse them
+fn accumulate(cumulative: &mut Vec<f64>, weights: impl Iterator<Item = f64>) -> bool {
+    cumulative.clear();
+    let mut total = 0.0;
+    for weight in weights {
+        if !(weight.is_finite() && weight >= 0.0) {
+            return false;
+        }
+        total += weight;
+        cumulative.push(total);
+    }
+    total > 0.0 && total.is_finite()
+}
@@ -0,0 +1,231 @@
+use crate::bridge::{self, addressed, Bridge};
+use reqwest::{Client, Method, RequestBuilder, Url};
+use serde_json::{json, Value};
+use std::{collections::HashSet, env, io, time::Duration};
+use wordora_ai::{config::Config, layered::LayeredModel, trigger::Incoming};
+
+/// How long the homeserver holds a sync request open waiting for events
+const SYNC_TIMEOUT_MS: u64 = 30_000;
+
+// **📌 Homeserver構造体：Matrixのクライアント API を呼ぶ**
+struct Homeserver {
+    client: Client,
+    base: Url,
+    token: String,
+    /// Transaction IDs of sent messages, unique within this run
+    txn: u64,
+}
+
+impl Homeserver {
+    /// Request to `/_matrix/client/v3/` followed by `segments`, each
+    /// percent-encoded so room IDs and aliases stay one segment
+    fn request(&self, method: Method, segments: &[&str]) -> io::Result<RequestBuilder> {
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .map_err(|_| invalid("bridge.matrix.homeserver cannot be a base URL"))?
+            .pop_if_empty()
+            .extend(["_matrix", "client", "v3"])
+            .extend(segments);
+        Ok(self.client.request(method, url).bearer_auth(&self.token))
+    }
+
+    async fn call(&self, request: RequestBuilder) -> io::Result<Value> {
+        let response = request.send().await.map_err(io::Error::other)?;
+        let status = response.status();
+        let body: Value = response.json().await.map_err(io::Error::other)?;
+        if !status.is_success() {
+            let error = body["error"].as_str().unwrap_or("no error message");
+            return Err(io::Error::other(format!("Matrix {}: {}", status, error)));
+        }
+        Ok(body)
+    }
+
+    /// ID of the user the access token belongs to
+    async fn whoami(&self) -> io::Result<String> {
+        let body = self
+            .call(self.request(Method::GET, &["account", "whoami"])?)
+            .await?;
+        body["user_id"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| io::Error::other("whoami returned no user_id"))
+    }
+
+    /// Join a room by ID or alias and return its ID
+    async fn join(&self, room: &str) -> io::Result<String> {
+        let body = self
+            .call(
+                self.request(Method::POST, &["join", room])?
+                    .json(&json!({})),
+            )
+            .await?;
+        body["room_id"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| io::Error::other(format!("joining {} returned no room_id", room)))
+    }
+
+    async fn sync(&self, since: Option<&str>, timeout_ms: u64) -> io::Result<Value> {
+        let timeout = timeout_ms.to_string();
+        let mut query = vec![("timeout", timeout.as_str())];
+        query.extend(since.map(|since| ("since", since)));
+        let request = self
+            .request(Method::GET, &["sync"])?
+            .query(&query)
+            .timeout(Duration::from_millis(timeout_ms) + Duration::from_secs(30));
+        self.call(request).await
+    }
+
+    /// Send a text message to a room and return its event ID
+    async fn send(&mut self, room_id: &str, body: &str) -> io::Result<String> {
+        self.txn += 1;
+        let txn = format!("wordora-{}-{}", std::process::id(), self.txn);
+        let request = self
+            .request(
+                Method::PUT,
+                &["rooms", room_id, "send", "m.room.message", &txn],
+            )?
+            .json(&json!({ "msgtype": "m.text", "body": body }));
+        let body = self.call(request).await?;
+        Ok(body["event_id"].as_str().unwrap_or_default().to_string())
+    }
+
+    /// Show the bot as typing in a room for `delay`
+    async fn typing(&self, room_id: &str, user_id: &str, delay: Duration) -> io::Result<()> {
+        let request = self
+            .request(Method::PUT, &["rooms", room_id, "typing", user_id])?
+            .json(&json!({ "typing": true, "timeout": delay.as_millis() as u64 }));
+        self.call(request).await.map(|_| ())
+    }
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.into())
+}
+
+/// Localpart of a user ID, `wordora` for `@wordora:matrix.org`
+fn localpart(user_id: &str) -> &str {
+    let name = user_id.strip_prefix('@').unwrap_or(user_id);
+    name.split(':').next().unwrap_or(name)
+}
+
+// **🤖 Matrixのルームでチャット**
+pub async fn run(config: &Config, model: LayeredModel) -> io::Result<()> {
+    let matrix = &config.bridge.matrix;
+    let token = env::var(&matrix.token_env).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("set {} to the bot's access token", matrix.token_env),
+        )
+    })?;
+    let base = Url::parse(&matrix.homeserver)
+        .map_err(|e| invalid(format!("bridge.matrix.homeserver: {}", e)))?;
+    let mut server = Homeserver {
+        client: Client::new(),
+        base,
+        token,
+        txn: 0,
+    };
+    let mut bridge = Bridge::new(config, model)?;
+
+    let user_id = server.whoami().await?;
+    let mut rooms = HashSet::new();
+    for room in &matrix.rooms {
+        rooms.insert(server.join(room).await?);
+    }
+    println!("🤖 connected to Matrix as {}", user_id);
+
+    let result = session(&mut bridge, &mut server, &user_id, &rooms).await;
+    bridge.save();
+    result
+}
+
+/// Answer in the joined rooms until Ctrl-C is pressed
+async fn session(
+    bridge: &mut Bridge,
+    server: &mut Homeserver,
+    user_id: &str,
+    rooms: &HashSet<String>,
+) -> io::Result<()> {
+    let typing = bridge.config().typing.clone();
+    let name = localpart(user_id).to_string();
+    // **📌 最初の同期は位置を得るだけで、過去の発言には答えない**
+    let mut since = server.sync(None, 0).await?["next_batch"]
+        .as_str()
+        .map(str::to_string);
+    // **📌 ボットの発言への返信を見分けるため、送ったイベントを覚える**
+    let mut sent = HashSet::new();
+
+    let mut autosave = bridge::autosave_timer(bridge.config());
+    loop {
+        let batch = tokio::select! {
+            batch = server.sync(since.as_deref(), SYNC_TIMEOUT_MS) => match batch {
+                Ok(batch) => batch,
+                Err(e) => {
+                    eprintln!("⚠️ Matrix sync failed, retrying: {}", e);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    continue;
+                }
+            },
+            _ = bridge::tick(&mut autosave) => {
+                bridge.save();
+                continue;
+            }
+            _ = tokio::signal::ctrl_c() => {
+                println!("🛑 interrupted, saving bridge model");
+                return Ok(());
+            }
+        };
+        if let Some(next) = batch["next_batch"].as_str() {
+            since = Some(next.to_string());
+        }
+        let Some(joined) = batch["rooms"]["join"].as_object() else {
+            continue;
+        };
+        for (room_id, room) in joined.iter().filter(|(id, _)| rooms.contains(*id)) {
+            let Some(events) = room["timeline"]["events"].as_array() else {
+                continue;
+            };
+            for event in events {
+                let content = &event["content"];
+                let sender = event["sender"].as_str().unwrap_or_default();
+                if event["type"] != "m.room.message"
+                    || content["msgtype"] != "m.text"
+                    || sender == user_id
+                {
+                    continue;
+                }
+                let Some(text) = content["body"].as_str() else {
+                    continue;
+                };
+                let called = addressed(text, &name);
+                let mentions = content["m.mentions"]["user_ids"]
+                    .as_array()
+                    .is_some_and(|ids| ids.iter().any(|id| id == user_id));
+                let incoming = Incoming {
+                    text,
+                    mentioned: mentions
+                        || called.is_some()
+                        || text.to_lowercase().contains(&name.to_lowercase()),
+                    reply_to_bot: content["m.relates_to"]["m.in_reply_to"]["event_id"]
+                        .as_str()
+                        .is_some_and(|id| sent.contains(id)),
+                };
+                let Some(reply) = bridge.hear(room_id, sender, &incoming, called.unwrap_or(text))
+                else {
+                    continue;
+                };
+                if let Some(delay) = typing.delay_for(&reply) {
+                    let _ = server.typing(room_id, user_id, delay).await;
+                    tokio::time::sleep(delay).await;
+                }
+                match server.send(room_id, &reply).await {
+                    Ok(event_id) => {
+                        sent.insert(event_id);
+                    }
+                    Err(e) => eprintln!("⚠️ Failed to send to {}: {}", room_id, e),
+                }
+            }
+        }
+    }
+}
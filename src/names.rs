@@ -0,0 +1,87 @@
+use crate::{
+    markov::{GenerateOptions, MarkovChain},
+    tokenizer::{TokenType, TokenizerConfig, TokenizerKind},
+};
+use std::collections::HashSet;
+
+/// Markers around each word in the character model
+const BEGIN: &str = "^";
+const END: &str = "$";
+
+/// Attempts per requested name before giving up
+const ATTEMPTS_PER_NAME: usize = 50;
+
+// **📌 NameOptions構造体：名前生成の設定**
+#[derive(Debug, Clone)]
+pub struct NameOptions {
+    pub count: usize,
+    /// Length bounds in characters
+    pub min: usize,
+    pub max: usize,
+    /// Characters of context used by the character model
+    pub order: usize,
+}
+
+impl Default for NameOptions {
+    fn default() -> Self {
+        Self {
+            count: 10,
+            min: 2,
+            max: 4,
+            order: 2,
+        }
+    }
+}
+
+/// Generate plausible new words from a character model of the vocabulary of
+/// `chain`. Words that already occur in the vocabulary are excluded.
+pub fn generate_names(chain: &MarkovChain, opts: &NameOptions) -> Vec<String> {
+    let tokenizer = TokenizerConfig {
+        kind: TokenizerKind::Char,
+        ..TokenizerConfig::default()
+    };
+    let mut chars = MarkovChain::new(opts.order, tokenizer);
+
+    // **📌 名前らしい漢字だけ・カタカナだけの単語を1文字ずつ学習**
+    let known: HashSet<&str> = chain.words().map(|word| word.word.as_str()).collect();
+    for word in chain
+        .words()
+        .filter(|word| matches!(word.kind, TokenType::Kanji | TokenType::Katakana))
+    {
+        chars.learn(&format!("{}{}{}", BEGIN, word.word, END));
+    }
+
+    let generate = GenerateOptions {
+        length: opts.max + 1,
+        fallback_length: 0,
+        no_double_punctuation: false,
+        no_leading_punctuation: false,
+        ..GenerateOptions::default()
+    };
+    let mut names = Vec::new();
+    let mut seen = HashSet::new();
+    for _ in 0..opts.count * ATTEMPTS_PER_NAME {
+        if names.len() >= opts.count {
+            break;
+        }
+        // **📌 終わりの印が出るまでを1つの名前とする**
+        let mut ended = false;
+        let name: String = chars
+            .generate_iter(BEGIN, &generate)
+            .skip(1)
+            .take_while(|c| {
+                ended = c == END;
+                !ended
+            })
+            .collect();
+        let length = name.chars().count();
+        if ended
+            && (opts.min..=opts.max).contains(&length)
+            && !known.contains(name.as_str())
+            && seen.insert(name.clone())
+        {
+            names.push(name);
+        }
+    }
+    names
+}
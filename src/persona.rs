@@ -0,0 +1,126 @@
+use crate::markov::MarkovChain;
+use serde::Deserialize;
+use std::{collections::HashMap, io, path::PathBuf};
+
+// **📌 PersonaConfig構造体：名前を付けて読み込むモデル**
+#[derive(Debug, Clone, Deserialize)]
+pub struct PersonaConfig {
+    pub name: String,
+    pub path: PathBuf,
+    /// Weights for sentence endings, as the tokenizer writes them, replacing
+    /// those of `generation.endings` to give the persona its tone, e.g.
+    /// `{ "!" = 2.0, "。" = 0.5 }`
+    #[serde(default)]
+    pub endings: HashMap<String, f64>,
+}
+
+// **📌 Personas構造体：切り替えられる名前付きのモデル**
+#[derive(Clone, Default)]
+pub struct Personas {
+    models: Vec<(String, MarkovChain)>,
+    endings: Vec<HashMap<String, f64>>,
+}
+
+impl Personas {
+    pub fn load(configs: &[PersonaConfig]) -> io::Result<Self> {
+        let models = configs
+            .iter()
+            .map(|persona| Ok((persona.name.clone(), MarkovChain::load(&persona.path)?)))
+            .collect::<io::Result<_>>()?;
+        Ok(Self {
+            models,
+            endings: configs.iter().map(|p| p.endings.clone()).collect(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.models.is_empty()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.models.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Parse a selection such as `polite` or `polite:0.7,casual:0.3` into persona
+    /// indices and weights; names are separated by commas or spaces and weigh 1
+    /// unless given
+    pub fn select(&self, spec: &str) -> Result<Vec<(usize, f64)>, String> {
+        let selection: Vec<(usize, f64)> = spec
+            .split([',', ' '])
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                let (name, weight) = match part.split_once(':') {
+                    Some((name, weight)) => {
+                        let weight: f64 = weight
+                            .parse()
+                            .map_err(|_| format!("invalid weight {:?}", weight))?;
+                        (name, weight)
+                    }
+                    None => (part, 1.0),
+                };
+                if !(weight.is_finite() && weight > 0.0) {
+                    return Err(format!("weight of {} must be positive", name));
+                }
+                let index = self
+                    .models
+                    .iter()
+                    .position(|(n, _)| n == name)
+                    .ok_or_else(|| format!("unknown persona {:?}", name))?;
+                Ok((index, weight))
+            })
+            .collect::<Result<_, _>>()?;
+        if selection.is_empty() {
+            return Err("no persona given".to_string());
+        }
+        Ok(selection)
+    }
+
+    /// Models of a selection, ready for [`generate_blend`](crate::markov::generate_blend)
+    pub fn chains(&self, selection: &[(usize, f64)]) -> Vec<(&MarkovChain, f64)> {
+        selection
+            .iter()
+            .map(|&(index, weight)| (&self.models[index].1, weight))
+            .collect()
+    }
+
+    /// Ending weights of a selection: each persona's weight for an ending, or
+    /// `base`'s if it sets none, averaged by the personas' weights
+    pub fn endings(
+        &self,
+        selection: &[(usize, f64)],
+        base: &HashMap<String, f64>,
+    ) -> HashMap<String, f64> {
+        let total: f64 = selection.iter().map(|(_, weight)| weight).sum();
+        if selection.is_empty() || total <= 0.0 {
+            return base.clone();
+        }
+        let mut keys: Vec<&String> = base.keys().collect();
+        for (index, _) in selection {
+            keys.extend(self.endings[*index].keys());
+        }
+        keys.into_iter()
+            .map(|key| {
+                let mixed = selection
+                    .iter()
+                    .map(|(index, weight)| {
+                        let own = self.endings[*index].get(key).or(base.get(key));
+                        weight * own.copied().unwrap_or(1.0)
+                    })
+                    .sum::<f64>();
+                (key.clone(), mixed / total)
+            })
+            .collect()
+    }
+
+    /// Describe a selection as it would be written, e.g. `polite:0.7,casual:0.3`
+    pub fn describe(&self, selection: &[(usize, f64)]) -> String {
+        match selection {
+            [(index, _)] => self.models[*index].0.clone(),
+            _ => selection
+                .iter()
+                .map(|(index, weight)| format!("{}:{}", self.models[*index].0, weight))
+                .collect::<Vec<_>>()
+                .join(","),
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use crate::{
+    error::{self, WordoraError},
+    markov::MarkovChain,
+    tokenizer::TokenizerConfig,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs,
+    path::Path,
+};
+
+// **📌 ポータブル形式（JSON、バージョン 1）**
+//
+// {
+//   "format": "wordora-model",
+//   "version": 1,
+//   "order": 2,
+//   "tokenizer": { "kind": "regex", "pattern": "...", "nfkc": true, ... },
+//   "documents": 120.0,
+//   "tokens": [
+//     { "token": "今日", "meta": { "reading": "きょう" }, "documents": 3.0 }
+//   ],
+//   "transitions": [
+//     { "context": ["今日", "は"], "next": "晴れ", "count": 2.0 }
+//   ],
+//   "responses": [
+//     { "token": "映画", "start": "私", "count": 1.0 }
+//   ]
+// }
+//
+// format, version, order, tokens and transitions are required, the rest optional
+// tokenizer      fields of the [tokenizer] config section; missing ones take their defaults
+// documents      weighted number of texts learned, for salience; per token, the texts containing it
+// tokens         every token used below, once each; meta holds free-form string annotations
+// transitions    context of 1..=order tokens followed by next, count times; once per context and next
+// responses      response pairs: a reply to a prompt containing token started with start
+// counts are finite and positive. Unknown fields are rejected, and so is any
+// other version. Per-source tracking (`forget --source`) and topics are not part of it
+
+/// Value of the `format` field
+pub const FORMAT: &str = "wordora-model";
+/// Version of the format written, and the only one read
+pub const VERSION: u32 = 1;
+
+// **📌 PortableModel構造体：モデルの JSON 表現**
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PortableModel {
+    pub format: String,
+    pub version: u32,
+    pub order: usize,
+    /// Checked field by field on import, see [`PortableModel::into_chain`]
+    #[serde(default)]
+    pub tokenizer: Value,
+    #[serde(default)]
+    pub documents: f64,
+    pub tokens: Vec<PortableToken>,
+    pub transitions: Vec<PortableTransition>,
+    #[serde(default)]
+    pub responses: Vec<PortableResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PortableToken {
+    pub token: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub meta: BTreeMap<String, String>,
+    /// Texts learned that contained the token
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub documents: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PortableTransition {
+    pub context: Vec<String>,
+    pub next: String,
+    pub count: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PortableResponse {
+    pub token: String,
+    pub start: String,
+    pub count: f64,
+}
+
+fn is_zero(value: &f64) -> bool {
+    *value == 0.0
+}
+
+fn invalid(reason: String) -> WordoraError {
+    WordoraError::InvalidModelFile(reason)
+}
+
+impl PortableModel {
+    /// Portable form of `chain`, with tokens and transitions sorted so the
+    /// same model always gives the same file
+    pub fn from_chain(chain: &MarkovChain) -> Self {
+        let mut transitions: Vec<PortableTransition> = chain
+            .words()
+            .chain(chain.contexts())
+            .flat_map(|entry| {
+                let context: Vec<String> = entry.word.split(' ').map(str::to_string).collect();
+                entry
+                    .transitions
+                    .iter()
+                    .map(move |(next, &count)| PortableTransition {
+                        context: context.clone(),
+                        next: next.clone(),
+                        count,
+                    })
+            })
+            .collect();
+        transitions.sort_by(|a, b| (&a.context, &a.next).cmp(&(&b.context, &b.next)));
+        let mut responses: Vec<PortableResponse> = chain
+            .responses()
+            .flat_map(|entry| {
+                entry
+                    .transitions
+                    .iter()
+                    .map(|(start, &count)| PortableResponse {
+                        token: entry.word.clone(),
+                        start: start.clone(),
+                        count,
+                    })
+            })
+            .collect();
+        responses.sort_by(|a, b| (&a.token, &a.start).cmp(&(&b.token, &b.start)));
+
+        // **📌 応答ペアにしか出てこない単語も tokens に載せる**
+        let mut names: BTreeSet<&str> = chain.words().map(|word| word.word.as_str()).collect();
+        for response in &responses {
+            names.insert(&response.token);
+            names.insert(&response.start);
+        }
+        let tokens = names
+            .into_iter()
+            .map(|token| PortableToken {
+                token: token.to_string(),
+                meta: chain
+                    .word(token)
+                    .map(|word| word.meta.clone())
+                    .unwrap_or_default(),
+                documents: chain.document_frequency(token),
+            })
+            .collect();
+
+        Self {
+            format: FORMAT.to_string(),
+            version: VERSION,
+            order: chain.order(),
+            tokenizer: serde_json::to_value(chain.tokenizer()).unwrap_or_default(),
+            documents: chain.documents(),
+            tokens,
+            transitions,
+            responses,
+        }
+    }
+
+    /// Check the model against the schema and build the chain from it
+    pub fn into_chain(self) -> error::Result<MarkovChain> {
+        if self.format != FORMAT {
+            return Err(invalid(format!(
+                "format must be {:?}, not {:?}",
+                FORMAT, self.format
+            )));
+        }
+        if self.version != VERSION {
+            return Err(invalid(format!(
+                "unsupported version {} (this build reads version {})",
+                self.version, VERSION
+            )));
+        }
+        if self.order == 0 {
+            return Err(invalid("order must be at least 1".to_string()));
+        }
+        let tokenizer = tokenizer(self.tokenizer)?;
+        tokenizer.validate()?;
+        check_count("documents", self.documents, true)?;
+
+        let mut known: HashSet<&str> = HashSet::new();
+        for token in &self.tokens {
+            if token.token.is_empty() || token.token.contains(char::is_whitespace) {
+                return Err(invalid(format!(
+                    "token {:?} must be non-empty without whitespace",
+                    token.token
+                )));
+            }
+            if !known.insert(&token.token) {
+                return Err(invalid(format!("token {:?} is listed twice", token.token)));
+            }
+            check_count("token documents", token.documents, true)?;
+        }
+        let check_known = |token: &str| {
+            if known.contains(token) {
+                Ok(())
+            } else {
+                Err(invalid(format!("{:?} is not in tokens", token)))
+            }
+        };
+
+        let mut chain = MarkovChain::new(self.order, tokenizer);
+        chain.set_documents(self.documents);
+        let mut seen: HashSet<(&[String], &str)> = HashSet::new();
+        for transition in &self.transitions {
+            let length = transition.context.len();
+            if length == 0 || length > self.order {
+                return Err(invalid(format!(
+                    "context {:?} must have 1 to {} tokens",
+                    transition.context, self.order
+                )));
+            }
+            for token in &transition.context {
+                check_known(token)?;
+            }
+            check_known(&transition.next)?;
+            check_count("transition count", transition.count, false)?;
+            if !seen.insert((&transition.context, &transition.next)) {
+                return Err(invalid(format!(
+                    "transition {:?} -> {:?} is listed twice",
+                    transition.context, transition.next
+                )));
+            }
+        }
+        let mut seen: HashSet<(&str, &str)> = HashSet::new();
+        for response in &self.responses {
+            check_known(&response.token)?;
+            check_known(&response.start)?;
+            check_count("response count", response.count, false)?;
+            if !seen.insert((&response.token, &response.start)) {
+                return Err(invalid(format!(
+                    "response {:?} -> {:?} is listed twice",
+                    response.token, response.start
+                )));
+            }
+        }
+
+        for token in &self.tokens {
+            chain.insert_token(&token.token, token.meta.clone(), token.documents);
+        }
+        for transition in &self.transitions {
+            chain.insert_transition(&transition.context, &transition.next, transition.count);
+        }
+        for response in &self.responses {
+            chain.insert_response(&response.token, &response.start, response.count);
+        }
+        chain.rebuild_index();
+        Ok(chain)
+    }
+}
+
+/// Tokenizer settings of a portable model; unknown fields are rejected
+/// instead of ignored as in the config file
+fn tokenizer(value: Value) -> error::Result<TokenizerConfig> {
+    let value = match value {
+        Value::Null => return Ok(TokenizerConfig::default()),
+        Value::Object(fields) => {
+            let defaults = serde_json::to_value(TokenizerConfig::default()).unwrap_or_default();
+            if let Some(unknown) = fields.keys().find(|key| defaults.get(key).is_none()) {
+                return Err(invalid(format!("unknown tokenizer field {:?}", unknown)));
+            }
+            Value::Object(fields)
+        }
+        _ => return Err(invalid("tokenizer must be an object".to_string())),
+    };
+    serde_json::from_value(value).map_err(|e| invalid(format!("tokenizer: {}", e)))
+}
+
+fn check_count(what: &str, count: f64, zero: bool) -> error::Result<()> {
+    if count.is_finite() && (count > 0.0 || zero && count == 0.0) {
+        Ok(())
+    } else if zero {
+        Err(invalid(format!(
+            "{} must not be negative, got {}",
+            what, count
+        )))
+    } else {
+        Err(invalid(format!("{} must be positive, got {}", what, count)))
+    }
+}
+
+/// Write `chain` in the portable JSON format
+pub fn save_json(chain: &MarkovChain, path: &Path) -> error::Result<()> {
+    let json = serde_json::to_string_pretty(&PortableModel::from_chain(chain))
+        .map_err(|e| invalid(e.to_string()))?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+/// Read a model in the portable JSON format, checking it against the schema
+pub fn load_json(path: &Path) -> error::Result<MarkovChain> {
+    let text = fs::read_to_string(path)?;
+    let model: PortableModel = serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+    model.into_chain()
+}
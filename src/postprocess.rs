@@ -0,0 +1,151 @@
+use crate::{markov::is_terminator, tokenizer::TokenType};
+use serde::Deserialize;
+
+/// Join generated tokens into display text.
+///
+/// Japanese needs no spaces, so tokens are concatenated, except that a space is
+/// kept between adjacent Latin words and after punctuation inside or at the end
+/// of a Latin sentence ("Hello, world! How are you").
+pub fn repair_spacing(tokens: &[&str]) -> String {
+    let mut text = String::new();
+    let mut previous: Option<&str> = None;
+    let mut latin_sentence = false;
+
+    for &token in tokens {
+        let kind = TokenType::of(token);
+        if let Some(prev) = previous {
+            let prev_kind = TokenType::of(prev);
+            let between_words = is_latin_word(prev_kind) && is_latin_word(kind);
+            let after_break = latin_sentence && is_latin_break(prev) && is_latin_word(kind);
+            if between_words || after_break {
+                text.push(' ');
+            }
+        }
+        text.push_str(token);
+
+        // **📌 区切りの記号の直前までラテン文字の文が続いていたか**
+        if is_latin_word(kind) {
+            latin_sentence = true;
+        } else if !is_latin_break(token) {
+            latin_sentence = false;
+        }
+        previous = Some(token);
+    }
+    text
+}
+
+fn is_latin_word(kind: TokenType) -> bool {
+    matches!(kind, TokenType::Latin | TokenType::Number)
+}
+
+/// Punctuation followed by a space when Latin text continues after it
+fn is_latin_break(token: &str) -> bool {
+    matches!(
+        token,
+        "." | "!" | "?" | "," | ";" | ":" | "。" | "！" | "？"
+    )
+}
+
+// **📌 ReplyFilter：表示する前に返事のトークンを書き換える段階**
+pub trait ReplyFilter {
+    fn apply(&self, tokens: Vec<String>) -> Vec<String>;
+}
+
+/// Run `tokens` through every filter in order
+pub fn apply_filters<F: ReplyFilter>(filters: &[F], tokens: Vec<String>) -> Vec<String> {
+    filters
+        .iter()
+        .fold(tokens, |tokens, filter| filter.apply(tokens))
+}
+
+// **📌 FilterStage：設定の `postprocess = [...]` に並べる組み込みの段階**
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FilterStage {
+    /// Drop particles such as `が` or `、` left dangling at the end
+    TrimParticles,
+    /// End the reply with `。`, or `.` after Latin text, unless it already
+    /// ends with a terminator or an emoji
+    EnsureTerminator,
+    /// Capitalize the first letter of each Latin sentence
+    Capitalize,
+    /// Wrap the reply in a template such as `"{reply}ですね"`
+    Template(String),
+}
+
+impl FilterStage {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::Template(template) if !template.contains(REPLY) => {
+                Err(format!("template {:?} has no {}", template, REPLY))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl ReplyFilter for FilterStage {
+    fn apply(&self, mut tokens: Vec<String>) -> Vec<String> {
+        if tokens.is_empty() {
+            return tokens;
+        }
+        match self {
+            Self::TrimParticles => {
+                // **📌 全部が助詞なら何も残らないので消さない**
+                let keep = tokens
+                    .iter()
+                    .rposition(|token| !DANGLING.contains(&token.as_str()))
+                    .map_or(tokens.len(), |last| last + 1);
+                tokens.truncate(keep);
+                tokens
+            }
+            Self::EnsureTerminator => {
+                let last = tokens.last().map(String::as_str).unwrap_or_default();
+                if !(is_terminator(last) || TokenType::of(last) == TokenType::Emoji) {
+                    let latin = is_latin_word(TokenType::of(last));
+                    tokens.push(if latin { "." } else { "。" }.to_string());
+                }
+                tokens
+            }
+            Self::Capitalize => {
+                let mut start = true;
+                for token in &mut tokens {
+                    let kind = TokenType::of(token);
+                    if start && kind == TokenType::Latin {
+                        let mut chars = token.chars();
+                        if let Some(first) = chars.next() {
+                            *token = first.to_uppercase().chain(chars).collect();
+                        }
+                    }
+                    if is_terminator(token) {
+                        start = true;
+                    } else if kind != TokenType::Punctuation {
+                        start = false;
+                    }
+                }
+                tokens
+            }
+            Self::Template(template) => {
+                let (before, after) = template.split_once(REPLY).unwrap_or((template, ""));
+                let mut wrapped = Vec::with_capacity(tokens.len() + 2);
+                if !before.is_empty() {
+                    wrapped.push(before.to_string());
+                }
+                wrapped.append(&mut tokens);
+                if !after.is_empty() {
+                    wrapped.push(after.to_string());
+                }
+                wrapped
+            }
+        }
+    }
+}
+
+/// Where a template puts the reply
+const REPLY: &str = "{reply}";
+
+/// Particles and commas a sentence cannot end on
+pub(crate) const DANGLING: &[&str] = &[
+    "は", "が", "を", "に", "で", "と", "の", "へ", "や", "も", "から", "まで", "より", "けど",
+    "って", "、", ",",
+];
@@ -0,0 +1,86 @@
+use crate::{
+    layered::LayeredModel,
+    markov::{is_terminator, MarkovChain},
+    tokenizer::TokenType,
+};
+use rand::distr::{weighted::WeightedIndex, Distribution};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Tokens followed from each word of a message when looking for emoji
+const REACH: usize = 8;
+
+// **📌 ReactionMode：ボットが絵文字でリアクションするか**
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReactionMode {
+    /// Only reply with text
+    #[default]
+    Off,
+    /// React with an emoji instead of replying
+    Instead,
+    /// React with an emoji and reply
+    Also,
+}
+
+/// Emoji the model expects to follow the words of `text`, with their weights.
+///
+/// Starting from every known token of the message, the chain is followed for a
+/// few steps until the sentence ends; probability that lands on an emoji token,
+/// directly or right after the closing punctuation, counts towards that emoji.
+pub fn reaction_weights(model: &LayeredModel, text: &str) -> HashMap<String, f64> {
+    let layers = model.layers();
+    let tokens = model.base().tokenizer().tokenize(text);
+    let mut weights: HashMap<String, f64> = HashMap::new();
+
+    for token in &tokens {
+        let mut frontier = HashMap::from([(token.clone(), 1.0)]);
+        for _ in 0..REACH {
+            let mut next: HashMap<String, f64> = HashMap::new();
+            for (word, p) in frontier {
+                for (to, q) in transitions(&layers, &word) {
+                    let p = p * q;
+                    if is_emoji(&to) {
+                        *weights.entry(to).or_insert(0.0) += p;
+                    } else if is_terminator(&to) {
+                        // **📌 文末の記号の直後の絵文字までは数える**
+                        for (emoji, r) in transitions(&layers, &to) {
+                            if is_emoji(&emoji) {
+                                *weights.entry(emoji).or_insert(0.0) += p * r;
+                            }
+                        }
+                    } else {
+                        *next.entry(to).or_insert(0.0) += p;
+                    }
+                }
+            }
+            frontier = next;
+        }
+    }
+    weights
+}
+
+/// Sample an emoji to react to `text` with, if the model knows any that fit
+pub fn suggest_reaction(model: &LayeredModel, text: &str) -> Option<String> {
+    let weights: Vec<(String, f64)> = reaction_weights(model, text).into_iter().collect();
+    let dist = WeightedIndex::new(weights.iter().map(|(_, w)| *w)).ok()?;
+    Some(weights[dist.sample(&mut rand::rng())].0.clone())
+}
+
+fn is_emoji(token: &str) -> bool {
+    TokenType::of(token) == TokenType::Emoji
+}
+
+/// Next-token probabilities of `word`, from the first layer that knows it
+fn transitions(layers: &[&MarkovChain], word: &str) -> Vec<(String, f64)> {
+    layers
+        .iter()
+        .find_map(|chain| chain.word(word).filter(|w| w.count() > 0.0))
+        .map(|word| {
+            word.distribution()
+                .into_iter()
+                .map(|(to, p)| (to.to_string(), p))
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,107 @@
+use crate::{markov::MarkovChain, tokenizer::TokenizerConfig};
+use serde::Deserialize;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// **📌 RecentConfig構造体：最近の会話だけのモデルの設定**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RecentConfig {
+    /// Messages kept per channel; 0 disables the recent-style model
+    pub messages: usize,
+    /// Messages older than this many hours are dropped too
+    pub hours: Option<f64>,
+    /// Share of each step's probability taken from the recent messages
+    pub weight: f64,
+}
+
+impl Default for RecentConfig {
+    fn default() -> Self {
+        Self {
+            messages: 0,
+            hours: None,
+            weight: 0.3,
+        }
+    }
+}
+
+impl RecentConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.weight) {
+            return Err("weight must be between 0 and 1".to_string());
+        }
+        if self.hours.is_some_and(|h| h.is_nan() || h <= 0.0) {
+            return Err("hours must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.messages > 0
+    }
+}
+
+// **📌 RecentChain構造体：直近のメッセージだけで作る小さなモデル**
+#[derive(Clone)]
+pub struct RecentChain {
+    config: RecentConfig,
+    messages: VecDeque<(Instant, String)>,
+    chain: MarkovChain,
+}
+
+impl RecentChain {
+    pub fn new(config: &RecentConfig, order: usize, tokenizer: TokenizerConfig) -> Self {
+        Self {
+            config: config.clone(),
+            messages: VecDeque::new(),
+            chain: MarkovChain::new(order, tokenizer),
+        }
+    }
+
+    pub fn chain(&self) -> &MarkovChain {
+        &self.chain
+    }
+
+    pub fn weight(&self) -> f64 {
+        self.config.weight
+    }
+
+    /// Add a message, dropping the ones that fell out of the window
+    pub fn push(&mut self, text: &str) {
+        self.messages.push_back((Instant::now(), text.to_string()));
+        if self.expire() {
+            self.rebuild();
+        } else {
+            self.chain.learn(text);
+        }
+    }
+
+    /// Remove messages beyond the count or age limit; true if any were removed
+    fn expire(&mut self) -> bool {
+        let before = self.messages.len();
+        while self.messages.len() > self.config.messages {
+            self.messages.pop_front();
+        }
+        if let Some(hours) = self.config.hours {
+            let max_age = Duration::from_secs_f64(hours * 3600.0);
+            while self
+                .messages
+                .front()
+                .is_some_and(|(at, _)| at.elapsed() > max_age)
+            {
+                self.messages.pop_front();
+            }
+        }
+        self.messages.len() < before
+    }
+
+    /// Relearn the chain from the messages still in the window
+    fn rebuild(&mut self) {
+        self.chain.clear();
+        for (_, text) in &self.messages {
+            self.chain.learn(text);
+        }
+    }
+}
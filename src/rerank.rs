@@ -0,0 +1,98 @@
+use crate::markov::MarkovChain;
+use serde::Deserialize;
+use std::collections::HashSet;
+
+// **📌 RerankOptions構造体：候補の数と採点の重み**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RerankOptions {
+    /// Number of replies generated per message; 1 disables reranking
+    pub candidates: usize,
+    /// Reply length (in tokens) that gets the best length score
+    pub ideal_length: usize,
+    pub length_weight: f64,
+    pub overlap_weight: f64,
+    pub log_prob_weight: f64,
+    pub repetition_weight: f64,
+}
+
+impl Default for RerankOptions {
+    fn default() -> Self {
+        Self {
+            candidates: 1,
+            ideal_length: 15,
+            length_weight: 1.0,
+            overlap_weight: 2.0,
+            log_prob_weight: 0.3,
+            repetition_weight: 2.0,
+        }
+    }
+}
+
+/// Score one candidate reply; higher is better
+pub fn score_reply(
+    chain: &MarkovChain,
+    reply: &[&str],
+    user_tokens: &[String],
+    opts: &RerankOptions,
+) -> f64 {
+    if reply.is_empty() {
+        return f64::NEG_INFINITY;
+    }
+
+    // **📌 長さ：理想の長さからのずれを減点**
+    let ideal = opts.ideal_length.max(1) as f64;
+    let length = -((reply.len() as f64 - ideal).abs() / ideal);
+
+    // **📌 ユーザーの単語との重なり**
+    let reply_set: HashSet<&str> = reply.iter().copied().collect();
+    let user_set: HashSet<&str> = user_tokens
+        .iter()
+        .map(String::as_str)
+        .filter(|t| t.chars().any(char::is_alphabetic))
+        .collect();
+    let overlap = if user_set.is_empty() {
+        0.0
+    } else {
+        user_set.intersection(&reply_set).count() as f64 / user_set.len() as f64
+    };
+
+    // **📌 繰り返しの割合を減点**
+    let repetition = 1.0 - reply_set.len() as f64 / reply.len() as f64;
+
+    opts.length_weight * length
+        + opts.overlap_weight * overlap
+        + opts.log_prob_weight * chain.average_log_prob(reply)
+        - opts.repetition_weight * repetition
+}
+
+/// Generate `opts.candidates` replies and return the best-scoring one
+pub fn best_reply<F>(
+    chain: &MarkovChain,
+    user_tokens: &[String],
+    opts: &RerankOptions,
+    mut generate: F,
+) -> String
+where
+    F: FnMut() -> String,
+{
+    let mut best = generate();
+    if opts.candidates <= 1 {
+        return best;
+    }
+    let score = |reply: &str| {
+        let tokens: Vec<&str> = reply.split_whitespace().collect();
+        score_reply(chain, &tokens, user_tokens, opts)
+    };
+
+    let mut best_score = score(&best);
+    for _ in 1..opts.candidates {
+        let candidate = generate();
+        let candidate_score = score(&candidate);
+        if candidate_score > best_score {
+            best = candidate;
+            best_score = candidate_score;
+        }
+    }
+    best
+}
@@ -0,0 +1,702 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::VecDeque,
+    io::{self, Read},
+    panic::{self, AssertUnwindSafe},
+    sync::{Mutex, OnceLock, RwLock},
+    thread,
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+use wordora_ai::{
+    config::Config,
+    conversation::{most_salient, Conversation},
+    feedback::Rating,
+    layered::LayeredModel,
+    markov::{blend_salience, generate_blend, GenerateOptions, MarkovChain},
+    persona::Personas,
+    postprocess::{apply_filters, repair_spacing},
+    recent::RecentChain,
+    session::{Session, SessionSettings, Sessions},
+};
+
+#[derive(Deserialize)]
+struct GenerateRequest {
+    prompt: String,
+    /// Personas to answer as instead of the model, e.g. `polite` or `polite:0.7,casual:0.3`
+    persona: Option<String>,
+    /// Session from POST /sessions whose history and settings the reply uses
+    session: Option<String>,
+}
+
+#[derive(Serialize)]
+struct GenerateResponse {
+    response: String,
+    /// Rates the reply with POST /feedback
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct BatchRequest {
+    prompts: Vec<String>,
+    persona: Option<String>,
+}
+
+#[derive(Serialize)]
+struct BatchResponse {
+    responses: Vec<String>,
+    ids: Vec<u64>,
+}
+
+#[derive(Deserialize)]
+struct FeedbackRequest {
+    /// `id` of a reply from /generate or /generate/batch
+    id: u64,
+    rating: Rating,
+}
+
+#[derive(Serialize)]
+struct SessionResponse {
+    /// Passed as `session` to /generate, and in DELETE /sessions/<id>
+    session: String,
+    /// Seconds the session lasts without being used
+    expires_in_seconds: f64,
+}
+
+#[derive(Serialize)]
+struct FeedbackResponse {
+    /// Transitions of the reply the rating changed
+    transitions: usize,
+    /// Always false: ratings are kept in memory and lost when the server stops
+    persisted: bool,
+}
+
+/// In-memory overlay POST /feedback rates replies in
+const FEEDBACK: &str = "feedback";
+
+/// Replies kept for POST /feedback; older ones can no longer be rated
+const RATED_REPLIES: usize = 1024;
+
+// **📌 Replies構造体：評価を待っている最近の返事**
+#[derive(Default)]
+struct Replies {
+    next: u64,
+    recent: VecDeque<(u64, Vec<String>)>,
+}
+
+impl Replies {
+    /// Keep the tokens of a reply and return its id
+    fn remember(&mut self, reply: &str) -> u64 {
+        self.next += 1;
+        if self.recent.len() == RATED_REPLIES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((
+            self.next,
+            reply.split_whitespace().map(str::to_string).collect(),
+        ));
+        self.next
+    }
+
+    /// Tokens of a reply that has not been rated yet
+    fn take(&mut self, id: u64) -> Option<Vec<String>> {
+        let index = self.recent.iter().position(|(i, _)| *i == id)?;
+        self.recent.remove(index).map(|(_, tokens)| tokens)
+    }
+}
+
+/// Body of every failed request, e.g.
+/// `{"code": "unknown_persona", "message": "unknown persona \"x\"", "details": {...}}`
+#[derive(Serialize)]
+struct ErrorBody {
+    /// Stable identifier clients can match on
+    code: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<serde_json::Value>,
+}
+
+// **📌 Failure構造体：失敗したリクエストに返すステータスとエラー**
+struct Failure {
+    status: u16,
+    body: ErrorBody,
+}
+
+impl Failure {
+    fn new(status: u16, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: ErrorBody {
+                code,
+                message: message.into(),
+                details: None,
+            },
+        }
+    }
+
+    fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.body.details = Some(details);
+        self
+    }
+}
+
+// **📌 Loaded構造体：読み込みが終わってから使うモデルと状態**
+struct Loaded {
+    /// Written only when a reply is rated
+    model: RwLock<LayeredModel>,
+    personas: Personas,
+    replies: Mutex<Replies>,
+    sessions: Mutex<Sessions>,
+}
+
+// **🌐 HTTPでチャット**
+/// Listen right away and answer with 503 until `load` has finished loading the
+/// models in the background; the process exits if loading fails
+pub fn run<L>(config: &Config, load: L) -> io::Result<()>
+where
+    L: FnOnce() -> io::Result<(LayeredModel, Personas)> + Send,
+{
+    let server = Server::http(&config.server.addr).map_err(io::Error::other)?;
+    println!("🌐 listening on http://{}", config.server.addr);
+
+    let loaded: OnceLock<Loaded> = OnceLock::new();
+    thread::scope(|scope| {
+        scope.spawn(|| match panic::catch_unwind(AssertUnwindSafe(load)) {
+            Ok(Ok((mut model, personas))) => {
+                // **📌 評価はメモリ上のレイヤーに貯める（保存はしない）**
+                model.push_overlay(FEEDBACK, None);
+                let _ = loaded.set(Loaded {
+                    model: RwLock::new(model),
+                    personas,
+                    replies: Mutex::default(),
+                    sessions: Mutex::new(Sessions::new(
+                        &config.session,
+                        config.history.turns,
+                        config.history.distinct_replies,
+                    )),
+                });
+                println!("✅ model loaded");
+            }
+            Ok(Err(e)) => {
+                eprintln!("⚠️ Failed to load model: {}", e);
+                std::process::exit(1);
+            }
+            Err(_) => std::process::exit(1),
+        });
+
+        for mut request in server.incoming_requests() {
+            let json =
+                Header::from_bytes("Content-Type", "application/json").expect("valid header");
+            let response = match handle(config, loaded.get(), &mut request) {
+                Ok(body) => Response::from_string(body).with_header(json),
+                Err(failure) => {
+                    let body = serde_json::to_string(&failure.body).unwrap_or_default();
+                    let response = Response::from_string(body)
+                        .with_status_code(failure.status)
+                        .with_header(json);
+                    if failure.status == 503 {
+                        response.with_header(
+                            Header::from_bytes("Retry-After", "1").expect("valid header"),
+                        )
+                    } else {
+                        response
+                    }
+                }
+            };
+            if let Err(e) = request.respond(response) {
+                eprintln!("⚠️ Failed to send response: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+fn handle(
+    config: &Config,
+    loaded: Option<&Loaded>,
+    request: &mut Request,
+) -> Result<String, Failure> {
+    let path = request
+        .url()
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let method = request.method().clone();
+    let session_id = path.strip_prefix("/sessions/").map(str::to_string);
+    match (&session_id, path.as_str()) {
+        (Some(_), _) if method != Method::Delete => {
+            return Err(Failure::new(405, "method_not_allowed", "use DELETE"));
+        }
+        (Some(_), _) => {}
+        (None, "/generate" | "/generate/batch" | "/feedback" | "/sessions") => {
+            if method != Method::Post {
+                return Err(Failure::new(405, "method_not_allowed", "use POST"));
+            }
+        }
+        _ => return Err(Failure::new(404, "not_found", "not found")),
+    }
+    // **📌 読み込みが終わるまでは 503 で待ってもらう**
+    let Some(loaded) = loaded else {
+        return Err(Failure::new(503, "loading", "the model is still loading"));
+    };
+    let personas = &loaded.personas;
+
+    // **📌 セッションを終える**
+    if let Some(id) = session_id {
+        if !loaded.sessions.lock().unwrap().remove(&id) {
+            return Err(unknown_session(&id));
+        }
+        return to_json(&json!({ "ended": true }));
+    }
+
+    match path.as_str() {
+        // **📌 利用者ごとの会話を始める**
+        "/sessions" => {
+            let settings: SessionSettings = read_json(config, request)?;
+            let opts = persona_blend(config, personas, settings.persona.as_deref())?.1;
+            settings
+                .apply(&opts)
+                .map_err(|e| Failure::new(400, "invalid_settings", e))?;
+            to_json(&SessionResponse {
+                session: loaded.sessions.lock().unwrap().create(settings),
+                expires_in_seconds: config.session.ttl_seconds,
+            })
+        }
+        "/generate" => {
+            let body: GenerateRequest = read_json(config, request)?;
+            check_prompt(&body.prompt)
+                .map_err(|failure| failure.with_details(json!({ "field": "prompt" })))?;
+            // **📌 セッションの履歴と設定で生成（生成のあいだはロックを持たない）**
+            let session: Option<Session> = match &body.session {
+                Some(id) => Some(
+                    loaded
+                        .sessions
+                        .lock()
+                        .unwrap()
+                        .checkout(id)
+                        .ok_or_else(|| unknown_session(id))?,
+                ),
+                None => None,
+            };
+            let persona = body.persona.as_deref().or(session
+                .as_ref()
+                .and_then(|session| session.settings.persona.as_deref()));
+            let mut blend = persona_blend(config, personas, persona)?;
+            if let Some(session) = &session {
+                blend.1 = session
+                    .settings
+                    .apply(&blend.1)
+                    .map_err(|e| Failure::new(400, "invalid_settings", e))?;
+                blend.1.boost = session.conversation.boosts(config.history.boost);
+            }
+            let model = loaded.model.read().unwrap();
+            let history = session.as_ref().map(|session| &session.conversation);
+            let response = answer(config, &model, &blend, &body.prompt, history);
+            if let Some(id) = &body.session {
+                let prompt = model.base().tokenizer().tokenize(&body.prompt);
+                let reply = response.split_whitespace().map(str::to_string).collect();
+                loaded.sessions.lock().unwrap().record(id, prompt, reply);
+            }
+            let id = loaded.replies.lock().unwrap().remember(&response);
+            to_json(&GenerateResponse {
+                response: finish(config, &response),
+                id,
+            })
+        }
+        // **📌 評価された返事の遷移を強める・弱める**
+        "/feedback" => {
+            let body: FeedbackRequest = read_json(config, request)?;
+            let mut model = loaded.model.write().unwrap();
+            if model.is_read_only() {
+                return Err(Failure::new(403, "read_only", "the model is read-only"));
+            }
+            let Some(tokens) = loaded.replies.lock().unwrap().take(body.id) else {
+                return Err(Failure::new(
+                    404,
+                    "unknown_reply",
+                    format!("no reply {} to rate", body.id),
+                )
+                .with_details(json!({ "id": body.id })));
+            };
+            let factor = config.feedback.factor(body.rating);
+            to_json(&FeedbackResponse {
+                transitions: model.reinforce(FEEDBACK, &tokens, factor).unwrap_or(0),
+                persisted: false,
+            })
+        }
+        // **📌 複数のプロンプトをまとめて処理**
+        _ => {
+            let body: BatchRequest = read_json(config, request)?;
+            if body.prompts.len() > config.server.max_batch {
+                return Err(Failure::new(
+                    413,
+                    "batch_too_large",
+                    format!("at most {} prompts per batch", config.server.max_batch),
+                )
+                .with_details(json!({
+                    "max_batch": config.server.max_batch,
+                    "prompts": body.prompts.len(),
+                })));
+            }
+            for (index, prompt) in body.prompts.iter().enumerate() {
+                check_prompt(prompt).map_err(|failure| {
+                    failure.with_details(json!({ "field": "prompts", "index": index }))
+                })?;
+            }
+            let blend = persona_blend(config, personas, body.persona.as_deref())?;
+            let model = loaded.model.read().unwrap();
+            let responses = batch(config, &model, &blend, &body.prompts);
+            let mut replies = loaded.replies.lock().unwrap();
+            to_json(&BatchResponse {
+                ids: responses
+                    .iter()
+                    .map(|response| replies.remember(response))
+                    .collect(),
+                responses: responses
+                    .iter()
+                    .map(|response| finish(config, response))
+                    .collect(),
+            })
+        }
+    }
+}
+
+fn unknown_session(id: &str) -> Failure {
+    Failure::new(
+        404,
+        "unknown_session",
+        format!("no session {} (it may have expired)", id),
+    )
+    .with_details(json!({ "session": id }))
+}
+
+/// Refuse prompts there is nothing to answer
+fn check_prompt(prompt: &str) -> Result<(), Failure> {
+    if prompt.trim().is_empty() {
+        return Err(Failure::new(400, "empty_prompt", "prompt is empty"));
+    }
+    Ok(())
+}
+
+/// Raw reply to `prompt` from the personas of `blend`, or the model if it has
+/// none, seeded from `history` when the prompt has no known word
+fn answer(
+    config: &Config,
+    model: &LayeredModel,
+    blend: &Blend,
+    prompt: &str,
+    history: Option<&Conversation>,
+) -> String {
+    match blend.0.as_slice() {
+        [] => generate_reply(config, model, None, prompt, &blend.1, history),
+        _ => persona_reply(config, blend, prompt, history),
+    }
+}
+
+/// Answer the prompts on up to `server.parallelism` threads, keeping their order
+fn batch(config: &Config, model: &LayeredModel, blend: &Blend, prompts: &[String]) -> Vec<String> {
+    let chunk_size = prompts.len().div_ceil(config.server.parallelism).max(1);
+    thread::scope(|scope| {
+        let handles: Vec<_> = prompts
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|prompt| answer(config, model, blend, prompt, None))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("generation thread panicked"))
+            .collect()
+    })
+}
+
+// **📌 SavedOverlay構造体：ボットが会話ごとに保存したレイヤー**
+#[cfg(any(feature = "discord", feature = "telegram"))]
+pub struct SavedOverlay {
+    pub chain: Option<MarkovChain>,
+    /// Whether saving the overlay back to its file is safe
+    pub writable: bool,
+}
+
+/// Overlay a bot saved at `path`, if any. A file that fails to load or
+/// validate is renamed aside to `<file>.corrupt` so saving the overlay again
+/// cannot overwrite it; when even that fails the overlay is not writable.
+/// `what` names the overlay in the warning
+#[cfg(any(feature = "discord", feature = "telegram"))]
+pub fn load_overlay(path: &std::path::Path, what: &str) -> SavedOverlay {
+    if !path.exists() {
+        return SavedOverlay {
+            chain: None,
+            writable: true,
+        };
+    }
+    let error = match MarkovChain::load(path) {
+        Ok(chain) => {
+            return SavedOverlay {
+                chain: Some(chain),
+                writable: true,
+            }
+        }
+        Err(e) => e,
+    };
+    // **📌 壊れたファイルは脇によけ、よけられなければ保存しない**
+    let mut aside = path.as_os_str().to_owned();
+    aside.push(".corrupt");
+    let aside = std::path::PathBuf::from(aside);
+    let writable = match std::fs::rename(path, &aside) {
+        Ok(()) => {
+            eprintln!(
+                "⚠️ Failed to load {} {}: {}; moved it to {} and starting empty",
+                what,
+                path.display(),
+                error,
+                aside.display()
+            );
+            true
+        }
+        Err(e) => {
+            eprintln!(
+                "⚠️ Failed to load {} {}: {}; could not move it aside ({}), so it will not be saved",
+                what,
+                path.display(),
+                error,
+                e
+            );
+            false
+        }
+    };
+    SavedOverlay {
+        chain: None,
+        writable,
+    }
+}
+
+/// Write a copy of a bot's overlay to `path` on the blocking pool, so neither
+/// the runtime nor the lock the overlay was copied under waits for the disk.
+/// Writes run one at a time so two saves of a file never share its temporary
+/// file; `what` names the overlay in the warning
+#[cfg(any(feature = "discord", feature = "telegram"))]
+pub fn save_overlay(
+    path: std::path::PathBuf,
+    chain: MarkovChain,
+    what: &'static str,
+) -> tokio::task::JoinHandle<()> {
+    static WRITING: Mutex<()> = Mutex::new(());
+    tokio::task::spawn_blocking(move || {
+        let _writing = WRITING.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        if let Err(e) = chain.save(&path) {
+            eprintln!("⚠️ Failed to save {}: {}", what, e);
+        }
+    })
+}
+
+/// Seed from the learned response pairs, or else the first token of the prompt
+/// the model knows, and generate a reply, mixing in the recent messages' chain
+/// when one is given
+#[cfg(any(
+    feature = "discord",
+    feature = "telegram",
+    feature = "irc",
+    feature = "matrix"
+))]
+pub fn reply(
+    config: &Config,
+    model: &LayeredModel,
+    recent: Option<&RecentChain>,
+    prompt: &str,
+) -> String {
+    let reply = generate_reply(config, model, recent, prompt, &config.generation, None);
+    finish(config, &reply)
+}
+
+/// Like [`reply`], but the tokens as generated, separated by spaces; the last
+/// known word of `history` seeds it when the prompt has none
+fn generate_reply(
+    config: &Config,
+    model: &LayeredModel,
+    recent: Option<&RecentChain>,
+    prompt: &str,
+    opts: &GenerateOptions,
+    history: Option<&Conversation>,
+) -> String {
+    let tokens = model.base().tokenizer().tokenize_input(prompt);
+    let resolved: Vec<String> = tokens
+        .iter()
+        .filter_map(|token| model.resolve_seed(token))
+        .map(String::from)
+        .collect();
+    let is_known = |token: &str| model.contains(token);
+    let salience = |token: &str| model.salience(token);
+    let seed = model
+        .response_seed(&tokens)
+        .map(String::from)
+        .or_else(|| most_salient(&resolved, is_known, salience).cloned())
+        .or_else(|| history?.seed(&[], is_known, salience))
+        .or_else(|| tokens.first().cloned())
+        .unwrap_or_default();
+    let seeds = salient(config, &tokens, is_known, salience);
+    let generate = |seed: &str, opts: &GenerateOptions| match recent {
+        Some(recent) => model.generate_mixed(recent.chain(), recent.weight(), seed, opts),
+        None => model.generate(seed, opts),
+    };
+    fresh(config, history, opts, |opts| {
+        opts.blocklist
+            .regenerate(config.blocklist.retries, || match seeds.len() {
+                0 | 1 => generate(&seed, opts),
+                _ => config.compose.compose(&seeds, opts, generate),
+            })
+    })
+}
+
+/// Reply from `generate`, made again a little hotter while it repeats one
+/// of the latest replies of `history`
+fn fresh<F>(
+    config: &Config,
+    history: Option<&Conversation>,
+    opts: &GenerateOptions,
+    mut generate: F,
+) -> String
+where
+    F: FnMut(&GenerateOptions) -> String,
+{
+    match history {
+        Some(history) => history.fresh_reply(
+            opts,
+            config.history.repeat_retries,
+            config.history.repeat_heat,
+            generate,
+        ),
+        None => generate(opts),
+    }
+}
+
+/// Words of the prompt seeding the segments of a composed reply, when
+/// `compose` is on; empty otherwise
+fn salient<'a, F, S>(
+    config: &Config,
+    tokens: &'a [String],
+    is_known: F,
+    salience: S,
+) -> Vec<&'a str>
+where
+    F: Fn(&str) -> bool,
+    S: Fn(&str) -> f64,
+{
+    if config.compose.enabled() {
+        config.compose.salient(tokens, is_known, salience)
+    } else {
+        Vec::new()
+    }
+}
+
+/// Models of the personas a request asked for with the options they speak
+/// with; no models when it named none
+fn persona_blend<'a>(
+    config: &Config,
+    personas: &'a Personas,
+    spec: Option<&str>,
+) -> Result<Blend<'a>, Failure> {
+    let selection = match spec {
+        Some(spec) => personas.select(spec).map_err(|e| {
+            Failure::new(400, "unknown_persona", e).with_details(json!({
+                "persona": spec,
+                "available": personas.names().collect::<Vec<_>>(),
+            }))
+        })?,
+        None => Vec::new(),
+    };
+    let mut opts = config.generation.clone();
+    opts.endings = personas.endings(&selection, &config.generation.endings);
+    Ok((personas.chains(&selection), opts))
+}
+
+/// Persona models with their weights, and the options to generate with
+type Blend<'a> = (Vec<(&'a MarkovChain, f64)>, GenerateOptions);
+
+/// Like [`generate_reply`], generating from a weighted mixture of personas
+fn persona_reply(
+    config: &Config,
+    (blend, opts): &Blend,
+    prompt: &str,
+    history: Option<&Conversation>,
+) -> String {
+    let Some((first, _)) = blend.first() else {
+        return String::new();
+    };
+    let tokens = first.tokenizer().tokenize_input(prompt);
+    let resolved: Vec<String> = tokens
+        .iter()
+        .filter_map(|token| {
+            blend
+                .iter()
+                .find_map(|(chain, _)| chain.resolve_seed(token))
+        })
+        .map(String::from)
+        .collect();
+    let is_known = |token: &str| blend.iter().any(|(chain, _)| chain.contains(token));
+    let salience = |token: &str| blend_salience(blend, token);
+    let seed = blend
+        .iter()
+        .find_map(|(chain, _)| chain.response_seed(&tokens))
+        .map(String::from)
+        .or_else(|| most_salient(&resolved, is_known, salience).cloned())
+        .or_else(|| history?.seed(&[], is_known, salience))
+        .or_else(|| tokens.first().cloned())
+        .unwrap_or_default();
+    let seeds = salient(config, &tokens, is_known, salience);
+    let generate = |seed: &str, opts: &GenerateOptions| generate_blend(blend, seed, opts);
+    fresh(config, history, opts, |opts| {
+        opts.blocklist
+            .regenerate(config.blocklist.retries, || match seeds.len() {
+                0 | 1 => generate(&seed, opts),
+                _ => config.compose.compose(&seeds, opts, generate),
+            })
+    })
+}
+
+/// Rewrite the reply's kana in `output_script` and join its tokens
+fn finish(config: &Config, response: &str) -> String {
+    let tokens = config
+        .output_script
+        .apply(&response.split_whitespace().collect::<Vec<_>>());
+    let tokens = apply_filters(&config.postprocess, tokens);
+    repair_spacing(&tokens.iter().map(String::as_str).collect::<Vec<_>>())
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(
+    config: &Config,
+    request: &mut Request,
+) -> Result<T, Failure> {
+    let limit = config.server.max_body_bytes;
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(limit as u64 + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| Failure::new(400, "unreadable_body", e.to_string()))?;
+    if body.len() > limit {
+        return Err(Failure::new(
+            413,
+            "payload_too_large",
+            format!("body is larger than {} bytes", limit),
+        )
+        .with_details(json!({ "max_body_bytes": limit })));
+    }
+    serde_json::from_slice(&body).map_err(|e| {
+        Failure::new(400, "invalid_json", e.to_string())
+            .with_details(json!({ "line": e.line(), "column": e.column() }))
+    })
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, Failure> {
+    serde_json::to_string(value).map_err(|e| Failure::new(500, "internal", e.to_string()))
+}
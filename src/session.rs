@@ -0,0 +1,175 @@
+use crate::{
+    conversation::{Conversation, Speaker},
+    markov::{GenerateOptions, Strategy},
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+// **📌 SessionConfig構造体：利用者ごとの会話を覚えておく期間と数**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Sessions unused for this long are forgotten
+    pub ttl_seconds: f64,
+    /// Sessions kept at once; the least recently used one makes room
+    pub max_sessions: usize,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            ttl_seconds: 1800.0,
+            max_sessions: 10_000,
+        }
+    }
+}
+
+impl SessionConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.ttl_seconds.is_finite() && self.ttl_seconds > 0.0) {
+            return Err("ttl_seconds must be positive".to_string());
+        }
+        if self.max_sessions == 0 {
+            return Err("max_sessions must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs_f64(self.ttl_seconds)
+    }
+}
+
+// **📌 SessionSettings構造体：セッションごとに変えられる生成の設定**
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionSettings {
+    pub length: Option<usize>,
+    pub temperature: Option<f64>,
+    pub strategy: Option<Strategy>,
+    /// Personas to answer as, e.g. `polite:0.7,casual:0.3`
+    pub persona: Option<String>,
+}
+
+impl SessionSettings {
+    /// `opts` with the settings the session changes
+    pub fn apply(&self, opts: &GenerateOptions) -> Result<GenerateOptions, String> {
+        let mut opts = opts.clone();
+        if let Some(length) = self.length {
+            opts.length = length;
+        }
+        if let Some(temperature) = self.temperature {
+            opts.temperature = temperature;
+        }
+        if let Some(strategy) = self.strategy {
+            opts.strategy = strategy;
+        }
+        opts.validate()?;
+        Ok(opts)
+    }
+}
+
+// **📌 Session構造体：1人の利用者との会話**
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub conversation: Conversation,
+    pub settings: SessionSettings,
+    last_seen: Instant,
+}
+
+// **📌 Sessions構造体：IDで引けるセッションの一覧**
+#[derive(Debug)]
+pub struct Sessions {
+    sessions: HashMap<String, Session>,
+    ttl: Duration,
+    max_sessions: usize,
+    /// Turns of history each new session keeps
+    turns: usize,
+    /// Latest replies each new session avoids repeating
+    replies: usize,
+}
+
+impl Sessions {
+    pub fn new(config: &SessionConfig, turns: usize, replies: usize) -> Self {
+        Self {
+            sessions: HashMap::new(),
+            ttl: config.ttl(),
+            max_sessions: config.max_sessions,
+            turns,
+            replies,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Start a session and return its ID, forgetting the least recently used
+    /// one if there are already `max_sessions`
+    pub fn create(&mut self, settings: SessionSettings) -> String {
+        self.expire();
+        if self.sessions.len() >= self.max_sessions {
+            let oldest = self
+                .sessions
+                .iter()
+                .min_by_key(|(_, session)| session.last_seen)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                self.sessions.remove(&oldest);
+            }
+        }
+        let id = new_id();
+        self.sessions.insert(
+            id.clone(),
+            Session {
+                conversation: Conversation::new(self.turns, self.replies),
+                settings,
+                last_seen: Instant::now(),
+            },
+        );
+        id
+    }
+
+    /// Copy of a live session, marking it as used
+    pub fn checkout(&mut self, id: &str) -> Option<Session> {
+        self.expire();
+        let session = self.sessions.get_mut(id)?;
+        session.last_seen = Instant::now();
+        Some(session.clone())
+    }
+
+    /// Add an exchange to the history of a session, if it still exists
+    pub fn record(&mut self, id: &str, prompt: Vec<String>, reply: Vec<String>) {
+        if let Some(session) = self.sessions.get_mut(id) {
+            session.conversation.push(Speaker::User, prompt);
+            session.conversation.push(Speaker::Bot, reply);
+            session.last_seen = Instant::now();
+        }
+    }
+
+    /// End a session; `false` if it did not exist or had expired
+    pub fn remove(&mut self, id: &str) -> bool {
+        self.expire();
+        self.sessions.remove(id).is_some()
+    }
+
+    /// Forget the sessions unused for longer than the TTL
+    pub fn expire(&mut self) {
+        let ttl = self.ttl;
+        self.sessions
+            .retain(|_, session| session.last_seen.elapsed() < ttl);
+    }
+}
+
+/// Random 128-bit session ID as hex
+fn new_id() -> String {
+    format!("{:032x}", rand::rng().random::<u128>())
+}
@@ -0,0 +1,296 @@
+use crate::{
+    markov::{GenerateOptions, TokenStream},
+    tokenizer::TokenizerConfig,
+};
+use std::{cell::RefCell, collections::HashMap, io, rc::Rc};
+
+/// Following tokens and their counts
+pub type Transitions = HashMap<String, f64>;
+
+// **📌 Storageトレイト：遷移の保存先**
+pub trait Storage {
+    /// Add `count` to the transition from `key`, a token or a space-joined context
+    fn add(&mut self, key: &str, next: &str, count: f64) -> io::Result<()>;
+    /// Transitions from `key`, if it was ever seen
+    fn get(&self, key: &str) -> io::Result<Option<Transitions>>;
+    /// Write buffered changes through
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// **📌 MemoryStorage構造体：メモリ上の遷移表**
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    table: HashMap<String, Transitions>,
+}
+
+impl Storage for MemoryStorage {
+    fn add(&mut self, key: &str, next: &str, count: f64) -> io::Result<()> {
+        *self
+            .table
+            .entry(key.to_string())
+            .or_default()
+            .entry(next.to_string())
+            .or_insert(0.0) += count;
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> io::Result<Option<Transitions>> {
+        Ok(self.table.get(key).cloned())
+    }
+}
+
+// **📌 StoredChain構造体：任意の保存先で学習・生成するマルコフ連鎖**
+/// A chain learning into and generating from a [`Storage`], for corpora too
+/// large to keep in memory.
+///
+/// This is a separate, smaller API than [`MarkovChain`], which keeps its own
+/// tables and is not backed by a `Storage`. A stored chain only learns plain
+/// transitions: no reverse index, response pairs, topics, metadata or
+/// unlearning, and it cannot be saved as a model file or layered. Storage
+/// errors are returned instead of ending generation quietly.
+///
+/// [`MarkovChain`]: crate::markov::MarkovChain
+pub struct StoredChain<S> {
+    order: usize,
+    tokenizer: TokenizerConfig,
+    storage: S,
+}
+
+impl<S: Storage> StoredChain<S> {
+    pub fn new(order: usize, tokenizer: TokenizerConfig, storage: S) -> Self {
+        Self {
+            order: order.max(1),
+            tokenizer,
+            storage,
+        }
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    // **🔍 学習**
+    pub fn learn(&mut self, text: &str) -> io::Result<()> {
+        let tokens = self.tokenizer.tokenize(text);
+        for i in 0..tokens.len().saturating_sub(1) {
+            for n in 1..=self.order.min(i + 1) {
+                let key = tokens[i + 1 - n..=i].join(" ");
+                self.storage.add(&key, &tokens[i + 1], 1.0)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.storage.flush()
+    }
+
+    /// Yield generated tokens one at a time, like [`MarkovChain::generate_iter`].
+    ///
+    /// The length, temperature, boost, ending weight, fallback, punctuation,
+    /// repetition and loop options apply, and so does the blocklist. The
+    /// strategy, smoothing, interpolation, aliases, metadata exclusions and
+    /// keyword mode do not. A storage error ends the stream as its last item.
+    ///
+    /// [`MarkovChain::generate_iter`]: crate::markov::MarkovChain::generate_iter
+    pub fn generate_iter<'a>(
+        &'a self,
+        start_word: &str,
+        opts: &GenerateOptions,
+    ) -> impl Iterator<Item = io::Result<String>> + 'a {
+        let failed: Rc<RefCell<Option<io::Error>>> = Rc::default();
+        let seen = Rc::clone(&failed);
+        let mut tokens = TokenStream::new(start_word, opts, self.order, move |context| {
+            // **📌 読み込みに失敗したらそれ以上は読まずに終える**
+            if seen.borrow().is_some() {
+                return None;
+            }
+            self.distribution(context).unwrap_or_else(|e| {
+                *seen.borrow_mut() = Some(e);
+                None
+            })
+        });
+        let mut ended = false;
+        std::iter::from_fn(move || {
+            if ended {
+                return None;
+            }
+            match tokens.next() {
+                Some(token) => Some(Ok(token)),
+                None => {
+                    ended = true;
+                    failed.take().map(Err)
+                }
+            }
+        })
+    }
+
+    // **📝 文章を生成**
+    pub fn generate(&self, start_word: &str, opts: &GenerateOptions) -> io::Result<String> {
+        let mut failed = None;
+        let reply = opts.limits.retry(|| {
+            match self
+                .generate_iter(start_word, opts)
+                .collect::<io::Result<Vec<_>>>()
+            {
+                Ok(tokens) => tokens.join(" "),
+                Err(e) => {
+                    failed.get_or_insert(e);
+                    String::new()
+                }
+            }
+        });
+        match failed {
+            Some(e) => Err(e),
+            None => Ok(reply),
+        }
+    }
+
+    /// Next-token probabilities after the longest stored context
+    fn distribution(&self, context: &[String]) -> io::Result<Option<Vec<(String, f64)>>> {
+        let context = &context[context.len().saturating_sub(self.order)..];
+        for n in (1..=context.len()).rev() {
+            let key = context[context.len() - n..].join(" ");
+            let Some(transitions) = self.storage.get(&key)?.filter(|t| !t.is_empty()) else {
+                continue;
+            };
+            let total: f64 = transitions.values().sum();
+            return Ok(Some(
+                transitions
+                    .into_iter()
+                    .map(|(next, count)| (next, count / total))
+                    .collect(),
+            ));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "sled")]
+pub use self::sled_store::SledStorage;
+
+#[cfg(feature = "sled")]
+mod sled_store {
+    use super::{Storage, Transitions};
+    use crate::tokenizer::TokenizerConfig;
+    use std::{cell::RefCell, collections::HashMap, io, path::Path};
+
+    /// Keys whose additions are buffered before they are merged into the database
+    const MAX_PENDING: usize = 50_000;
+    /// Transition lists kept in memory after being read
+    const MAX_CACHED: usize = 10_000;
+    /// Key of the order and tokenizer in the default tree
+    const CONFIG_KEY: &str = "config";
+    /// Tree holding one bincode-encoded transition list per key
+    const TRANSITIONS: &str = "transitions";
+
+    // **📌 SledStorage構造体：sled に保存する遷移表**
+    pub struct SledStorage {
+        db: sled::Db,
+        transitions: sled::Tree,
+        /// Additions not yet written to the database
+        pending: HashMap<String, Transitions>,
+        cache: RefCell<HashMap<String, Transitions>>,
+    }
+
+    impl SledStorage {
+        pub fn open(path: &Path) -> io::Result<Self> {
+            let db = sled::open(path).map_err(io::Error::other)?;
+            Ok(Self {
+                transitions: db.open_tree(TRANSITIONS).map_err(io::Error::other)?,
+                db,
+                pending: HashMap::new(),
+                cache: RefCell::new(HashMap::new()),
+            })
+        }
+
+        /// Order and tokenizer the store was trained with
+        pub fn config(&self) -> io::Result<Option<(usize, TokenizerConfig)>> {
+            let Some(bytes) = self.db.get(CONFIG_KEY).map_err(io::Error::other)? else {
+                return Ok(None);
+            };
+            serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+
+        pub fn set_config(&self, order: usize, tokenizer: &TokenizerConfig) -> io::Result<()> {
+            let bytes = serde_json::to_vec(&(order, tokenizer)).map_err(io::Error::other)?;
+            self.db
+                .insert(CONFIG_KEY, bytes)
+                .map_err(io::Error::other)?;
+            Ok(())
+        }
+
+        fn stored(&self, key: &str) -> io::Result<Option<Transitions>> {
+            let Some(bytes) = self.transitions.get(key).map_err(io::Error::other)? else {
+                return Ok(None);
+            };
+            bincode::deserialize(&bytes)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+    }
+
+    impl Storage for SledStorage {
+        fn add(&mut self, key: &str, next: &str, count: f64) -> io::Result<()> {
+            *self
+                .pending
+                .entry(key.to_string())
+                .or_default()
+                .entry(next.to_string())
+                .or_insert(0.0) += count;
+            if self.pending.len() >= MAX_PENDING {
+                self.flush()?;
+            }
+            Ok(())
+        }
+
+        fn get(&self, key: &str) -> io::Result<Option<Transitions>> {
+            let cached = self.cache.borrow().get(key).cloned();
+            let mut transitions = match cached {
+                Some(transitions) => Some(transitions),
+                None => {
+                    let stored = self.stored(key)?;
+                    // **📌 キャッシュがいっぱいになったら入れ替える**
+                    let mut cache = self.cache.borrow_mut();
+                    if cache.len() >= MAX_CACHED {
+                        cache.clear();
+                    }
+                    if let Some(stored) = &stored {
+                        cache.insert(key.to_string(), stored.clone());
+                    }
+                    stored
+                }
+            };
+            if let Some(pending) = self.pending.get(key) {
+                let transitions = transitions.get_or_insert_with(Transitions::new);
+                for (next, count) in pending {
+                    *transitions.entry(next.clone()).or_insert(0.0) += count;
+                }
+            }
+            Ok(transitions)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            // **📌 書き込めたキーだけ取り除き、失敗したら残りは次の flush に回す**
+            let keys: Vec<String> = self.pending.keys().cloned().collect();
+            for key in keys {
+                let mut transitions = self.stored(&key)?.unwrap_or_default();
+                for (next, count) in &self.pending[&key] {
+                    *transitions.entry(next.clone()).or_insert(0.0) += count;
+                }
+                let bytes = bincode::serialize(&transitions).map_err(io::Error::other)?;
+                self.transitions
+                    .insert(key.as_bytes(), bytes)
+                    .map_err(io::Error::other)?;
+                self.pending.remove(&key);
+                self.cache.get_mut().remove(&key);
+            }
+            self.db.flush().map_err(io::Error::other)?;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{
+    markov::{is_terminator, GenerateOptions, MarkovChain},
+    postprocess::repair_spacing,
+    tokenizer::TokenType,
+};
+use rand::{seq::IndexedRandom, Rng};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// **📌 StoryOptions構造体：長い文章の生成設定**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StoryOptions {
+    pub paragraphs: usize,
+    /// Sentences per paragraph are picked between these bounds
+    pub min_sentences: usize,
+    pub max_sentences: usize,
+    /// Start a chapter with a sampled header every this many paragraphs; 0 for none
+    pub chapter_every: usize,
+    /// Weight multiplier per earlier use of a token anywhere in the story; 1.0 disables it
+    pub repetition_penalty: f64,
+}
+
+impl Default for StoryOptions {
+    fn default() -> Self {
+        Self {
+            paragraphs: 3,
+            min_sentences: 3,
+            max_sentences: 6,
+            chapter_every: 0,
+            repetition_penalty: 0.7,
+        }
+    }
+}
+
+impl StoryOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_sentences == 0 || self.min_sentences > self.max_sentences {
+            return Err("sentences must satisfy 1 <= min_sentences <= max_sentences".to_string());
+        }
+        if !(self.repetition_penalty > 0.0 && self.repetition_penalty <= 1.0) {
+            return Err("repetition_penalty must be in (0, 1]".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Generate several paragraphs, breaking them after sentence terminators,
+/// with optional chapter headers and a repetition penalty over the whole piece
+pub fn generate_story(chain: &MarkovChain, opts: &GenerateOptions, story: &StoryOptions) -> String {
+    let mut rng = rand::rng();
+    let mut opts = opts.clone();
+    let mut used: HashMap<String, i32> = HashMap::new();
+    let mut last = opts.fallback_word.clone();
+    let mut blocks = Vec::new();
+
+    for paragraph in 0..story.paragraphs {
+        // **📌 章の見出し**
+        if story.chapter_every > 0 && paragraph % story.chapter_every == 0 {
+            let number = paragraph / story.chapter_every + 1;
+            blocks.push(format!("第{}章　{}", number, chapter_title(chain, &opts)));
+        }
+
+        let sentences = rng.random_range(story.min_sentences..=story.max_sentences);
+        let mut tokens: Vec<String> = Vec::new();
+        for _ in 0..sentences {
+            // **📌 作品全体で使った単語ほど選ばれにくくする**
+            opts.boost = used
+                .iter()
+                .map(|(token, &n)| (token.clone(), story.repetition_penalty.powi(n) - 1.0))
+                .collect();
+
+            let mut stream = chain.generate_iter(&last, &opts).peekable();
+            // **📌 前の文の最後の単語は繰り返さない**
+            if stream.peek() == Some(&last) {
+                stream.next();
+            }
+            for token in stream {
+                if !TokenType::of(&token).is_punctuation() {
+                    *used.entry(token.clone()).or_insert(0) += 1;
+                }
+                let end = is_terminator(&token);
+                last = token.clone();
+                tokens.push(token);
+                if end {
+                    break;
+                }
+            }
+        }
+
+        let refs: Vec<&str> = tokens.iter().map(String::as_str).collect();
+        blocks.push(repair_spacing(&refs));
+    }
+    blocks.join("\n\n")
+}
+
+/// A few tokens grown from a frequent content word of the corpus
+fn chapter_title(chain: &MarkovChain, opts: &GenerateOptions) -> String {
+    let mut candidates: Vec<(&str, f64)> = chain
+        .words()
+        .filter(|word| matches!(word.kind, TokenType::Kanji | TokenType::Katakana))
+        .map(|word| (word.word.as_str(), word.count()))
+        .collect();
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+    candidates.truncate(50);
+    let Some((seed, _)) = candidates.choose(&mut rand::rng()) else {
+        return String::new();
+    };
+
+    let opts = GenerateOptions {
+        length: 3,
+        ..opts.clone()
+    };
+    let title: Vec<String> = chain
+        .generate_iter(seed, &opts)
+        .take_while(|token| !TokenType::of(token).is_punctuation())
+        .collect();
+    let refs: Vec<&str> = title.iter().map(String::as_str).collect();
+    repair_spacing(&refs)
+}
@@ -0,0 +1,215 @@
+use crate::server;
+use std::{
+    collections::{HashMap, HashSet},
+    env, io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use teloxide::{
+    prelude::*,
+    types::{ChatAction, ChatId},
+};
+use wordora_ai::{
+    config::Config,
+    guard::LearnGuard,
+    layered::LayeredModel,
+    recent::RecentChain,
+    trigger::{Incoming, Triggers},
+};
+
+/// Name of the overlay holding what was learned in a chat
+const CHAT: &str = "chat";
+
+// **📌 Relay構造体：チャットごとのモデルを管理**
+struct Relay {
+    config: Config,
+    /// Base model and configured overlays shared by every chat
+    model: LayeredModel,
+    chats: Mutex<HashMap<ChatId, LayeredModel>>,
+    /// Latest messages of each chat, when `[recent]` is enabled
+    recent: Mutex<HashMap<ChatId, RecentChain>>,
+    guards: Mutex<HashMap<ChatId, LearnGuard>>,
+    /// Chats whose rejected model file could not be moved aside, so it is
+    /// never saved over
+    unwritable: Mutex<HashSet<ChatId>>,
+    triggers: Triggers,
+}
+
+impl Relay {
+    fn path(&self, chat_id: ChatId) -> Option<PathBuf> {
+        self.config
+            .telegram
+            .chat_models
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.bin", chat_id)))
+    }
+
+    /// Where the chat's overlay is saved, `None` when it is not or its
+    /// rejected file could not be moved aside
+    fn writable_path(&self, chat_id: ChatId) -> Option<PathBuf> {
+        if self.unwritable.lock().unwrap().contains(&chat_id) {
+            return None;
+        }
+        self.path(chat_id)
+    }
+
+    /// Stack the chat's saved overlay, if any, on the shared model
+    fn load_chat(&self, chat_id: ChatId) -> LayeredModel {
+        // **📌 読み込めないモデルは空のレイヤーから始め、元のファイルは上書きしない**
+        let mut chain = None;
+        if let Some(path) = self.path(chat_id) {
+            let saved = server::load_overlay(&path, "chat model");
+            if !saved.writable {
+                self.unwritable.lock().unwrap().insert(chat_id);
+            }
+            chain = saved.chain;
+        }
+        let mut model = self.model.clone();
+        model.push_overlay(CHAT, chain);
+        model
+    }
+
+    /// Save a copy of the chat's overlay in the background
+    fn save(&self, chat_id: ChatId, model: &LayeredModel) {
+        if let (Some(path), Some(chain)) = (self.writable_path(chat_id), model.overlay(CHAT)) {
+            server::save_overlay(path, chain.clone(), "chat model");
+        }
+    }
+
+    /// Answer one message from `user`: a bot command, or text that is replied
+    /// to when it satisfies the trigger rules
+    fn answer(&self, chat_id: ChatId, user: &str, incoming: &Incoming) -> String {
+        let text = incoming.text;
+        let mut chats = self.chats.lock().unwrap();
+        let model = chats
+            .entry(chat_id)
+            .or_insert_with(|| self.load_chat(chat_id));
+
+        // **📌 グループでは /learn@ボット名 の形で届く**
+        let (command, arg) = text.split_once(' ').unwrap_or((text, ""));
+        let command = command.split('@').next().unwrap_or(command);
+        let arg = arg.trim();
+
+        match command {
+            "/learn" | "/reset" if model.is_read_only() => {
+                "🔒 read-only mode: the model cannot change".to_string()
+            }
+            // **📝 /learn <文章>：このチャットのモデルで学習**
+            "/learn" => {
+                let mut guards = self.guards.lock().unwrap();
+                let guard = guards
+                    .entry(chat_id)
+                    .or_insert_with(|| LearnGuard::new(&self.config.guard));
+                if let Err(reason) = guard.check(user, arg, model.base().tokenizer()) {
+                    return format!("🛡️ not learned: {}", reason);
+                }
+                model.overlay_mut(CHAT).expect("chat overlay").learn(arg);
+                self.save(chat_id, model);
+                "📝 learned".to_string()
+            }
+            // **📊 /stats：モデルの大きさ**
+            "/stats" => {
+                let chat_words = model.overlay(CHAT).map_or(0, |chain| chain.words().count());
+                format!(
+                    "📊 base vocabulary {}, learned in this chat {}, order {}",
+                    model.base().words().count(),
+                    chat_words,
+                    model.base().order()
+                )
+            }
+            // **🗑️ /reset：このチャットで学習した内容を消す**
+            "/reset" => {
+                if let Some(chain) = model.overlay_mut(CHAT) {
+                    chain.clear();
+                }
+                if let Some(path) = self.writable_path(chat_id) {
+                    let _ = std::fs::remove_file(path);
+                }
+                "🗑️ forgot what was learned in this chat".to_string()
+            }
+            _ if self.config.recent.enabled() => {
+                let mut recent = self.recent.lock().unwrap();
+                let recent = recent.entry(chat_id).or_insert_with(|| {
+                    let base = model.base();
+                    RecentChain::new(&self.config.recent, base.order(), base.tokenizer().clone())
+                });
+                recent.push(text);
+                match self.triggers.check(incoming) {
+                    Some(_) => server::reply(&self.config, model, Some(recent), text),
+                    None => String::new(),
+                }
+            }
+            _ if self.triggers.check(incoming).is_none() => String::new(),
+            _ => server::reply(&self.config, model, None, text),
+        }
+    }
+}
+
+/// Show the bot as typing for `delay`; Telegram clears the status after five
+/// seconds, so it is sent again until the delay is over
+async fn show_typing(bot: &Bot, chat_id: ChatId, delay: Duration) {
+    let mut remaining = delay;
+    while !remaining.is_zero() {
+        let _ = bot.send_chat_action(chat_id, ChatAction::Typing).await;
+        let step = remaining.min(Duration::from_secs(4));
+        tokio::time::sleep(step).await;
+        remaining -= step;
+    }
+}
+
+// **🤖 Telegramでチャット**
+pub async fn run(config: &Config, model: LayeredModel) -> io::Result<()> {
+    let token = env::var(&config.telegram.token_env).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("set {} to the bot token", config.telegram.token_env),
+        )
+    })?;
+    let relay = Arc::new(Relay {
+        config: config.clone(),
+        model,
+        chats: Mutex::new(HashMap::new()),
+        recent: Mutex::new(HashMap::new()),
+        guards: Mutex::new(HashMap::new()),
+        unwritable: Mutex::new(HashSet::new()),
+        triggers: Triggers::new(&config.telegram.trigger)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+    });
+
+    let bot = Bot::new(token);
+    let me = bot.get_me().await.map_err(io::Error::other)?;
+    println!("🤖 connected to Telegram as {}", me.mention());
+    teloxide::repl(bot, move |bot: Bot, msg: Message| {
+        let relay = relay.clone();
+        let me = me.clone();
+        async move {
+            if let Some(text) = msg.text() {
+                let user = msg.from.as_ref().map(|user| user.id.to_string());
+                let incoming = Incoming {
+                    text,
+                    mentioned: text.to_lowercase().contains(&me.mention().to_lowercase()),
+                    reply_to_bot: msg
+                        .reply_to_message()
+                        .and_then(|replied| replied.from.as_ref())
+                        .is_some_and(|author| author.id == me.id),
+                };
+                let reply =
+                    relay.answer(msg.chat.id, user.as_deref().unwrap_or_default(), &incoming);
+                if !reply.is_empty() {
+                    // **📌 コマンド以外の返信は入力中の表示を出してから送る**
+                    let delay = (!text.starts_with('/'))
+                        .then(|| relay.config.typing.delay_for(&reply))
+                        .flatten();
+                    if let Some(delay) = delay {
+                        show_typing(&bot, msg.chat.id, delay).await;
+                    }
+                    bot.send_message(msg.chat.id, reply).await?;
+                }
+            }
+            Ok(())
+        }
+    })
+    .await;
+    Ok(())
+}
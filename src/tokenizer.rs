@@ -0,0 +1,494 @@
+use crate::{
+    error::{self, WordoraError},
+    kana::to_hiragana,
+};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::RwLock};
+use unicode_normalization::UnicodeNormalization;
+
+/// Default token classes: kanji, hiragana, katakana, latin words (with inner
+/// apostrophes and hyphens) and numbers, plus standalone emoji (with modifiers
+/// and ZWJ sequences) and punctuation marks
+pub const DEFAULT_PATTERN: &str = concat!(
+    r"([一-龯々]+|[ぁ-ん]+|[ァ-ヴー]+",
+    r"|[a-zA-Zａ-ｚＡ-Ｚ]+(?:['’\-][a-zA-Zａ-ｚＡ-Ｚ]+)*|[0-9０-９]+",
+    r"|\p{Extended_Pictographic}(?:\p{Emoji_Modifier}|\u{FE0F}|\u{200D}\p{Extended_Pictographic})*",
+    r"|[。、！？!?…・「」『』（）()〜~,.:;])"
+);
+
+/// The default pattern, compiled on first use
+static DEFAULT_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(DEFAULT_PATTERN).unwrap());
+
+/// Custom patterns compiled so far, so each one is compiled only once
+static CUSTOM_REGEXES: Lazy<RwLock<HashMap<String, Regex>>> = Lazy::new(Default::default);
+
+// **📌 RegexTokenizer構造体：コンパイル済みのトークンの区切り方**
+#[derive(Debug, Clone)]
+pub struct RegexTokenizer {
+    regex: Regex,
+}
+
+impl Default for RegexTokenizer {
+    fn default() -> Self {
+        Self {
+            regex: DEFAULT_REGEX.clone(),
+        }
+    }
+}
+
+impl RegexTokenizer {
+    /// Token classes of the default pattern
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Custom token classes; every match is a token and the rest is dropped,
+    /// e.g. `DEFAULT_PATTERN` with `#\w+|` added in front to keep hashtags whole
+    pub fn with_pattern(pattern: &str) -> error::Result<Self> {
+        if pattern == DEFAULT_PATTERN {
+            return Ok(Self::default());
+        }
+        if let Some(regex) = CUSTOM_REGEXES.read().unwrap().get(pattern) {
+            return Ok(Self {
+                regex: regex.clone(),
+            });
+        }
+        let regex =
+            Regex::new(pattern).map_err(|e| WordoraError::Tokenizer(format!("pattern: {}", e)))?;
+        CUSTOM_REGEXES
+            .write()
+            .unwrap()
+            .insert(pattern.to_string(), regex.clone());
+        Ok(Self { regex })
+    }
+
+    pub fn pattern(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    /// Split `text` into the matches of the pattern
+    pub fn separate(&self, text: &str) -> Vec<String> {
+        self.regex
+            .find_iter(text)
+            .flat_map(|m| m.as_str().split_whitespace())
+            .map(|s| s.to_string())
+            .collect()
+    }
+}
+
+// **📌 トークナイザーの種類**
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenizerKind {
+    /// Regex token classes, then split into words as set by `chunking`
+    Regex,
+    /// One token per character
+    Char,
+}
+
+// **📌 日本語のトークンの区切り方**
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Chunking {
+    /// Cut every token into pieces of the chunk size
+    Fixed,
+    /// Keep kanji with their okurigana and split particles off kana; only words
+    /// longer than three times the chunk size are cut further
+    Boundary,
+}
+
+// **📌 TokenizerConfig構造体：分割の設定を管理**
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TokenizerConfig {
+    pub kind: TokenizerKind,
+    pub pattern: String,
+    pub chunking: Chunking,
+    /// Chunk size used when learning
+    pub learn_chunk_size: usize,
+    /// Chunk size used when picking a seed from the user's input
+    pub input_chunk_size: usize,
+    /// Apply NFKC first, so `Ｔｏｋｙｏ` becomes `Tokyo` and `ﾃﾞｰﾀ` becomes `データ`
+    pub nfkc: bool,
+    /// Fold katakana into hiragana, so `ネコ` and `ねこ` are the same token
+    pub fold_katakana: bool,
+    /// Split mostly-Latin lines into words instead of running them through the pattern
+    pub detect_script: bool,
+}
+
+impl Default for TokenizerConfig {
+    fn default() -> Self {
+        Self {
+            kind: TokenizerKind::Regex,
+            pattern: DEFAULT_PATTERN.to_string(),
+            chunking: Chunking::Boundary,
+            learn_chunk_size: 5,
+            input_chunk_size: 3,
+            nfkc: true,
+            fold_katakana: false,
+            detect_script: true,
+        }
+    }
+}
+
+impl TokenizerConfig {
+    /// Default config splitting by a custom pattern, see [`RegexTokenizer::with_pattern`]
+    pub fn with_pattern(pattern: &str) -> error::Result<Self> {
+        RegexTokenizer::with_pattern(pattern)?;
+        Ok(Self {
+            pattern: pattern.to_string(),
+            ..Self::default()
+        })
+    }
+
+    /// Check the config can be used to split text
+    pub fn validate(&self) -> error::Result<()> {
+        RegexTokenizer::with_pattern(&self.pattern)?;
+        if self.learn_chunk_size == 0 || self.input_chunk_size == 0 {
+            return Err(WordoraError::Tokenizer(
+                "chunk sizes must be at least 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Normalize text before it is split, as configured
+    pub fn normalize(&self, text: &str) -> String {
+        // **📌 「…」は「...」に分解されないようにそのまま残す**
+        let text: String = if self.nfkc {
+            text.split('…')
+                .map(|part| part.nfkc().collect::<String>())
+                .collect::<Vec<_>>()
+                .join("…")
+        } else {
+            text.to_string()
+        };
+        if self.fold_katakana {
+            to_hiragana(&text)
+        } else {
+            text
+        }
+    }
+
+    /// Split training text into tokens
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        let text = &self.normalize(text);
+        match self.kind {
+            TokenizerKind::Regex => {
+                let mut separated: Vec<String> = vec![];
+                for segment in text.lines() {
+                    if self.is_latin(segment) {
+                        separated.extend(self.latin_words(segment));
+                        continue;
+                    }
+
+                    // **📌 日本語を「漢字」「ひらがな」「カタカナ」「記号」単位で分割**
+                    for word in segment.split_whitespace() {
+                        let tokens = separate_tokens(word, &self.pattern);
+                        separated.extend(self.chunk(tokens, self.learn_chunk_size));
+                    }
+                }
+                separated
+            }
+            TokenizerKind::Char => split_chars(text),
+        }
+    }
+
+    /// Split the user's input into candidate seed tokens
+    pub fn tokenize_input(&self, text: &str) -> Vec<String> {
+        let text = &self.normalize(text);
+        match self.kind {
+            TokenizerKind::Regex => {
+                let mut chunks = vec![];
+                for segment in text.lines() {
+                    if self.is_latin(segment) {
+                        chunks.extend(self.latin_words(segment));
+                        continue;
+                    }
+
+                    if self.chunking == Chunking::Boundary {
+                        let tokens = separate_tokens(segment, &self.pattern);
+                        // **📌 学習時と同じ単語になるよう学習時のサイズを使う**
+                        chunks.extend(self.chunk(tokens, self.learn_chunk_size));
+                        continue;
+                    }
+
+                    // **📌 日本語はまとめて区切り直し、英単語などはそのまま使う**
+                    let mut japanese = String::new();
+                    for token in separate_tokens(segment, &self.pattern) {
+                        if is_whole_token(&token) {
+                            chunks.extend(chunk_string(&japanese, self.input_chunk_size));
+                            japanese.clear();
+                            chunks.push(token);
+                        } else {
+                            japanese.push_str(&token);
+                        }
+                    }
+                    chunks.extend(chunk_string(&japanese, self.input_chunk_size));
+                }
+                chunks
+            }
+            TokenizerKind::Char => split_chars(text),
+        }
+    }
+
+    /// Split the pattern's tokens further, keeping Latin words, numbers and emoji whole
+    fn chunk(&self, tokens: Vec<String>, chunk_size: usize) -> Vec<String> {
+        match self.chunking {
+            Chunking::Fixed => tokens
+                .into_iter()
+                .flat_map(|token| {
+                    // **📌 英単語・数字・絵文字は途中で切らない**
+                    if is_whole_token(&token) {
+                        vec![token]
+                    } else {
+                        chunk_string(&token, chunk_size)
+                    }
+                })
+                .collect(),
+            Chunking::Boundary => split_words(tokens)
+                .into_iter()
+                .flat_map(|word| {
+                    if is_whole_token(&word) || word.chars().count() <= chunk_size * 3 {
+                        vec![word]
+                    } else {
+                        split_long(&word, chunk_size)
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether a line should be split into words rather than by the pattern
+    fn is_latin(&self, segment: &str) -> bool {
+        self.detect_script && detect_script(segment) == Script::Latin
+    }
+
+    /// Whitespace-separated words, with the punctuation and emoji around them
+    /// split off through the pattern
+    fn latin_words(&self, segment: &str) -> Vec<String> {
+        let mut words = vec![];
+        for word in segment.split_whitespace() {
+            let Some(start) = word.find(char::is_alphanumeric) else {
+                words.extend(separate_tokens(word, &self.pattern));
+                continue;
+            };
+            let end = word
+                .char_indices()
+                .rev()
+                .find(|(_, c)| c.is_alphanumeric())
+                .map(|(i, c)| i + c.len_utf8())
+                .unwrap_or(word.len());
+            words.extend(separate_tokens(&word[..start], &self.pattern));
+            words.push(word[start..end].to_string());
+            words.extend(separate_tokens(&word[end..], &self.pattern));
+        }
+        words
+    }
+}
+
+// **📌 文の文字体系**
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Script {
+    Japanese,
+    Latin,
+}
+
+/// Script `text` is written in: any kana makes it Japanese, otherwise Latin if
+/// Latin letters outnumber kanji
+pub fn detect_script(text: &str) -> Script {
+    let (mut kanji, mut latin) = (0, 0);
+    for c in text.chars() {
+        match TokenType::of_char(c) {
+            TokenType::Hiragana | TokenType::Katakana => return Script::Japanese,
+            TokenType::Kanji => kanji += 1,
+            TokenType::Latin => latin += 1,
+            _ => {}
+        }
+    }
+    if latin > kanji {
+        Script::Latin
+    } else {
+        Script::Japanese
+    }
+}
+
+pub fn chunk_string(input: &str, chunk_size: usize) -> Vec<String> {
+    input
+        .chars() // 文字単位で処理
+        .collect::<Vec<char>>() // Vec<char>に変換
+        .chunks(chunk_size.max(1)) // chunk_sizeごとに区切る
+        .map(|chunk| chunk.iter().collect()) // チャンクを文字列に変換
+        .collect()
+}
+
+/// Separate tokens, compiling `pattern` only the first time it is used; a
+/// pattern that does not compile, which [`TokenizerConfig::validate`] rules
+/// out, splits at whitespace only
+pub fn separate_tokens(text: &str, pattern: &str) -> Vec<String> {
+    match RegexTokenizer::with_pattern(pattern) {
+        Ok(tokenizer) => tokenizer.separate(text),
+        Err(_) => text.split_whitespace().map(|s| s.to_string()).collect(),
+    }
+}
+
+/// Particles a word boundary is placed after
+const PARTICLES: &[char] = &['は', 'が', 'を', 'に', 'で', 'と', 'も', 'へ', 'の', 'や'];
+
+/// Whether `c` can be a particle after `prev`; the と of こと and the の of もの are not
+fn is_particle(prev: Option<char>, c: char) -> bool {
+    PARTICLES.contains(&c) && !matches!((prev, c), (Some('こ'), 'と') | (Some('も'), 'の'))
+}
+
+/// Join kanji with the kana that follows them up to a particle, and split
+/// particles off the start of the remaining kana runs
+fn split_words(tokens: Vec<String>) -> Vec<String> {
+    let mut words = vec![];
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        let kind = TokenType::of(&token);
+        let kana = match kind {
+            TokenType::Kanji => {
+                match tokens.next_if(|next| TokenType::of(next) == TokenType::Hiragana) {
+                    // **📌 送り仮名は漢字と一緒にする（美味しかった）**
+                    Some(kana) => {
+                        let end = kana
+                            .char_indices()
+                            .zip(std::iter::once(None).chain(kana.chars().map(Some)))
+                            .find(|((_, c), prev)| is_particle(*prev, *c))
+                            .map_or(kana.len(), |((i, _), _)| i);
+                        words.push(token + &kana[..end]);
+                        kana[end..].to_string()
+                    }
+                    None => {
+                        words.push(token);
+                        continue;
+                    }
+                }
+            }
+            TokenType::Hiragana => token,
+            _ => {
+                words.push(token);
+                continue;
+            }
+        };
+        words.extend(split_kana(&kana));
+    }
+    words
+}
+
+/// Split a leading particle off a hiragana run (but not the で of です, でした,
+/// では or でも)
+fn split_kana(kana: &str) -> Vec<String> {
+    let mut chars = kana.chars();
+    match (chars.next(), chars.clone().next()) {
+        (Some('で'), Some('す' | 'し' | 'は' | 'も')) => vec![kana.to_string()],
+        (Some(first), Some(_)) if PARTICLES.contains(&first) => {
+            vec![first.to_string(), chars.collect()]
+        }
+        _ if kana.is_empty() => vec![],
+        _ => vec![kana.to_string()],
+    }
+}
+
+/// Cut a word that is too long after its inner particles, then into chunks
+fn split_long(word: &str, chunk_size: usize) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let mut pieces = vec![];
+    let mut start = 0;
+    for i in 1..chars.len().saturating_sub(2) {
+        if i > start && is_particle(Some(chars[i - 1]), chars[i]) {
+            pieces.push(chars[start..=i].iter().collect::<String>());
+            start = i + 1;
+        }
+    }
+    pieces.push(chars[start..].iter().collect());
+    pieces
+        .into_iter()
+        .flat_map(|piece| {
+            if piece.chars().count() > chunk_size * 3 {
+                chunk_string(&piece, chunk_size)
+            } else {
+                vec![piece]
+            }
+        })
+        .collect()
+}
+
+/// Latin words, numbers and emoji are never cut into chunks
+fn is_whole_token(token: &str) -> bool {
+    token.chars().next().is_some_and(|c| {
+        matches!(
+            TokenType::of_char(c),
+            TokenType::Latin | TokenType::Number | TokenType::Emoji
+        )
+    })
+}
+
+/// Split into single characters, skipping whitespace
+fn split_chars(text: &str) -> Vec<String> {
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_string())
+        .collect()
+}
+
+// **📌 トークンの文字種**
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenType {
+    Kanji,
+    Hiragana,
+    Katakana,
+    Latin,
+    Number,
+    Punctuation,
+    Emoji,
+    /// Several classes, e.g. kanji with okurigana
+    Mixed,
+    #[default]
+    Other,
+}
+
+impl TokenType {
+    /// Classify a token by its characters; tokens mixing classes are `Mixed`
+    pub fn of(token: &str) -> Self {
+        // **📌 don't や 1,000 のような語中の記号は単語の一部として扱う**
+        let word = token.starts_with(|c| matches!(Self::of_char(c), Self::Latin | Self::Number));
+        let mut kinds = token
+            .chars()
+            .filter(|c| !(word && matches!(c, '\'' | '’' | '-' | '.' | ',')))
+            .map(Self::of_char);
+        let Some(first) = kinds.next() else {
+            return Self::Other;
+        };
+        if kinds.all(|kind| kind == first) {
+            first
+        } else {
+            Self::Mixed
+        }
+    }
+
+    pub fn is_punctuation(self) -> bool {
+        self == Self::Punctuation
+    }
+
+    fn of_char(c: char) -> Self {
+        match c {
+            '一'..='龯' | '々' | '〆' => Self::Kanji,
+            'ぁ'..='ゖ' | 'ゝ' | 'ゞ' => Self::Hiragana,
+            'ァ'..='ヺ' | 'ー' | 'ｦ'..='ﾟ' => Self::Katakana,
+            'a'..='z' | 'A'..='Z' | 'À'..='ɏ' | 'ａ'..='ｚ' | 'Ａ'..='Ｚ' => Self::Latin,
+            '0'..='9' | '０'..='９' => Self::Number,
+            '\u{1F000}'..='\u{1FAFF}' | '\u{2600}'..='\u{27BF}' | '\u{FE0F}' | '\u{200D}' => {
+                Self::Emoji
+            }
+            c if c.is_ascii_punctuation() => Self::Punctuation,
+            '、' | '。' | '・' | '「' | '」' | '『' | '』' | '（' | '）' | '！' | '？' | '〜'
+            | '…' => Self::Punctuation,
+            _ => Self::Other,
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use serde::Deserialize;
+
+// **📌 TopicConfig構造体：話題を見分けて返事に反映する設定**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TopicConfig {
+    /// Guess the topic of every message and answer leaning towards it
+    pub detect: bool,
+    /// Share of each step taken from the topic's own transitions, 0 to 1
+    pub weight: f64,
+    /// Score a topic needs before a message counts as being about it
+    pub min_score: f64,
+}
+
+impl Default for TopicConfig {
+    fn default() -> Self {
+        Self {
+            detect: true,
+            weight: 0.6,
+            min_score: 1.0,
+        }
+    }
+}
+
+impl TopicConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.weight) {
+            return Err("weight must be between 0 and 1".to_string());
+        }
+        if !(self.min_score.is_finite() && self.min_score >= 0.0) {
+            return Err("min_score must not be negative".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A corpus line switching topics: `#movies` starts a section about movies
+/// and a lone `#` ends it. `None` for ordinary lines, `Some(None)` for `#`
+pub fn marker(line: &str) -> Option<Option<&str>> {
+    let name = line.trim().strip_prefix('#')?;
+    if name.is_empty() {
+        return Some(None);
+    }
+    name.chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        .then_some(Some(name))
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    io::{self, BufRead},
+};
+
+// **📌 TranscriptEntry構造体：会話ログの1往復**
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub input: String,
+    /// Reply tokens separated by spaces
+    pub response: String,
+    pub latency_ms: f64,
+    /// The reply had to start from the fallback word because no seed was known
+    pub fallback: bool,
+}
+
+// **📌 TranscriptReport構造体：会話ログの集計結果**
+#[derive(Debug, Clone, Default)]
+pub struct TranscriptReport {
+    pub turns: usize,
+    pub mean_latency_ms: f64,
+    pub max_latency_ms: f64,
+    /// Mean reply length in tokens
+    pub mean_reply_tokens: f64,
+    /// Share of turns that fell back
+    pub fallback_rate: f64,
+    /// Share of repeated tokens among the replies of each window of turns, oldest first
+    pub repetition: Vec<f64>,
+}
+
+/// Read a JSON Lines transcript; returns the entries and the number of unreadable lines
+pub fn read_transcript<R: BufRead>(reader: R) -> io::Result<(Vec<TranscriptEntry>, usize)> {
+    let mut entries = Vec::new();
+    let mut malformed = 0;
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(_) => malformed += 1,
+        }
+    }
+    Ok((entries, malformed))
+}
+
+/// Summarize a transcript, measuring repetition over windows of `window` turns
+pub fn analyze(entries: &[TranscriptEntry], window: usize) -> TranscriptReport {
+    if entries.is_empty() {
+        return TranscriptReport::default();
+    }
+    let turns = entries.len() as f64;
+    let lengths = entries
+        .iter()
+        .map(|entry| entry.response.split_whitespace().count());
+
+    TranscriptReport {
+        turns: entries.len(),
+        mean_latency_ms: entries.iter().map(|entry| entry.latency_ms).sum::<f64>() / turns,
+        max_latency_ms: entries
+            .iter()
+            .map(|entry| entry.latency_ms)
+            .fold(0.0, f64::max),
+        mean_reply_tokens: lengths.sum::<usize>() as f64 / turns,
+        fallback_rate: entries.iter().filter(|entry| entry.fallback).count() as f64 / turns,
+        repetition: entries.chunks(window.max(1)).map(repetition_rate).collect(),
+    }
+}
+
+/// 1 - distinct / total tokens over the replies
+fn repetition_rate(entries: &[TranscriptEntry]) -> f64 {
+    let tokens: Vec<&str> = entries
+        .iter()
+        .flat_map(|entry| entry.response.split_whitespace())
+        .collect();
+    if tokens.is_empty() {
+        return 0.0;
+    }
+    let distinct: HashSet<&str> = tokens.iter().copied().collect();
+    1.0 - distinct.len() as f64 / tokens.len() as f64
+}
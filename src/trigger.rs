@@ -0,0 +1,118 @@
+use rand::Rng;
+use regex::Regex;
+use serde::Deserialize;
+
+// **📌 TriggerConfig構造体：ボットが返事をする条件**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TriggerConfig {
+    /// Answer messages that mention the bot
+    pub mention: bool,
+    /// Answer replies to one of the bot's own messages
+    pub reply: bool,
+    /// Answer messages containing any of these names, ignoring case
+    pub names: Vec<String>,
+    /// Answer messages matching any of these regexes
+    pub patterns: Vec<String>,
+    /// Chance of answering any other message, from 0 (never) to 1 (always)
+    pub probability: f64,
+}
+
+impl Default for TriggerConfig {
+    fn default() -> Self {
+        Self {
+            mention: true,
+            reply: true,
+            names: Vec::new(),
+            patterns: Vec::new(),
+            probability: 0.0,
+        }
+    }
+}
+
+impl TriggerConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.probability) {
+            return Err("probability must be between 0 and 1".to_string());
+        }
+        if self.names.iter().any(|name| name.trim().is_empty()) {
+            return Err("names must not be empty".to_string());
+        }
+        for pattern in &self.patterns {
+            Regex::new(pattern).map_err(|e| format!("patterns: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+// **📌 Trigger：返事をすることにした理由**
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// The message mentions the bot
+    Mention,
+    /// The message replies to the bot
+    Reply,
+    /// The message contains one of the bot's names
+    Name,
+    /// The message matches a pattern
+    Pattern,
+    /// Chosen by chance
+    Random,
+}
+
+/// What the chat integration knows about an incoming message
+#[derive(Debug, Clone, Copy)]
+pub struct Incoming<'a> {
+    pub text: &'a str,
+    /// The message mentions the bot
+    pub mentioned: bool,
+    /// The message replies to one of the bot's messages
+    pub reply_to_bot: bool,
+}
+
+// **📌 Triggers構造体：設定した条件をまとめて判定**
+#[derive(Debug, Clone)]
+pub struct Triggers {
+    config: TriggerConfig,
+    names: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl Triggers {
+    pub fn new(config: &TriggerConfig) -> Result<Self, regex::Error> {
+        Ok(Self {
+            config: config.clone(),
+            names: config
+                .names
+                .iter()
+                .map(|name| name.to_lowercase())
+                .collect(),
+            patterns: config
+                .patterns
+                .iter()
+                .map(|pattern| Regex::new(pattern))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// The first rule the message satisfies, or `None` when the bot should stay quiet
+    pub fn check(&self, message: &Incoming) -> Option<Trigger> {
+        if self.config.mention && message.mentioned {
+            return Some(Trigger::Mention);
+        }
+        if self.config.reply && message.reply_to_bot {
+            return Some(Trigger::Reply);
+        }
+        let text = message.text.to_lowercase();
+        if self.names.iter().any(|name| text.contains(name.as_str())) {
+            return Some(Trigger::Name);
+        }
+        if self.patterns.iter().any(|re| re.is_match(message.text)) {
+            return Some(Trigger::Pattern);
+        }
+        if self.config.probability > 0.0 && rand::rng().random_bool(self.config.probability) {
+            return Some(Trigger::Random);
+        }
+        None
+    }
+}
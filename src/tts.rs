@@ -0,0 +1,134 @@
+use crate::{markov::is_terminator, postprocess::repair_spacing};
+use serde::Deserialize;
+use std::{
+    io,
+    process::{Command, Stdio},
+};
+
+/// Typical Japanese speaking rate
+pub const DEFAULT_MORAE_PER_SECOND: f64 = 7.0;
+
+// **📌 読み上げの設定**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TtsConfig {
+    pub enabled: bool,
+    /// Program that speaks the text passed as its last argument, e.g. `say` or `espeak-ng`
+    pub command: String,
+    pub args: Vec<String>,
+    /// Spoken replies are cut to fit within this many seconds
+    pub max_seconds: f64,
+    pub morae_per_second: f64,
+}
+
+impl Default for TtsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            command: "say".to_string(),
+            args: vec![],
+            max_seconds: 8.0,
+            morae_per_second: DEFAULT_MORAE_PER_SECOND,
+        }
+    }
+}
+
+impl TtsConfig {
+    /// Truncate the reply to the time budget and speak it
+    pub fn speak(&self, tokens: &[&str]) -> io::Result<()> {
+        let text = truncate_for_speech(tokens, self.max_seconds, self.morae_per_second);
+        if text.is_empty() {
+            return Ok(());
+        }
+        Command::new(&self.command)
+            .args(&self.args)
+            .arg(text)
+            .stdout(Stdio::null())
+            .status()?;
+        Ok(())
+    }
+}
+
+/// Rough number of morae needed to read `text` aloud
+pub fn estimate_morae(text: &str) -> f64 {
+    let mut morae = 0.0;
+    for word in text.split_whitespace() {
+        let mut latin = String::new();
+        for c in word.chars() {
+            if c.is_ascii_alphabetic() {
+                latin.push(c);
+                continue;
+            }
+            morae += latin_morae(&latin);
+            latin.clear();
+            morae += char_morae(c);
+        }
+        morae += latin_morae(&latin);
+    }
+    morae
+}
+
+/// Estimated speaking time in seconds
+pub fn estimate_seconds(text: &str, morae_per_second: f64) -> f64 {
+    estimate_morae(text) / morae_per_second
+}
+
+/// Keep as many whole tokens as fit in `max_seconds`, preferring to stop after a terminator
+pub fn truncate_for_speech(tokens: &[&str], max_seconds: f64, morae_per_second: f64) -> String {
+    let budget = max_seconds * morae_per_second;
+    let mut used = 0.0;
+    let mut end = 0;
+    let mut last_sentence_end = None;
+
+    for (i, token) in tokens.iter().enumerate() {
+        used += estimate_morae(token);
+        if used > budget {
+            break;
+        }
+        end = i + 1;
+        if is_terminator(token) {
+            last_sentence_end = Some(end);
+        }
+    }
+
+    // **📌 途中で切れる場合は最後の文末で切る**
+    if end < tokens.len() {
+        if let Some(sentence_end) = last_sentence_end {
+            end = sentence_end;
+        }
+    }
+    repair_spacing(&tokens[..end])
+}
+
+fn char_morae(c: char) -> f64 {
+    match c {
+        // **📌 拗音の小さい仮名は前の仮名とまとめて1拍**
+        'ぁ' | 'ぃ' | 'ぅ' | 'ぇ' | 'ぉ' | 'ゃ' | 'ゅ' | 'ょ' | 'ゎ' | 'ァ' | 'ィ' | 'ゥ'
+        | 'ェ' | 'ォ' | 'ャ' | 'ュ' | 'ョ' | 'ヮ' => 0.0,
+        'ぁ'..='ゖ' | 'ァ'..='ヺ' | 'ー' => 1.0,
+        // **📌 漢字は平均2拍として数える**
+        '一'..='龯' | '々' => 2.0,
+        '0'..='9' => 2.0,
+        // **📌 句読点は間として数える**
+        '、' | ',' => 1.0,
+        '。' | '！' | '？' | '!' | '?' | '.' => 2.0,
+        _ => 0.0,
+    }
+}
+
+/// English-ish words: about one and a half morae per vowel group
+fn latin_morae(word: &str) -> f64 {
+    if word.is_empty() {
+        return 0.0;
+    }
+    let mut groups = 0;
+    let mut in_vowel = false;
+    for c in word.chars() {
+        let vowel = matches!(c.to_ascii_lowercase(), 'a' | 'e' | 'i' | 'o' | 'u' | 'y');
+        if vowel && !in_vowel {
+            groups += 1;
+        }
+        in_vowel = vowel;
+    }
+    groups.max(1) as f64 * 1.5
+}
@@ -0,0 +1,209 @@
+use crate::chat::Chat;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Wrap},
+    DefaultTerminal, Frame,
+};
+use std::io;
+use wordora_ai::postprocess::repair_spacing;
+
+/// Number of sampled transitions listed in the side panel
+const SHOWN_TRANSITIONS: usize = 12;
+
+// **📌 App構造体：全画面チャットの表示状態**
+struct App {
+    /// Conversation scrollback, oldest first
+    lines: Vec<Line<'static>>,
+    input: String,
+    /// Lines scrolled up from the bottom of the scrollback
+    scroll: usize,
+    /// Transitions taken by the last reply, with their probabilities
+    transitions: Vec<(String, String, f64)>,
+}
+
+// **🖥️ 全画面でチャット**
+pub fn run(chat: &mut Chat) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, chat);
+    ratatui::restore();
+    result
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, chat: &mut Chat) -> io::Result<()> {
+    let mut app = App {
+        lines: vec![Line::from(chat.config().prompt.banner.clone())],
+        input: String::new(),
+        scroll: 0,
+        transitions: Vec::new(),
+    };
+
+    loop {
+        terminal.draw(|frame| draw(frame, chat, &app))?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Esc => break,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+            KeyCode::Char(c) => app.input.push(c),
+            KeyCode::Backspace => {
+                app.input.pop();
+            }
+            KeyCode::Up => app.scroll += 1,
+            KeyCode::Down => app.scroll = app.scroll.saturating_sub(1),
+            KeyCode::PageUp => app.scroll += 10,
+            KeyCode::PageDown => app.scroll = app.scroll.saturating_sub(10),
+            KeyCode::Enter => {
+                let input = std::mem::take(&mut app.input);
+                let input = input.trim();
+                if input == chat.config().prompt.exit {
+                    break;
+                }
+                if !input.is_empty() {
+                    submit(&mut app, chat, input);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Send one line to the bot and record the exchange
+fn submit(app: &mut App, chat: &mut Chat, input: &str) {
+    app.scroll = 0;
+    let user = chat.config().prompt.user.clone();
+    app.lines.push(Line::from(vec![
+        Span::styled(user, Style::new().fg(Color::Cyan)),
+        Span::raw(input.to_string()),
+    ]));
+
+    app.lines.extend(
+        chat.reload()
+            .into_iter()
+            .map(|line| Line::styled(line, Style::new().fg(Color::Yellow))),
+    );
+    if let Some(lines) = chat.command(input) {
+        app.lines.extend(
+            lines
+                .into_iter()
+                .map(|line| Line::styled(line, Style::new().fg(Color::Yellow))),
+        );
+        return;
+    }
+
+    let response = chat.message(input, |_| {});
+    let tokens: Vec<&str> = response.split_whitespace().collect();
+
+    // **📌 返答でたどった遷移とその確率を記録**
+    let model = chat.model();
+    let base = model.base();
+    app.transitions = tokens
+        .windows(2)
+        .map(|pair| {
+            let p = base.word(pair[0]).map_or(0.0, |w| w.probability(pair[1]));
+            (pair[0].to_string(), pair[1].to_string(), p)
+        })
+        .collect();
+    drop(model);
+
+    let bot = chat.config().prompt.bot.clone();
+    app.lines.push(Line::from(vec![
+        Span::styled(bot, Style::new().fg(Color::Green)),
+        Span::raw(repair_spacing(&tokens)),
+    ]));
+    if let Some(note) = chat.unknown_note() {
+        app.lines
+            .push(Line::styled(note, Style::new().fg(Color::Magenta)));
+    }
+    if chat.debugging() {
+        app.lines.extend(
+            chat.explain()
+                .into_iter()
+                .map(|line| Line::styled(line, Style::new().fg(Color::DarkGray))),
+        );
+    }
+    chat.speak(&tokens);
+}
+
+fn draw(frame: &mut Frame, chat: &Chat, app: &App) {
+    let [main, input] =
+        Layout::vertical([Constraint::Min(3), Constraint::Length(3)]).areas(frame.area());
+    let [log, side] =
+        Layout::horizontal([Constraint::Percentage(70), Constraint::Percentage(30)]).areas(main);
+
+    // **📌 会話のスクロールバック（折り返しを考えて下端に合わせる）**
+    let height = log.height.saturating_sub(2) as usize;
+    let total = wrapped_height(&app.lines, log);
+    let scroll = total.saturating_sub(height).saturating_sub(app.scroll);
+    frame.render_widget(
+        Paragraph::new(app.lines.clone())
+            .block(Block::bordered().title(" chat "))
+            .wrap(Wrap { trim: false })
+            .scroll((scroll as u16, 0)),
+        log,
+    );
+
+    frame.render_widget(side_panel(chat, app), side);
+
+    frame.render_widget(
+        Paragraph::new(app.input.as_str()).block(Block::bordered().title(" input (Esc to quit) ")),
+        input,
+    );
+    let cursor = Line::from(app.input.as_str()).width() as u16;
+    frame.set_cursor_position((input.x + 1 + cursor, input.y + 1));
+}
+
+/// Seed word, sampled transitions and model stats
+fn side_panel<'a>(chat: &'a Chat, app: &'a App) -> Paragraph<'a> {
+    let model = chat.model();
+    let base = model.base();
+    let label = Style::new().fg(Color::DarkGray);
+
+    let mut lines = vec![
+        Line::styled("seed", label),
+        Line::from(chat.seed()),
+        Line::default(),
+        Line::styled("transitions", label),
+    ];
+    for (from, to, p) in app.transitions.iter().take(SHOWN_TRANSITIONS) {
+        lines.push(Line::from(format!("{} → {} {:.0}%", from, to, p * 100.0)));
+    }
+    if app.transitions.len() > SHOWN_TRANSITIONS {
+        lines.push(Line::from("…"));
+    }
+    lines.extend([
+        Line::default(),
+        Line::styled("model", label),
+        Line::from(format!("vocabulary {}", base.words().count())),
+        Line::from(format!("order {}", base.order())),
+        Line::from(format!("layers {}", model.overlays().len() + 1)),
+    ]);
+    for layer in model.overlays() {
+        lines.push(Line::from(format!(
+            "  {} ({} words)",
+            layer.name,
+            layer.chain.words().count()
+        )));
+    }
+
+    Paragraph::new(lines)
+        .block(Block::bordered().title(" model "))
+        .wrap(Wrap { trim: true })
+}
+
+/// Rows the lines take up inside a bordered `area` once wrapped
+fn wrapped_height(lines: &[Line], area: Rect) -> usize {
+    let width = area.width.saturating_sub(2).max(1) as usize;
+    lines
+        .iter()
+        .map(|line| line.width().div_ceil(width).max(1))
+        .sum()
+}
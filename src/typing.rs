@@ -0,0 +1,60 @@
+use rand::Rng;
+use serde::Deserialize;
+use std::time::Duration;
+
+// **📌 TypingConfig構造体：返信するまでわざと待つ時間**
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TypingConfig {
+    /// Typing speed the delay is worked out from; unset replies at once
+    pub chars_per_second: Option<f64>,
+    /// Random share the delay may be shorter or longer by, e.g. 0.3 for ±30%
+    pub jitter: f64,
+    pub min_seconds: f64,
+    pub max_seconds: f64,
+}
+
+impl Default for TypingConfig {
+    fn default() -> Self {
+        Self {
+            chars_per_second: None,
+            jitter: 0.3,
+            min_seconds: 0.5,
+            max_seconds: 8.0,
+        }
+    }
+}
+
+impl TypingConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self
+            .chars_per_second
+            .is_some_and(|speed| !(speed.is_finite() && speed > 0.0))
+        {
+            return Err("chars_per_second must be positive".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.jitter) {
+            return Err("jitter must be between 0 and 1".to_string());
+        }
+        if !(self.min_seconds >= 0.0 && self.min_seconds <= self.max_seconds) {
+            return Err("min_seconds must be between 0 and max_seconds".to_string());
+        }
+        if self.max_seconds > 600.0 {
+            return Err("max_seconds must be at most 600".to_string());
+        }
+        Ok(())
+    }
+
+    /// How long to appear to be typing `reply` before sending it, if at all
+    pub fn delay_for(&self, reply: &str) -> Option<Duration> {
+        let speed = self.chars_per_second?;
+        let jitter = match self.jitter {
+            0.0 => 1.0,
+            jitter => rand::rng().random_range(1.0 - jitter..=1.0 + jitter),
+        };
+        let seconds = reply.chars().count() as f64 / speed * jitter;
+        Some(Duration::from_secs_f64(
+            seconds.clamp(self.min_seconds, self.max_seconds),
+        ))
+    }
+}
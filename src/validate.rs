@@ -0,0 +1,149 @@
+use crate::error::WordoraError;
+use std::fmt;
+
+/// Issues spelled out in a report's message; the rest are only counted
+const SHOWN_ISSUES: usize = 5;
+
+// **📌 Table：遷移を持つ表の種類**
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Table {
+    /// Single tokens
+    Words,
+    /// Contexts of 2..=order tokens
+    Contexts,
+    /// Which tokens precede each token
+    Reverse,
+    /// Response pairs
+    Responses,
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Table::Words => "words",
+            Table::Contexts => "contexts",
+            Table::Reverse => "reverse index",
+            Table::Responses => "responses",
+        })
+    }
+}
+
+// **📌 Issue：モデルの壊れている箇所**
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    /// The order is 0
+    ZeroOrder,
+    /// An entry's token differs from the key it is stored under
+    MisnamedEntry {
+        table: Table,
+        key: String,
+        word: String,
+    },
+    /// A transition count is zero, negative or not a number
+    BadCount {
+        table: Table,
+        key: String,
+        next: String,
+        count: f64,
+    },
+    /// A key or a transition mentions a token missing from the vocabulary
+    UnknownToken {
+        table: Table,
+        key: String,
+        token: String,
+    },
+    /// A context does not have between 2 and `order` tokens
+    ContextLength { key: String, tokens: usize },
+    /// The reverse index does not count `from → to` as often as the words do;
+    /// a missing transition counts 0
+    ReverseMismatch {
+        from: String,
+        to: String,
+        forward: f64,
+        reverse: f64,
+    },
+    /// The cached distribution of an entry does not match its counts
+    StaleCache { table: Table, key: String },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::ZeroOrder => f.write_str("order is 0"),
+            Issue::MisnamedEntry { table, key, word } => {
+                write!(f, "{} entry {:?} is stored as {:?}", table, word, key)
+            }
+            Issue::BadCount {
+                table,
+                key,
+                next,
+                count,
+            } => write!(f, "{} {:?} -> {:?} has count {}", table, key, next, count),
+            Issue::UnknownToken { table, key, token } => {
+                write!(
+                    f,
+                    "{} entry {:?} mentions unknown token {:?}",
+                    table, key, token
+                )
+            }
+            Issue::ContextLength { key, tokens } => {
+                write!(f, "context {:?} has {} tokens", key, tokens)
+            }
+            Issue::ReverseMismatch {
+                from,
+                to,
+                forward,
+                reverse,
+            } => write!(
+                f,
+                "{:?} -> {:?} is counted {} times but {} in the reverse index",
+                from, to, forward, reverse
+            ),
+            Issue::StaleCache { table, key } => {
+                write!(f, "{} entry {:?} has a stale distribution", table, key)
+            }
+        }
+    }
+}
+
+// **📌 ValidationReport構造体：モデルの検査結果**
+/// What [`MarkovChain::validate`](crate::markov::MarkovChain::validate) found
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    /// Whether every invariant holds
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// The report as an error naming the first issues, for a model file that
+    /// cannot be used
+    pub fn into_result(self) -> crate::error::Result<()> {
+        if self.is_ok() {
+            return Ok(());
+        }
+        Err(WordoraError::InvalidModelFile(self.to_string()))
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_ok() {
+            return f.write_str("no issues");
+        }
+        let shown: Vec<String> = self
+            .issues
+            .iter()
+            .take(SHOWN_ISSUES)
+            .map(Issue::to_string)
+            .collect();
+        write!(f, "{}", shown.join("; "))?;
+        if self.issues.len() > SHOWN_ISSUES {
+            write!(f, " (and {} more)", self.issues.len() - SHOWN_ISSUES)?;
+        }
+        Ok(())
+    }
+}
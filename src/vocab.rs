@@ -0,0 +1,60 @@
+use crate::{
+    markov::{GenerateOptions, MarkovChain},
+    postprocess::repair_spacing,
+};
+use std::io::{self, Write};
+
+// **📌 VocabExport構造体：単語帳の書き出し設定**
+#[derive(Debug, Clone, Default)]
+pub struct VocabExport {
+    /// Skip words seen fewer times than this
+    pub min_count: usize,
+    /// Add a sentence generated from each word
+    pub examples: bool,
+}
+
+/// Write the vocabulary as a tab-separated file that Anki can import as a deck
+pub fn export_vocab<W: Write>(
+    chain: &MarkovChain,
+    opts: &GenerateOptions,
+    export: &VocabExport,
+    mut out: W,
+) -> io::Result<()> {
+    // **📌 記号だけのトークンは単語帳に入れない**
+    let mut words: Vec<(&str, f64)> = chain
+        .words()
+        .filter(|word| word.word.chars().any(char::is_alphabetic))
+        .map(|word| (word.word.as_str(), word.count()))
+        .filter(|(_, count)| *count >= export.min_count as f64)
+        .collect();
+    words.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+
+    // **📌 Anki のインポート用ヘッダー**
+    writeln!(out, "#separator:tab")?;
+    writeln!(out, "#html:false")?;
+    if export.examples {
+        writeln!(out, "#columns:word\tcount\texample")?;
+    } else {
+        writeln!(out, "#columns:word\tcount")?;
+    }
+
+    for (word, count) in words {
+        if export.examples {
+            let example = if chain.contains(word) {
+                let example = chain.generate(word, opts);
+                repair_spacing(&example.split_whitespace().collect::<Vec<_>>())
+            } else {
+                String::new()
+            };
+            writeln!(out, "{}\t{}\t{}", word, count, clean_field(&example))?;
+        } else {
+            writeln!(out, "{}\t{}", word, count)?;
+        }
+    }
+    Ok(())
+}
+
+/// Keep generated text from breaking the TSV layout
+fn clean_field(text: &str) -> String {
+    text.replace(['\t', '\n', '\r'], " ")
+}
@@ -0,0 +1,103 @@
+use crate::{
+    blocklist::{Blocklist, BlocklistConfig},
+    conversation::most_salient,
+    markov::{GenerateOptions, MarkovChain},
+    postprocess::repair_spacing,
+    tokenizer::TokenizerConfig,
+};
+use std::sync::Arc;
+use wasm_bindgen::prelude::*;
+
+// **📌 WasmMarkovChain構造体：JavaScriptから使うマルコフ連鎖**
+#[wasm_bindgen]
+pub struct WasmMarkovChain {
+    chain: MarkovChain,
+    opts: GenerateOptions,
+    /// Times a reply with a banned word is generated again, see `setBlocklist`
+    retries: usize,
+}
+
+impl WasmMarkovChain {
+    fn with_chain(chain: MarkovChain) -> Self {
+        Self {
+            chain,
+            opts: GenerateOptions::default(),
+            retries: BlocklistConfig::default().retries,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl WasmMarkovChain {
+    #[wasm_bindgen(constructor)]
+    pub fn new(order: usize) -> Self {
+        Self::with_chain(MarkovChain::new(order, TokenizerConfig::default()))
+    }
+
+    pub fn learn(&mut self, text: &str) {
+        self.chain.learn(text);
+    }
+
+    /// Generate from `start_word`, joined for display
+    pub fn generate(&self, start_word: &str) -> String {
+        let text = self.chain.generate(start_word, &self.opts);
+        repair_spacing(&text.split_whitespace().collect::<Vec<_>>())
+    }
+
+    /// Answer a message the way the server does: seeded from the learned
+    /// response pairs, else the most telling known word of the message, and
+    /// generated again while the reply has a banned word
+    pub fn reply(&self, message: &str) -> String {
+        let chain = &self.chain;
+        let tokens = chain.tokenizer().tokenize_input(message);
+        let resolved: Vec<String> = tokens
+            .iter()
+            .filter_map(|token| chain.resolve_seed(token))
+            .map(String::from)
+            .collect();
+        let seed = chain
+            .response_seed(&tokens)
+            .map(String::from)
+            .or_else(|| {
+                most_salient(&resolved, |t| chain.contains(t), |t| chain.salience(t)).cloned()
+            })
+            .or_else(|| tokens.first().cloned())
+            .unwrap_or_default();
+        let text = self
+            .opts
+            .blocklist
+            .regenerate(self.retries, || chain.generate(&seed, &self.opts));
+        repair_spacing(&text.split_whitespace().collect::<Vec<_>>())
+    }
+
+    /// Replace the generation options with a JSON object of `[generation]`
+    /// settings; the blocklist is kept
+    #[wasm_bindgen(js_name = setOptions)]
+    pub fn set_options(&mut self, json: &str) -> Result<(), JsError> {
+        let mut opts: GenerateOptions = serde_json::from_str(json)?;
+        opts.validate().map_err(|e| JsError::new(&e))?;
+        opts.blocklist = self.opts.blocklist.clone();
+        self.opts = opts;
+        Ok(())
+    }
+
+    /// Ban the words of a blocklist file's text, see `blocklist.path`
+    #[wasm_bindgen(js_name = setBlocklist)]
+    pub fn set_blocklist(&mut self, text: &str, retries: usize) -> Result<(), JsError> {
+        let blocklist = Blocklist::parse(text).map_err(|e| JsError::new(&e))?;
+        self.opts.blocklist = Arc::new(blocklist);
+        self.retries = retries;
+        Ok(())
+    }
+
+    /// Model in the same format as saved model files
+    #[wasm_bindgen(js_name = saveBytes)]
+    pub fn save_bytes(&self) -> Result<Vec<u8>, JsError> {
+        Ok(self.chain.to_bytes()?)
+    }
+
+    #[wasm_bindgen(js_name = loadBytes)]
+    pub fn load_bytes(bytes: &[u8]) -> Result<WasmMarkovChain, JsError> {
+        Ok(Self::with_chain(MarkovChain::from_bytes(bytes)?))
+    }
+}
@@ -0,0 +1,102 @@
+use notify_debouncer_mini::{
+    new_debouncer,
+    notify::{RecommendedWatcher, RecursiveMode},
+    DebounceEventResult, Debouncer,
+};
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+    time::{Duration, SystemTime},
+};
+
+/// Quiet time after a burst of file events before the watcher reports it, so
+/// a file written in several steps is read once
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+// **📌 FileWatcher構造体：OS の通知でファイルの変更に気づく**
+/// Files followed through the OS's change notifications. Their folders are
+/// watched rather than the files, so files replaced by a rename or created
+/// later are noticed too; modification times then tell which files changed
+#[derive(Default)]
+pub struct FileWatcher {
+    /// Watched files with the modification time last seen, `None` if missing
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    /// Stops watching when dropped
+    _debouncer: Option<Debouncer<RecommendedWatcher>>,
+}
+
+impl FileWatcher {
+    /// Watch `paths`, taking their current state as unchanged. `wake` gets a
+    /// message after each debounced burst of changes in their folders, when
+    /// [`changed`](Self::changed) is worth calling
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>, wake: Sender<()>) -> io::Result<Self> {
+        let files: Vec<(PathBuf, Option<SystemTime>)> = paths
+            .into_iter()
+            .map(|path| {
+                let modified = modified(&path);
+                (path, modified)
+            })
+            .collect();
+        if files.is_empty() {
+            return Ok(Self::default());
+        }
+
+        // **📌 エラーでも起こす（変更の判定は更新日時で行うので取りこぼさない）**
+        let mut debouncer = new_debouncer(DEBOUNCE, move |_: DebounceEventResult| {
+            let _ = wake.send(());
+        })
+        .map_err(io::Error::other)?;
+        let folders: HashSet<&Path> = files.iter().map(|(path, _)| folder(path)).collect();
+        for dir in folders {
+            debouncer
+                .watcher()
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(|e| io::Error::other(format!("{}: {}", dir.display(), e)))?;
+        }
+        Ok(Self {
+            files,
+            _debouncer: Some(debouncer),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Files modified, created or removed since the last call
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for (path, seen) in &mut self.files {
+            let now = modified(path);
+            if now != *seen {
+                *seen = now;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+
+    /// Take the current state of `path` as unchanged, e.g. after writing it
+    /// yourself
+    pub fn refresh(&mut self, path: &Path) {
+        for (watched, seen) in &mut self.files {
+            if watched == path {
+                *seen = modified(watched);
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Folder holding `path`, `.` for a bare file name
+fn folder(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
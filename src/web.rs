@@ -0,0 +1,114 @@
+use crate::{
+    blocklist::BlocklistConfig,
+    config::PromptConfig,
+    markov::{GenerateOptions, MarkovChain},
+};
+use serde::Serialize;
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const INDEX_HTML: &str = include_str!("web/index.html");
+
+/// Name wasm-bindgen gives the JS glue (`wordora.js`) and module
+/// (`wordora_bg.wasm`) it writes next to the page
+const WASM_NAME: &str = "wordora";
+
+/// Version of wasm-bindgen the `wasm` feature is built with; the CLI that
+/// writes the JS glue must match it
+const WASM_BINDGEN_VERSION: &str = "0.2.129";
+
+// **📌 Settings構造体：Webデモに書き出す設定**
+#[derive(Serialize)]
+struct Settings<'a> {
+    options: &'a GenerateOptions,
+    prompt: &'a PromptConfig,
+    /// Text of the blocklist file, empty without one
+    blocklist: String,
+    retries: usize,
+}
+
+/// Write a static chat page running the `wasm` build of the library, with
+/// the model and settings, into `dir`. The module is built with cargo and
+/// `wasm-bindgen` from this crate's sources, or taken from `wasm` when
+/// given (`target/wasm32-unknown-unknown/release/wordora_ai.wasm`)
+pub fn export(
+    chain: &MarkovChain,
+    options: &GenerateOptions,
+    prompt: &PromptConfig,
+    blocklist: &BlocklistConfig,
+    wasm: Option<&Path>,
+    dir: &Path,
+) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let wasm = match wasm {
+        Some(wasm) => wasm.to_path_buf(),
+        None => build_wasm()?,
+    };
+    run(Command::new("wasm-bindgen")
+        .args([
+            "--target",
+            "web",
+            "--no-typescript",
+            "--out-name",
+            WASM_NAME,
+        ])
+        .arg("--out-dir")
+        .arg(dir)
+        .arg(&wasm))
+    .map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!(
+                "wasm-bindgen: {} (install it with `cargo install wasm-bindgen-cli --version {}`)",
+                e, WASM_BINDGEN_VERSION
+            ),
+        )
+    })?;
+
+    let settings = Settings {
+        options,
+        prompt,
+        blocklist: match &blocklist.path {
+            Some(path) => fs::read_to_string(path)?,
+            None => String::new(),
+        },
+        retries: blocklist.retries,
+    };
+    fs::write(dir.join("index.html"), INDEX_HTML)?;
+    fs::write(dir.join("settings.json"), serde_json::to_string(&settings)?)?;
+    fs::write(dir.join("model.bin"), chain.to_bytes()?)?;
+    Ok(())
+}
+
+/// Build the library for the web with only the `wasm` feature and return
+/// the module's path
+fn build_wasm() -> io::Result<PathBuf> {
+    // **📌 .cargo/config.toml の設定が効くようにクレートのフォルダで実行**
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let cargo = env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    run(Command::new(cargo).current_dir(root).args([
+        "build",
+        "--lib",
+        "--release",
+        "--target",
+        "wasm32-unknown-unknown",
+        "--no-default-features",
+        "--features",
+        "wasm",
+    ]))
+    .map_err(|e| io::Error::new(e.kind(), format!("building the wasm module: {}", e)))?;
+    Ok(root.join("target/wasm32-unknown-unknown/release/wordora_ai.wasm"))
+}
+
+/// Run a command, failing when it cannot start or exits unsuccessfully
+fn run(command: &mut Command) -> io::Result<()> {
+    let status = command.status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("exited with {}", status)));
+    }
+    Ok(())
+}